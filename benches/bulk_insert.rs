@@ -0,0 +1,54 @@
+// Bulk insertion timings at growing sizes. With an O(1) capacity check the
+// cost per entry stays flat as the vault grows, i.e. total time is linear.
+//
+// Run with: cargo bench --bench bulk_insert
+use memoria::{MemorySize, Resource, Vault};
+use std::time::{Duration, Instant};
+
+fn entries(count: usize) -> Vec<(String, Resource)> {
+    (0..count)
+        .map(|i| {
+            (
+                format!("memory/{}", i),
+                Resource::TextMessage(format!("fact number {}", i)),
+            )
+        })
+        .collect()
+}
+
+fn time_add(count: usize) -> Duration {
+    let mut vault = Vault::<String>::new("Bench".to_string(), MemorySize::GB(1));
+    let items = entries(count);
+    let start = Instant::now();
+    for (key, resource) in items {
+        vault.add(key, resource).unwrap();
+    }
+    start.elapsed()
+}
+
+fn time_add_many(count: usize) -> Duration {
+    let mut vault = Vault::<String>::new("Bench".to_string(), MemorySize::GB(1));
+    let items = entries(count);
+    let start = Instant::now();
+    let results = vault.add_many(items);
+    let elapsed = start.elapsed();
+    assert!(results.iter().all(|r| r.is_ok()));
+    elapsed
+}
+
+fn report(label: &str, count: usize, elapsed: Duration) {
+    println!(
+        "{:<10} {:>7} entries  {:>9.2} ms  {:>7.1} ns/entry",
+        label,
+        count,
+        elapsed.as_secs_f64() * 1000.0,
+        elapsed.as_nanos() as f64 / count as f64
+    );
+}
+
+fn main() {
+    for count in [25_000, 50_000, 100_000] {
+        report("add", count, time_add(count));
+        report("add_many", count, time_add_many(count));
+    }
+}
@@ -0,0 +1,51 @@
+// Generates the gRPC service code for the `grpc` feature from the `rpc` lines
+// of proto/vault.proto. Only the service is read from the file, so building
+// needs no protoc; the message types live in src/grpc.rs, and a test checks
+// them against the file.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=proto/vault.proto");
+    #[cfg(feature = "grpc")]
+    grpc_service();
+}
+
+#[cfg(feature = "grpc")]
+fn grpc_service() {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    let proto = std::fs::read_to_string("proto/vault.proto").expect("proto/vault.proto");
+    let mut service = Service::builder().name("Vault").package("memoria.v1");
+    // rpc Name(Request) returns (stream Response);
+    for line in proto.lines().map(str::trim) {
+        let Some(rpc) = line.strip_prefix("rpc ") else {
+            continue;
+        };
+        let words: Vec<&str> = rpc
+            .split(|c: char| c.is_whitespace() || "();".contains(c))
+            .filter(|word| !word.is_empty())
+            .collect();
+        let (route, input, streaming, output) = match words[..] {
+            [route, input, "returns", "stream", output] => (route, input, true, output),
+            [route, input, "returns", output] => (route, input, false, output),
+            _ => panic!("unsupported rpc in proto/vault.proto: {}", line),
+        };
+        let mut name = String::new();
+        for c in route.chars() {
+            if c.is_ascii_uppercase() && !name.is_empty() {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+        }
+        let mut method = Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("super::{}", input))
+            .output_type(format!("super::{}", output))
+            .codec_path("tonic_prost::ProstCodec");
+        if streaming {
+            method = method.server_streaming();
+        }
+        service = service.method(method.build());
+    }
+    Builder::new().compile(&[service.build()]);
+}
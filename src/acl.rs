@@ -0,0 +1,363 @@
+use crate::backend::{MemoryBackend, StorageBackend};
+use crate::error::VaultError;
+use crate::resource::Resource;
+use crate::vault::{invalid_data, Vault};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Read,
+    Write,
+    Delete,
+    // Implies the others, and covers vault-wide operations such as saving
+    Admin,
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Permission::Read => "read",
+            Permission::Write => "write",
+            Permission::Delete => "delete",
+            Permission::Admin => "admin",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Permissions on every key starting with `prefix`; an empty prefix covers the
+// whole vault
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grant {
+    pub prefix: String,
+    pub permissions: Vec<Permission>,
+}
+
+impl Grant {
+    pub fn new(prefix: &str, permissions: &[Permission]) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            permissions: permissions.to_vec(),
+        }
+    }
+
+    fn allows(&self, permission: Permission, key: &str) -> bool {
+        key.starts_with(&self.prefix)
+            && self
+                .permissions
+                .iter()
+                .any(|p| *p == permission || *p == Permission::Admin)
+    }
+}
+
+// Someone acting on the vault, identified by an API key. Their permissions are
+// the union of their own grants and those of their roles.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Principal {
+    pub name: String,
+    pub api_key: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub grants: Vec<Grant>,
+}
+
+// Principals and named roles, usually loaded from a JSON file:
+//
+// { "roles": { "agent": [{ "prefix": "shared/", "permissions": ["read"] }] },
+//   "principals": [{ "name": "planner", "api_key": "...", "roles": ["agent"],
+//                    "grants": [{ "prefix": "planner/", "permissions": ["read", "write", "delete"] }] }] }
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccessPolicy {
+    #[serde(default)]
+    pub roles: HashMap<String, Vec<Grant>>,
+    #[serde(default)]
+    pub principals: Vec<Principal>,
+}
+
+impl AccessPolicy {
+    pub fn load(path: &str) -> Result<Self, VaultError> {
+        let json = std::fs::read_to_string(path)?;
+        let policy: Self = serde_json::from_str(&json).map_err(invalid_data)?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    // Names and API keys must be unique, and every role a principal names must exist
+    pub fn validate(&self) -> Result<(), VaultError> {
+        let mut names = HashSet::new();
+        let mut keys = HashSet::new();
+        for principal in &self.principals {
+            if !names.insert(&principal.name) {
+                return Err(VaultError::InvalidInput(format!(
+                    "Principal '{}' is defined twice",
+                    principal.name
+                )));
+            }
+            if principal.api_key.is_empty() || !keys.insert(&principal.api_key) {
+                return Err(VaultError::InvalidInput(format!(
+                    "Principal '{}' needs an API key of its own",
+                    principal.name
+                )));
+            }
+            if let Some(role) = principal
+                .roles
+                .iter()
+                .find(|role| !self.roles.contains_key(*role))
+            {
+                return Err(VaultError::InvalidInput(format!(
+                    "Principal '{}' has unknown role '{}'",
+                    principal.name, role
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn principal(&self, name: &str) -> Option<&Principal> {
+        self.principals.iter().find(|p| p.name == name)
+    }
+
+    // The principal holding `api_key`
+    pub fn authenticate(&self, api_key: &str) -> Option<&Principal> {
+        self.principals
+            .iter()
+            .find(|p| constant_time_eq(p.api_key.as_bytes(), api_key.as_bytes()))
+    }
+
+    pub fn allows(&self, principal: &Principal, permission: Permission, key: &str) -> bool {
+        let from_roles = principal
+            .roles
+            .iter()
+            .filter_map(|role| self.roles.get(role))
+            .flatten();
+        principal
+            .grants
+            .iter()
+            .chain(from_roles)
+            .any(|grant| grant.allows(permission, key))
+    }
+
+    pub fn check(
+        &self,
+        principal: &Principal,
+        permission: Permission,
+        key: &str,
+    ) -> Result<(), VaultError> {
+        if self.allows(principal, permission, key) {
+            Ok(())
+        } else {
+            Err(denied(&principal.name, permission, key))
+        }
+    }
+}
+
+fn denied(principal: &str, permission: Permission, key: &str) -> VaultError {
+    VaultError::PermissionDenied {
+        principal: principal.to_string(),
+        permission: permission.to_string(),
+        key: key.to_string(),
+    }
+}
+
+// Compares every byte so the time taken does not reveal how much of a guessed
+// key was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// What one server connection may do. Without a policy everything is allowed;
+// with one, nothing is until the client authenticates.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Access {
+    policy: Option<Arc<AccessPolicy>>,
+    principal: Option<Principal>,
+}
+
+impl Access {
+    pub(crate) fn new(policy: Option<Arc<AccessPolicy>>) -> Self {
+        Self {
+            policy,
+            principal: None,
+        }
+    }
+
+    pub(crate) fn enforced(&self) -> bool {
+        self.policy.is_some()
+    }
+
+    pub(crate) fn authenticated(&self) -> bool {
+        self.policy.is_none() || self.principal.is_some()
+    }
+
+    // Acts as the principal holding `api_key` from now on; returns their name
+    pub(crate) fn authenticate(&mut self, api_key: &str) -> Result<String, VaultError> {
+        let Some(policy) = &self.policy else {
+            return Err(VaultError::InvalidInput(
+                "No access policy is configured".to_string(),
+            ));
+        };
+        let principal = policy
+            .authenticate(api_key)
+            .ok_or_else(|| VaultError::InvalidInput("Unknown API key".to_string()))?;
+        self.principal = Some(principal.clone());
+        Ok(principal.name.clone())
+    }
+
+    // The principal's name, or "anonymous" before authentication
+    pub(crate) fn actor(&self) -> &str {
+        self.principal.as_ref().map_or("anonymous", |p| &p.name)
+    }
+
+    pub(crate) fn allows(&self, permission: Permission, key: &str) -> bool {
+        match (&self.policy, &self.principal) {
+            (None, _) => true,
+            (Some(policy), Some(principal)) => policy.allows(principal, permission, key),
+            (Some(_), None) => false,
+        }
+    }
+
+    pub(crate) fn check(&self, permission: Permission, key: &str) -> Result<(), VaultError> {
+        if self.allows(permission, key) {
+            return Ok(());
+        }
+        Err(denied(self.actor(), permission, key))
+    }
+
+    // Delete permission on everything removing `key` would take out
+    pub(crate) fn check_removal<B: StorageBackend<String>>(
+        &self,
+        vault: &Vault<String, B>,
+        key: &String,
+    ) -> Result<(), VaultError> {
+        if !self.enforced() {
+            return Ok(());
+        }
+        for key in vault.removal_set(key) {
+            self.check(Permission::Delete, key)?;
+        }
+        Ok(())
+    }
+}
+
+// A vault seen through one principal's permissions. Reads of keys they cannot
+// read fail, and listings leave such keys out.
+pub struct AuthorizedVault<'a, K, B = MemoryBackend<K>>
+where
+    K: Eq + Hash + Display,
+{
+    vault: &'a mut Vault<K, B>,
+    policy: &'a AccessPolicy,
+    principal: &'a Principal,
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    pub fn authorized<'a>(
+        &'a mut self,
+        policy: &'a AccessPolicy,
+        principal: &'a Principal,
+    ) -> AuthorizedVault<'a, K, B> {
+        AuthorizedVault {
+            vault: self,
+            policy,
+            principal,
+        }
+    }
+}
+
+impl<K, B> AuthorizedVault<'_, K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    pub fn principal(&self) -> &Principal {
+        self.principal
+    }
+
+    pub fn check(&self, permission: Permission, key: &K) -> Result<(), VaultError> {
+        self.policy
+            .check(self.principal, permission, &key.to_string())
+    }
+
+    fn can_read(&self, key: &K) -> bool {
+        self.policy
+            .allows(self.principal, Permission::Read, &key.to_string())
+    }
+
+    pub fn get(&self, key: &K) -> Result<Option<&Resource>, VaultError> {
+        self.check(Permission::Read, key)?;
+        Ok(self.vault.get(key))
+    }
+
+    pub fn contains_key(&self, key: &K) -> Result<bool, VaultError> {
+        self.check(Permission::Read, key)?;
+        Ok(self.vault.contains_key(key))
+    }
+
+    // Readable keys only
+    pub fn keys(&self) -> Vec<&K> {
+        self.vault.keys().filter(|key| self.can_read(key)).collect()
+    }
+
+    // Readable hits only
+    pub fn search(&self, query: &str) -> Vec<(&K, &Resource)> {
+        self.vault
+            .search(query)
+            .into_iter()
+            .filter(|(key, _)| self.can_read(key))
+            .collect()
+    }
+
+    pub fn add(&mut self, key: K, resource: Resource) -> Result<(), VaultError> {
+        self.check(Permission::Write, &key)?;
+        self.vault.add(key, resource)
+    }
+
+    pub fn update(&mut self, key: &K, resource: Resource) -> Result<Resource, VaultError> {
+        self.check(Permission::Write, key)?;
+        self.vault.update(key, resource)
+    }
+
+    pub fn set_ttl(&mut self, key: &K, ttl: Option<Duration>) -> Result<(), VaultError> {
+        self.check(Permission::Write, key)?;
+        self.vault.set_ttl(key, ttl)
+    }
+
+    // Needs delete permission on every entry that would go, cascades included
+    pub fn remove(&mut self, key: &K) -> Result<Resource, VaultError> {
+        for removed in self.vault.removal_set(key) {
+            self.check(Permission::Delete, removed)?;
+        }
+        self.vault.remove(key)
+    }
+
+    // Writes to `from` and reads `to`
+    pub fn link(&mut self, from: &K, relation: &str, to: &K) -> Result<(), VaultError> {
+        self.check(Permission::Write, from)?;
+        self.check(Permission::Read, to)?;
+        self.vault.link(from, relation, to)
+    }
+
+    pub fn unlink(&mut self, from: &K, relation: &str, to: &K) -> Result<bool, VaultError> {
+        self.check(Permission::Write, from)?;
+        self.vault.unlink(from, relation, to)
+    }
+
+    // Full access, for admins of the whole vault
+    pub fn vault(&mut self) -> Result<&mut Vault<K, B>, VaultError> {
+        self.policy.check(self.principal, Permission::Admin, "")?;
+        Ok(self.vault)
+    }
+}
@@ -0,0 +1,183 @@
+use crate::error::VaultError;
+use crate::resource::Resource;
+use crate::vault::{temp_path, Vault};
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::Display;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::JoinHandle;
+
+// A `Vault` for async code. Clones are handles to the same vault.
+//
+// Every operation waits for the lock, then runs the sync `Vault` method
+// without awaiting again, so a call cancelled before it returns has changed
+// nothing. Files are read and written through tokio; saves go to a temporary
+// file that replaces the target only once complete.
+pub struct AsyncVault<K>
+where
+    K: Eq + Hash + Display,
+{
+    vault: Arc<Mutex<Vault<K>>>,
+    // Bumped by every change; background flushing skips saves when it has not moved
+    changes: Arc<AtomicU64>,
+}
+
+impl<K> Clone for AsyncVault<K>
+where
+    K: Eq + Hash + Display,
+{
+    fn clone(&self) -> Self {
+        Self {
+            vault: self.vault.clone(),
+            changes: self.changes.clone(),
+        }
+    }
+}
+
+impl<K> AsyncVault<K>
+where
+    K: Eq + Hash + Display + Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    pub fn new(vault: Vault<K>) -> Self {
+        Self {
+            vault: Arc::new(Mutex::new(vault)),
+            changes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub async fn load(path: &str) -> Result<Self, VaultError> {
+        let json = tokio::fs::read_to_string(path).await?;
+        Ok(Self::new(Vault::from_json(&json)?))
+    }
+
+    pub async fn add(&self, key: K, resource: Resource) -> Result<(), VaultError> {
+        self.write(|vault| vault.add(key, resource)).await
+    }
+
+    pub async fn get(&self, key: &K) -> Option<Resource> {
+        self.read(|vault| vault.get(key).cloned()).await
+    }
+
+    pub async fn update(&self, key: &K, resource: Resource) -> Result<Resource, VaultError> {
+        self.write(|vault| vault.update(key, resource)).await
+    }
+
+    pub async fn remove(&self, key: &K) -> Result<Resource, VaultError> {
+        self.write(|vault| vault.remove(key)).await
+    }
+
+    pub async fn contains_key(&self, key: &K) -> bool {
+        self.read(|vault| vault.contains_key(key)).await
+    }
+
+    pub async fn len(&self) -> usize {
+        self.read(|vault| vault.len()).await
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.read(|vault| vault.is_empty()).await
+    }
+
+    // Runs `f` against the vault, for any read the methods above do not cover
+    pub async fn read<R>(&self, f: impl FnOnce(&Vault<K>) -> R) -> R {
+        f(&*self.vault.lock().await)
+    }
+
+    // Runs `f` against the vault and marks it changed
+    pub async fn write<R>(&self, f: impl FnOnce(&mut Vault<K>) -> R) -> R {
+        let mut vault = self.vault.lock().await;
+        self.changes.fetch_add(1, Ordering::SeqCst);
+        f(&mut vault)
+    }
+
+    pub async fn save(&self, path: &str) -> Result<(), VaultError> {
+        self.save_changes(path).await.map(|_| ())
+    }
+
+    // Saves and returns the change count the file reflects
+    async fn save_changes(&self, path: &str) -> Result<u64, VaultError> {
+        let (json, changes) = {
+            let vault = self.vault.lock().await;
+            (vault.to_json()?, self.changes.load(Ordering::SeqCst))
+        };
+        let temp = temp_path(path);
+        if let Err(e) = write_then_rename(&temp, path, json).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(e);
+        }
+        Ok(changes)
+    }
+
+    // Saves to `path` every `interval` while there are unsaved changes, and
+    // once more when the returned `Flusher` is stopped or dropped. A failed
+    // save is retried on the next tick.
+    pub fn flush_every(&self, path: &str, interval: Duration) -> Flusher {
+        let vault = self.clone();
+        let path = path.to_string();
+        let (stop, mut stopped) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            let mut saved = vault.changes.load(Ordering::SeqCst);
+            let mut ticks = tokio::time::interval(interval);
+            ticks.tick().await;
+            loop {
+                tokio::select! {
+                    _ = ticks.tick() => {
+                        if vault.changes.load(Ordering::SeqCst) != saved {
+                            if let Ok(changes) = vault.save_changes(&path).await {
+                                saved = changes;
+                            }
+                        }
+                    }
+                    _ = &mut stopped => break,
+                }
+            }
+            if vault.changes.load(Ordering::SeqCst) != saved {
+                vault.save_changes(&path).await?;
+            }
+            Ok(())
+        });
+        Flusher {
+            stop: Some(stop),
+            task: Some(task),
+        }
+    }
+}
+
+async fn write_then_rename(temp: &str, path: &str, json: String) -> Result<(), VaultError> {
+    tokio::fs::write(temp, json).await?;
+    tokio::fs::rename(temp, path).await?;
+    Ok(())
+}
+
+// Background flushing started by `AsyncVault::flush_every`
+pub struct Flusher {
+    stop: Option<oneshot::Sender<()>>,
+    task: Option<JoinHandle<Result<(), VaultError>>>,
+}
+
+impl Flusher {
+    // Stops flushing and waits for the final save
+    pub async fn stop(mut self) -> Result<(), VaultError> {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        match self.task.take() {
+            Some(task) => task
+                .await
+                .map_err(|e| VaultError::IoError(std::io::Error::other(e)))?,
+            None => Ok(()),
+        }
+    }
+}
+
+// Dropping without `stop` still makes the final save, in the background
+impl Drop for Flusher {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use crate::backend::StorageBackend;
+use crate::entry::now_millis;
+use crate::error::VaultError;
+use crate::events::Removal;
+use crate::resource::Resource;
+use crate::vault::{invalid_data, Vault};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::{Display, Write as _};
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hash;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+// The `prev` of the first record in a log
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// Who changes the vault until told otherwise
+const DEFAULT_ACTOR: &str = "local";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOp {
+    Add,
+    Update,
+    Remove,
+    // Removed by the vault itself, to make room or because a time-to-live ran out
+    Evict,
+    Expire,
+}
+
+impl From<Removal> for AuditOp {
+    fn from(cause: Removal) -> Self {
+        match cause {
+            Removal::Removed => AuditOp::Remove,
+            Removal::Evicted => AuditOp::Evict,
+            Removal::Expired => AuditOp::Expire,
+        }
+    }
+}
+
+// One line of an audit log. Resources are recorded only as SHA-256 digests,
+// so the log shows that a value changed without keeping a copy of it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub seq: u64,
+    pub timestamp: u64, // milliseconds since the Unix epoch
+    pub op: AuditOp,
+    pub key: String,
+    pub actor: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    // Hash of the previous record; `hash` covers every other field, this one included
+    pub prev: String,
+    pub hash: String,
+}
+
+// The fields `hash` is computed over, in a fixed order
+#[derive(Serialize)]
+struct RecordBody<'a> {
+    seq: u64,
+    timestamp: u64,
+    op: AuditOp,
+    key: &'a str,
+    actor: &'a str,
+    before: &'a Option<String>,
+    after: &'a Option<String>,
+    prev: &'a str,
+}
+
+impl AuditRecord {
+    fn compute_hash(&self) -> String {
+        let body = RecordBody {
+            seq: self.seq,
+            timestamp: self.timestamp,
+            op: self.op,
+            key: &self.key,
+            actor: &self.actor,
+            before: &self.before,
+            after: &self.after,
+            prev: &self.prev,
+        };
+        let json = serde_json::to_vec(&body).unwrap_or_default();
+        hex(&Sha256::digest(json))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{:02x}", byte);
+        out
+    })
+}
+
+// SHA-256 of a resource's JSON form
+pub fn resource_digest(resource: &Resource) -> String {
+    hex(&Sha256::digest(
+        serde_json::to_vec(resource).unwrap_or_default(),
+    ))
+}
+
+// Which records `AuditLog::query` returns. Empty matches everything; times
+// are inclusive, in milliseconds since the Unix epoch.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditQuery {
+    pub key: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, record: &AuditRecord) -> bool {
+        self.key.as_ref().is_none_or(|key| *key == record.key)
+            && self.since.is_none_or(|since| record.timestamp >= since)
+            && self.until.is_none_or(|until| record.timestamp <= until)
+    }
+}
+
+// Append-only, hash-chained record of every change to a vault's entries, one
+// JSON record per line. Editing, reordering or deleting a record breaks the
+// chain from that point on, which `verify` reports.
+pub struct AuditLog {
+    file: File,
+    seq: u64,
+    last_hash: String,
+    actor: String,
+}
+
+impl AuditLog {
+    // Continues the chain of an existing log
+    pub(crate) fn open(path: &Path) -> Result<Self, VaultError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        // Cut off a torn final record so new records start on a fresh line
+        let contents = fs::read(path)?;
+        let keep = if contents.last().is_some_and(|byte| *byte != b'\n') {
+            let keep = contents
+                .iter()
+                .rposition(|byte| *byte == b'\n')
+                .map_or(0, |i| i + 1);
+            file.set_len(keep as u64)?;
+            keep
+        } else {
+            contents.len()
+        };
+        let last = String::from_utf8_lossy(&contents[..keep])
+            .lines()
+            .rfind(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str::<AuditRecord>(line).map_err(invalid_data))
+            .transpose()?;
+
+        Ok(Self {
+            file,
+            seq: last.as_ref().map_or(0, |record| record.seq + 1),
+            last_hash: last.map_or(GENESIS.to_string(), |record| record.hash),
+            actor: DEFAULT_ACTOR.to_string(),
+        })
+    }
+
+    // Writes one record and waits for it to reach the disk
+    fn append(
+        &mut self,
+        op: AuditOp,
+        key: String,
+        before: Option<String>,
+        after: Option<String>,
+    ) -> Result<(), VaultError> {
+        let mut record = AuditRecord {
+            seq: self.seq,
+            timestamp: now_millis(),
+            op,
+            key,
+            actor: self.actor.clone(),
+            before,
+            after,
+            prev: self.last_hash.clone(),
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash();
+        let mut line = serde_json::to_string(&record).map_err(invalid_data)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+        self.seq += 1;
+        self.last_hash = record.hash;
+        Ok(())
+    }
+
+    pub fn read(path: &str) -> Result<Vec<AuditRecord>, VaultError> {
+        let mut records = Vec::new();
+        for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            records.push(serde_json::from_str(&line).map_err(|e| {
+                VaultError::InvalidInput(format!(
+                    "Corrupt audit record on line {}: {}",
+                    index + 1,
+                    e
+                ))
+            })?);
+        }
+        Ok(records)
+    }
+
+    pub fn query(path: &str, query: &AuditQuery) -> Result<Vec<AuditRecord>, VaultError> {
+        let mut records = Self::read(path)?;
+        records.retain(|record| query.matches(record));
+        Ok(records)
+    }
+
+    // Checks every link of the chain and returns the number of records. Records
+    // cut off the end leave the chain intact, so keep the count or the last
+    // hash somewhere else to detect that.
+    pub fn verify(path: &str) -> Result<u64, VaultError> {
+        let mut prev = GENESIS.to_string();
+        let mut count = 0;
+        for record in Self::read(path)? {
+            let problem = if record.seq != count {
+                Some(format!("expected sequence number {}", count))
+            } else if record.prev != prev {
+                Some("does not follow the record before it".to_string())
+            } else if record.compute_hash() != record.hash {
+                Some("contents do not match its hash".to_string())
+            } else {
+                None
+            };
+            if let Some(problem) = problem {
+                return Err(VaultError::InvalidInput(format!(
+                    "Audit log tampered at record {}: {}",
+                    record.seq, problem
+                )));
+            }
+            prev = record.hash;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    // Records every later add, update and removal in the log at `path`,
+    // continuing its chain if it already exists
+    pub fn open_audit_log(&mut self, path: &str) -> Result<(), VaultError> {
+        self.audit = Some(AuditLog::open(Path::new(path))?);
+        Ok(())
+    }
+
+    // Names who is making the changes recorded from now on; "local" by
+    // default. Does nothing without an audit log.
+    pub fn set_actor(&mut self, actor: &str) {
+        if let Some(audit) = &mut self.audit {
+            if audit.actor != actor {
+                audit.actor = actor.to_string();
+            }
+        }
+    }
+
+    pub(crate) fn is_auditing(&self) -> bool {
+        self.audit.is_some()
+    }
+
+    pub(crate) fn audit(
+        &mut self,
+        op: AuditOp,
+        key: &K,
+        before: Option<String>,
+        after: Option<String>,
+    ) -> Result<(), VaultError> {
+        match &mut self.audit {
+            Some(audit) => audit.append(op, key.to_string(), before, after),
+            None => Ok(()),
+        }
+    }
+}
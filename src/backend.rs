@@ -0,0 +1,131 @@
+use crate::entry::EntryMeta;
+use crate::error::VaultError;
+use crate::resource::Resource;
+use imbl::HashMap as ImHashMap;
+use std::hash::Hash;
+
+// Resources a backend that pages them in from storage may keep past its next write
+pub(crate) const CACHE_ENTRIES: usize = 4096;
+
+// Where a vault keeps its key -> resource map. The vault itself handles
+// capacity, metadata, events and journaling; a backend only stores entries.
+pub trait StorageBackend<K> {
+    fn get(&self, key: &K) -> Option<&Resource>;
+
+    fn contains_key(&self, key: &K) -> bool;
+
+    // Stores a resource, returning the one it replaced
+    fn insert(&mut self, key: K, resource: Resource) -> Result<Option<Resource>, VaultError>;
+
+    // Stores a resource together with its metadata. Backends that keep
+    // metadata per entry write both at once; the rest store the resource and
+    // are handed all metadata through `store_meta`.
+    fn insert_with_meta(
+        &mut self,
+        key: K,
+        resource: Resource,
+        _meta: &EntryMeta,
+    ) -> Result<Option<Resource>, VaultError> {
+        self.insert(key, resource)
+    }
+
+    // Likewise for a change to an entry's metadata alone
+    fn put_meta(&mut self, _key: &K, _meta: &EntryMeta) -> Result<(), VaultError> {
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &K) -> Result<Option<Resource>, VaultError>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_>;
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &Resource)> + '_>;
+
+    // Size of a stored resource; backends that track sizes can answer without loading it
+    fn size_of(&self, key: &K) -> Option<u64> {
+        self.get(key).map(|res| res.size_bytes())
+    }
+
+    // Makes every write so far durable
+    fn flush(&mut self) -> Result<(), VaultError> {
+        Ok(())
+    }
+
+    // Backends that persist on their own also keep the vault's serialized entry
+    // metadata, so it survives a reopen
+    fn load_meta(&self) -> Result<Option<Vec<u8>>, VaultError> {
+        Ok(None)
+    }
+
+    fn store_meta(&mut self, _meta: &[u8]) -> Result<(), VaultError> {
+        Ok(())
+    }
+
+    // Likewise for the serialized edges between entries
+    fn load_edges(&self) -> Result<Option<Vec<u8>>, VaultError> {
+        Ok(None)
+    }
+
+    fn store_edges(&mut self, _edges: &[u8]) -> Result<(), VaultError> {
+        Ok(())
+    }
+}
+
+// The default backend: everything in RAM in a persistent (structurally shared) map
+pub struct MemoryBackend<K> {
+    map: ImHashMap<K, Resource>,
+}
+
+impl<K: Eq + Hash + Clone> MemoryBackend<K> {
+    pub fn new() -> Self {
+        Self {
+            map: ImHashMap::new(),
+        }
+    }
+
+    // Cloning the map is O(1), which is what makes vault snapshots cheap
+    pub(crate) fn map(&self) -> &ImHashMap<K, Resource> {
+        &self.map
+    }
+}
+
+impl<K: Eq + Hash + Clone> Default for MemoryBackend<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash + Clone> StorageBackend<K> for MemoryBackend<K> {
+    fn get(&self, key: &K) -> Option<&Resource> {
+        self.map.get(key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+
+    fn insert(&mut self, key: K, resource: Resource) -> Result<Option<Resource>, VaultError> {
+        Ok(self.map.insert(key, resource))
+    }
+
+    fn remove(&mut self, key: &K) -> Result<Option<Resource>, VaultError> {
+        Ok(self.map.remove(key))
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_> {
+        Box::new(self.map.keys())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &Resource)> + '_> {
+        Box::new(self.map.iter())
+    }
+}
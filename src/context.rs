@@ -0,0 +1,158 @@
+use crate::backend::StorageBackend;
+use crate::entry::now_millis;
+use crate::tokenizer::{ApproxTokenizer, Tokenizer};
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::hash::Hash;
+use std::time::Duration;
+
+// Relevance added for each way a memory was picked
+const PINNED_BOOST: f64 = 2.0;
+const RECENCY_WEIGHT: f64 = 0.5;
+
+// An assembled prompt context and where each part of it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Context<K> {
+    pub text: String,
+    // Keys of the included memories, in the order they appear in `text`
+    pub keys: Vec<K>,
+    pub tokens: usize,
+}
+
+// Gathers memories relevant to a query and packs as many as fit into a token
+// budget, most relevant first. Candidates come from pinned keys (for example
+// the result of a tag lookup), a keyword search and the most recently used
+// entries; a memory found several ways scores for each of them.
+pub struct ContextBuilder<K> {
+    query: String,
+    budget: usize,
+    tokenizer: Box<dyn Tokenizer>,
+    pinned: Vec<K>,
+    recent: usize,
+    half_life: Duration,
+}
+
+impl<K> ContextBuilder<K>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+{
+    pub fn new(query: &str, budget: usize) -> Self {
+        Self {
+            query: query.to_string(),
+            budget,
+            tokenizer: Box::new(ApproxTokenizer),
+            pinned: Vec::new(),
+            recent: 5,
+            half_life: Duration::from_secs(60 * 60),
+        }
+    }
+
+    pub fn tokenizer<T: Tokenizer + 'static>(mut self, tokenizer: T) -> Self {
+        self.tokenizer = Box::new(tokenizer);
+        self
+    }
+
+    // Always considered, ahead of anything the search finds
+    pub fn pin(mut self, key: K) -> Self {
+        self.pinned.push(key);
+        self
+    }
+
+    pub fn pin_all<I: IntoIterator<Item = K>>(mut self, keys: I) -> Self {
+        self.pinned.extend(keys);
+        self
+    }
+
+    // How many of the most recently used entries to consider regardless of the query
+    pub fn recent(mut self, n: usize) -> Self {
+        self.recent = n;
+        self
+    }
+
+    // How quickly the recency bonus fades
+    pub fn recency_half_life(mut self, half_life: Duration) -> Self {
+        self.half_life = half_life;
+        self
+    }
+
+    // One line per memory: `[key] content`
+    fn render(key: &K, text: &str) -> String {
+        format!("[{}] {}", key, text.trim())
+    }
+
+    pub fn build<B: StorageBackend<K>>(&self, vault: &Vault<K, B>) -> Context<K> {
+        let mut scores: HashMap<K, f64> = HashMap::new();
+
+        for key in &self.pinned {
+            if vault.contains_key(key) {
+                *scores.entry(key.clone()).or_default() += PINNED_BOOST;
+            }
+        }
+
+        // Share of query terms found in the key or content
+        let terms: Vec<String> = self
+            .query
+            .split_whitespace()
+            .map(|term| term.to_lowercase())
+            .collect();
+        if !terms.is_empty() {
+            for (key, resource) in vault.iter() {
+                let haystack = format!("{} {}", key, resource.to_text()).to_lowercase();
+                let matched = terms.iter().filter(|term| haystack.contains(*term)).count();
+                if matched > 0 {
+                    *scores.entry(key.clone()).or_default() += matched as f64 / terms.len() as f64;
+                }
+            }
+        }
+
+        let now = now_millis();
+        let mut by_recency: Vec<(&K, u64)> = vault
+            .keys()
+            .filter_map(|key| Some((key, vault.entry_meta(key)?.last_used())))
+            .collect();
+        by_recency.sort_by_key(|(_, last_used)| std::cmp::Reverse(*last_used));
+        for (key, last_used) in by_recency.into_iter().take(self.recent) {
+            let age = now.saturating_sub(last_used) as f64 / 1000.0;
+            let recency = 0.5f64.powf(age / self.half_life.as_secs_f64().max(f64::MIN_POSITIVE));
+            *scores.entry(key.clone()).or_default() += RECENCY_WEIGHT * recency;
+        }
+
+        let mut ranked: Vec<(K, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then_with(|| a.0.to_string().cmp(&b.0.to_string()))
+        });
+
+        let mut lines = Vec::new();
+        let mut keys = Vec::new();
+        let mut tokens = 0;
+        let mut seen = HashSet::new();
+        for (key, _) in ranked {
+            let Some(resource) = vault.get(&key) else {
+                continue;
+            };
+            // Identical content under different keys is only included once
+            let text = resource.to_text();
+            if !seen.insert(text.trim().to_lowercase()) {
+                continue;
+            }
+            let line = Self::render(&key, &text);
+            let cost = self.tokenizer.count(&line);
+            if tokens + cost > self.budget {
+                continue;
+            }
+            tokens += cost;
+            lines.push(line);
+            keys.push(key);
+        }
+
+        Context {
+            text: lines.join("\n"),
+            keys,
+            tokens,
+        }
+    }
+}
@@ -0,0 +1,569 @@
+use crate::acl::{Access, AccessPolicy, Permission};
+use crate::error::VaultError;
+use crate::graph::Edge;
+use crate::memory::MemorySize;
+use crate::recall::RecallOptions;
+use crate::resource::Resource;
+use crate::vault::{invalid_data, Vault};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How often the accept loop checks for a shutdown request
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// One line sent to the daemon. Requests are answered in the order they
+// arrive on a connection, so clients may pipeline them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub id: u64,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+// One line sent back, carrying the id of the request it answers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub id: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<WireError>,
+}
+
+// A `VaultError` in transit. `detail` holds the error itself when it can be
+// serialized, so the client can rebuild the same variant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WireError {
+    pub kind: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<Value>,
+}
+
+impl From<&VaultError> for WireError {
+    fn from(error: &VaultError) -> Self {
+        Self {
+            kind: error.kind().to_string(),
+            message: error.message(),
+            detail: serde_json::to_value(error).ok(),
+        }
+    }
+}
+
+impl From<WireError> for VaultError {
+    fn from(error: WireError) -> Self {
+        error
+            .detail
+            .and_then(|detail| serde_json::from_value(detail).ok())
+            .unwrap_or_else(|| VaultError::IoError(std::io::Error::other(error.message)))
+    }
+}
+
+fn param<T: DeserializeOwned>(params: &Value, name: &str) -> Result<T, VaultError> {
+    let value = params.get(name).cloned().unwrap_or(Value::Null);
+    serde_json::from_value(value)
+        .map_err(|e| VaultError::InvalidInput(format!("Bad parameter '{}': {}", name, e)))
+}
+
+// Negative, infinite and NaN durations are refused rather than panicking
+fn seconds(value: f64) -> Result<Duration, VaultError> {
+    Duration::try_from_secs_f64(value)
+        .map_err(|_| VaultError::InvalidInput(format!("Invalid number of seconds: {}", value)))
+}
+
+fn to_value<T: Serialize>(value: T) -> Result<Value, VaultError> {
+    serde_json::to_value(value).map_err(invalid_data)
+}
+
+// Runs one request against the vault. Method names and parameters follow the
+// `Vault` methods they call. Listings leave out keys the client cannot read.
+pub(crate) fn dispatch(
+    vault: &mut Vault<String>,
+    access: &Access,
+    method: &str,
+    params: &Value,
+) -> Result<Value, VaultError> {
+    let key = || param::<String>(params, "key");
+    // The request's key, once the client is known to hold `permission` on it
+    let checked = |permission| {
+        let key = key()?;
+        access.check(permission, &key)?;
+        Ok::<_, VaultError>(key)
+    };
+    match method {
+        "ping" => Ok(json!("pong")),
+        "add" => {
+            vault.add(checked(Permission::Write)?, param(params, "resource")?)?;
+            Ok(Value::Null)
+        }
+        "get" => to_value(vault.get(&checked(Permission::Read)?)),
+        "update" => {
+            to_value(vault.update(&checked(Permission::Write)?, param(params, "resource")?)?)
+        }
+        "remove" => {
+            let key = key()?;
+            access.check_removal(vault, &key)?;
+            to_value(vault.remove(&key)?)
+        }
+        "contains_key" => to_value(vault.contains_key(&checked(Permission::Read)?)),
+        // Vault-wide figures need read permission on the whole vault
+        "len" | "is_empty" | "current_usage" => {
+            access.check(Permission::Read, "")?;
+            match method {
+                "len" => to_value(vault.len()),
+                "is_empty" => to_value(vault.is_empty()),
+                _ => to_value(vault.current_usage()),
+            }
+        }
+        "keys" => {
+            let mut keys: Vec<&String> = vault
+                .keys()
+                .filter(|key| access.allows(Permission::Read, key))
+                .collect();
+            keys.sort();
+            to_value(keys)
+        }
+        "search" => {
+            let hits = vault.search(&param::<String>(params, "query")?);
+            let hits: Vec<_> = hits
+                .into_iter()
+                .filter(|(key, _)| access.allows(Permission::Read, key))
+                .collect();
+            to_value(hits)
+        }
+        "recall" => {
+            let limit: Option<usize> = param(params, "limit")?;
+            let within = access.enforced().then(|| {
+                vault
+                    .keys()
+                    .filter(|key| access.allows(Permission::Read, key))
+                    .cloned()
+                    .collect()
+            });
+            let options = RecallOptions {
+                limit: limit.unwrap_or(10),
+                within,
+                ..Default::default()
+            };
+            let hits: Vec<Value> = vault
+                .recall(&param::<String>(params, "query")?, &options)
+                .into_iter()
+                .map(|hit| {
+                    json!({
+                        "key": hit.key,
+                        "resource": hit.resource,
+                        "score": hit.score,
+                        "explanation": hit.explanation,
+                    })
+                })
+                .collect();
+            Ok(Value::Array(hits))
+        }
+        "set_ttl" => {
+            let seconds: Option<f64> = param(params, "seconds")?;
+            vault.set_ttl(
+                &checked(Permission::Write)?,
+                seconds.map(self::seconds).transpose()?,
+            )?;
+            Ok(Value::Null)
+        }
+        "ttl" => to_value(
+            vault
+                .ttl(&checked(Permission::Read)?)
+                .map(|ttl| ttl.as_secs_f64()),
+        ),
+        "set_importance" => {
+            vault.set_importance(&checked(Permission::Write)?, param(params, "importance")?)?;
+            Ok(Value::Null)
+        }
+        "reinforce" => {
+            vault.reinforce(&checked(Permission::Write)?)?;
+            Ok(Value::Null)
+        }
+        "link" => {
+            let (from, to): (String, String) = (param(params, "from")?, param(params, "to")?);
+            access.check(Permission::Write, &from)?;
+            access.check(Permission::Read, &to)?;
+            vault.link(&from, &param::<String>(params, "relation")?, &to)?;
+            Ok(Value::Null)
+        }
+        "unlink" => {
+            let (from, to): (String, String) = (param(params, "from")?, param(params, "to")?);
+            access.check(Permission::Write, &from)?;
+            to_value(vault.unlink(&from, &param::<String>(params, "relation")?, &to)?)
+        }
+        "neighbors" => {
+            let edges: Vec<_> = vault
+                .neighbors(&checked(Permission::Read)?)
+                .iter()
+                .filter(|edge| access.allows(Permission::Read, &edge.to))
+                .collect();
+            to_value(edges)
+        }
+        other => Err(VaultError::InvalidInput(format!(
+            "Unknown method '{}'",
+            other
+        ))),
+    }
+}
+
+// Serves a vault over a Unix domain socket, one thread per client. Stops on a
+// `shutdown` request, saving the vault first when a save path is set.
+pub struct Daemon {
+    vault: Arc<Mutex<Vault<String>>>,
+    save_path: Option<String>,
+    stopping: Arc<AtomicBool>,
+    policy: Option<Arc<AccessPolicy>>,
+}
+
+impl Daemon {
+    pub fn new(vault: Vault<String>, save_path: Option<String>) -> Self {
+        Self {
+            vault: Arc::new(Mutex::new(vault)),
+            save_path,
+            stopping: Arc::new(AtomicBool::new(false)),
+            policy: None,
+        }
+    }
+
+    // Requires clients to `auth` with an API key, and checks every request
+    // against the principal's permissions. `save` and `shutdown` need admin.
+    pub fn with_policy(mut self, policy: AccessPolicy) -> Self {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    // Records every change clients make in the audit log at `path`, under the
+    // name of the principal making it
+    pub fn with_audit_log(self, path: &str) -> Result<Self, VaultError> {
+        lock(&self.vault)?.open_audit_log(path)?;
+        Ok(self)
+    }
+
+    // Saves back to `path` on `save` requests and at shutdown
+    pub fn open(path: &str, location: String, capacity: MemorySize) -> Result<Self, VaultError> {
+        let vault = Vault::open_or_create(path, location, capacity)?;
+        Ok(Self::new(vault, Some(path.to_string())))
+    }
+
+    // Setting this to true stops the daemon as a `shutdown` request would
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stopping.clone()
+    }
+
+    // Listens on `socket` until shut down, then saves the vault and removes the socket file
+    pub fn serve(&self, socket: &str) -> Result<(), VaultError> {
+        // A socket file left behind by a daemon that did not shut down cleanly
+        if Path::new(socket).exists() && UnixStream::connect(socket).is_err() {
+            std::fs::remove_file(socket)?;
+        }
+        let listener = UnixListener::bind(socket)?;
+        listener.set_nonblocking(true)?;
+
+        while !self.stopping.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    let vault = self.vault.clone();
+                    let save_path = self.save_path.clone();
+                    let stopping = self.stopping.clone();
+                    let access = Access::new(self.policy.clone());
+                    thread::spawn(move || {
+                        serve_client(stream, &vault, save_path, &stopping, access)
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let result = self.save();
+        std::fs::remove_file(socket)?;
+        result
+    }
+
+    fn save(&self) -> Result<(), VaultError> {
+        let vault = lock(&self.vault)?;
+        match &self.save_path {
+            Some(path) => vault.save_to_file(path),
+            None => Ok(()),
+        }
+    }
+}
+
+fn lock(
+    vault: &Mutex<Vault<String>>,
+) -> Result<std::sync::MutexGuard<'_, Vault<String>>, VaultError> {
+    vault
+        .lock()
+        .map_err(|_| VaultError::IoError(std::io::Error::other("vault lock poisoned")))
+}
+
+fn serve_client(
+    stream: UnixStream,
+    vault: &Mutex<Vault<String>>,
+    save_path: Option<String>,
+    stopping: &AtomicBool,
+    mut access: Access,
+) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                let result = match request.method.as_str() {
+                    "auth" => param::<String>(&request.params, "api_key")
+                        .and_then(|api_key| access.authenticate(&api_key))
+                        .map(|principal| json!({ "principal": principal })),
+                    "shutdown" => access.check(Permission::Admin, "").map(|_| {
+                        stopping.store(true, Ordering::SeqCst);
+                        Value::Null
+                    }),
+                    "save" => access
+                        .check(Permission::Admin, "")
+                        .and_then(|_| match &save_path {
+                            Some(path) => lock(vault).and_then(|vault| vault.save_to_file(path)),
+                            None => Err(VaultError::InvalidInput(
+                                "The daemon has no save path".to_string(),
+                            )),
+                        })
+                        .map(|_| Value::Null),
+                    method => lock(vault).and_then(|mut vault| {
+                        vault.set_actor(access.actor());
+                        dispatch(&mut vault, &access, method, &request.params)
+                    }),
+                };
+                match result {
+                    Ok(value) => Response {
+                        id: request.id,
+                        result: Some(value),
+                        error: None,
+                    },
+                    Err(e) => Response {
+                        id: request.id,
+                        result: None,
+                        error: Some(WireError::from(&e)),
+                    },
+                }
+            }
+            Err(e) => Response {
+                id: 0,
+                result: None,
+                error: Some(WireError::from(&VaultError::InvalidInput(format!(
+                    "Malformed request: {}",
+                    e
+                )))),
+            },
+        };
+        let Ok(mut line) = serde_json::to_string(&response) else {
+            return;
+        };
+        line.push('\n');
+        if writer.write_all(line.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+// Talks to a running daemon with the same method names as `Vault`
+pub struct DaemonClient {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    next_id: u64,
+}
+
+impl DaemonClient {
+    pub fn connect(socket: &str) -> Result<Self, VaultError> {
+        let writer = UnixStream::connect(socket)?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Self {
+            reader,
+            writer,
+            next_id: 1,
+        })
+    }
+
+    fn send(&mut self, method: &str, params: Value) -> Result<u64, VaultError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = Request {
+            id,
+            method: method.to_string(),
+            params,
+        };
+        let mut line = serde_json::to_string(&request).map_err(invalid_data)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        Ok(id)
+    }
+
+    fn receive(&mut self, id: u64) -> Result<Result<Value, VaultError>, VaultError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(VaultError::IoError(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "daemon closed the connection",
+            )));
+        }
+        let response: Response = serde_json::from_str(&line).map_err(invalid_data)?;
+        if response.id != id {
+            return Err(VaultError::InvalidInput(format!(
+                "Expected a response to request {}, got {}",
+                id, response.id
+            )));
+        }
+        Ok(match response.error {
+            Some(error) => Err(error.into()),
+            None => Ok(response.result.unwrap_or(Value::Null)),
+        })
+    }
+
+    // Acts as the principal holding `api_key` for the rest of the connection;
+    // returns their name
+    pub fn auth(&mut self, api_key: &str) -> Result<String, VaultError> {
+        let reply = self.call("auth", json!({ "api_key": api_key }))?;
+        Ok(reply["principal"].as_str().unwrap_or_default().to_string())
+    }
+
+    // Sends one request and waits for its answer
+    pub fn call(&mut self, method: &str, params: Value) -> Result<Value, VaultError> {
+        let id = self.send(method, params)?;
+        self.receive(id)?
+    }
+
+    // Sends every request before reading any answer, saving a round trip per
+    // request. Each request gets its own result.
+    pub fn pipeline(
+        &mut self,
+        requests: Vec<(&str, Value)>,
+    ) -> Result<Vec<Result<Value, VaultError>>, VaultError> {
+        let ids = requests
+            .into_iter()
+            .map(|(method, params)| self.send(method, params))
+            .collect::<Result<Vec<_>, _>>()?;
+        ids.into_iter().map(|id| self.receive(id)).collect()
+    }
+
+    fn call_as<T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, VaultError> {
+        serde_json::from_value(self.call(method, params)?).map_err(invalid_data)
+    }
+
+    pub fn add(&mut self, key: &str, resource: Resource) -> Result<(), VaultError> {
+        self.call("add", json!({ "key": key, "resource": resource }))
+            .map(|_| ())
+    }
+
+    pub fn get(&mut self, key: &str) -> Result<Option<Resource>, VaultError> {
+        self.call_as("get", json!({ "key": key }))
+    }
+
+    pub fn update(&mut self, key: &str, resource: Resource) -> Result<Resource, VaultError> {
+        self.call_as("update", json!({ "key": key, "resource": resource }))
+    }
+
+    pub fn remove(&mut self, key: &str) -> Result<Resource, VaultError> {
+        self.call_as("remove", json!({ "key": key }))
+    }
+
+    pub fn contains_key(&mut self, key: &str) -> Result<bool, VaultError> {
+        self.call_as("contains_key", json!({ "key": key }))
+    }
+
+    pub fn len(&mut self) -> Result<usize, VaultError> {
+        self.call_as("len", Value::Null)
+    }
+
+    pub fn is_empty(&mut self) -> Result<bool, VaultError> {
+        self.call_as("is_empty", Value::Null)
+    }
+
+    pub fn current_usage(&mut self) -> Result<u64, VaultError> {
+        self.call_as("current_usage", Value::Null)
+    }
+
+    // Sorted
+    pub fn keys(&mut self) -> Result<Vec<String>, VaultError> {
+        self.call_as("keys", Value::Null)
+    }
+
+    pub fn search(&mut self, query: &str) -> Result<Vec<(String, Resource)>, VaultError> {
+        self.call_as("search", json!({ "query": query }))
+    }
+
+    pub fn set_ttl(&mut self, key: &str, ttl: Option<Duration>) -> Result<(), VaultError> {
+        let seconds = ttl.map(|ttl| ttl.as_secs_f64());
+        self.call("set_ttl", json!({ "key": key, "seconds": seconds }))
+            .map(|_| ())
+    }
+
+    pub fn ttl(&mut self, key: &str) -> Result<Option<Duration>, VaultError> {
+        let seconds: Option<f64> = self.call_as("ttl", json!({ "key": key }))?;
+        seconds.map(self::seconds).transpose()
+    }
+
+    pub fn set_importance(&mut self, key: &str, importance: f64) -> Result<(), VaultError> {
+        self.call(
+            "set_importance",
+            json!({ "key": key, "importance": importance }),
+        )
+        .map(|_| ())
+    }
+
+    pub fn reinforce(&mut self, key: &str) -> Result<(), VaultError> {
+        self.call("reinforce", json!({ "key": key })).map(|_| ())
+    }
+
+    pub fn link(&mut self, from: &str, relation: &str, to: &str) -> Result<(), VaultError> {
+        self.call(
+            "link",
+            json!({ "from": from, "relation": relation, "to": to }),
+        )
+        .map(|_| ())
+    }
+
+    pub fn unlink(&mut self, from: &str, relation: &str, to: &str) -> Result<bool, VaultError> {
+        self.call_as(
+            "unlink",
+            json!({ "from": from, "relation": relation, "to": to }),
+        )
+    }
+
+    pub fn neighbors(&mut self, key: &str) -> Result<Vec<Edge<String>>, VaultError> {
+        self.call_as("neighbors", json!({ "key": key }))
+    }
+
+    // Hits as JSON objects with `key`, `resource`, `score` and `explanation`
+    pub fn recall(&mut self, query: &str, limit: usize) -> Result<Vec<Value>, VaultError> {
+        self.call_as("recall", json!({ "query": query, "limit": limit }))
+    }
+
+    // Writes the vault to the daemon's save path now
+    pub fn save(&mut self) -> Result<(), VaultError> {
+        self.call("save", Value::Null).map(|_| ())
+    }
+
+    // Asks the daemon to save the vault and exit
+    pub fn shutdown(&mut self) -> Result<(), VaultError> {
+        self.call("shutdown", Value::Null).map(|_| ())
+    }
+}
@@ -0,0 +1,506 @@
+use crate::backend::StorageBackend;
+use crate::entry::EntryMeta;
+use crate::error::VaultError;
+use crate::graph::OnDelete;
+use crate::packed::fnv1a;
+use crate::resource::Resource;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::hash::Hash;
+
+// Number of min-hashes kept per text entry
+const SIGNATURE_LEN: usize = 64;
+// Characters per shingle
+const SHINGLE: usize = 3;
+// Signature rows hashed together into one LSH band. Texts sharing any band
+// are compared in full; with 16 bands of 4, pairs at 0.7 overlap share one
+// about 99% of the time.
+const BAND_ROWS: usize = 4;
+// Below this threshold banding misses too many matches, so every stored
+// signature is compared instead
+const BANDED_FROM: f64 = 0.7;
+// Relation used by `DedupPolicy::Link`
+pub const DUPLICATE_OF: &str = "duplicate_of";
+
+// What `add` does with a resource that duplicates an existing entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DedupPolicy {
+    // Fail with `VaultError::Duplicate`
+    Reject,
+    // Keep only the existing entry and bump its `duplicates` counter
+    Merge,
+    // Store it anyway, linked to the existing entry by a `duplicate_of` edge
+    Link,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DedupConfig {
+    pub policy: DedupPolicy,
+    // Estimated shingle overlap (0..=1) at which two texts count as the same;
+    // `None` only catches exact copies
+    pub near_threshold: Option<f64>,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            policy: DedupPolicy::Reject,
+            near_threshold: Some(0.8),
+        }
+    }
+}
+
+// Exact hash of a resource plus, for text, a MinHash signature of its shingles
+#[derive(Debug, Clone)]
+struct Fingerprint {
+    hash: u64,
+    signature: Option<Vec<u64>>,
+}
+
+impl Fingerprint {
+    fn of(resource: &Resource) -> Self {
+        let bytes = serde_json::to_vec(resource).unwrap_or_default();
+        let signature = match resource {
+            Resource::TextMessage(_) | Resource::SystemLogs(_) => {
+                Some(signature(&resource.to_text()))
+            }
+            Resource::SensorData(_) => None,
+        };
+        Self {
+            hash: fnv1a(&bytes),
+            signature,
+        }
+    }
+
+    // Estimated overlap of two texts, if it reaches `near_threshold`
+    fn near(&self, other: &Fingerprint, near_threshold: Option<f64>) -> Option<f64> {
+        let threshold = near_threshold?;
+        let (Some(a), Some(b)) = (&self.signature, &other.signature) else {
+            return None;
+        };
+        let similarity =
+            a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / SIGNATURE_LEN as f64;
+        (similarity >= threshold).then_some(similarity)
+    }
+}
+
+// 1.0 for exact copies, otherwise the estimated overlap of two texts if it
+// reaches `near_threshold`. Equal hashes are only a hint, so the resources
+// themselves are compared before calling them copies.
+fn similarity(
+    (print, resource): (&Fingerprint, &Resource),
+    (other_print, other): (&Fingerprint, &Resource),
+    near_threshold: Option<f64>,
+) -> Option<f64> {
+    if print.hash == other_print.hash && resource == other {
+        return Some(1.0);
+    }
+    print.near(other_print, near_threshold)
+}
+
+// The bucket each band of a signature falls in
+fn bands(signature: &[u64]) -> impl Iterator<Item = (usize, u64)> + '_ {
+    signature
+        .chunks(BAND_ROWS)
+        .map(|rows| rows.iter().fold(0, |hash, row| mix(hash ^ row)))
+        .enumerate()
+}
+
+// Lowercase words without punctuation, so wording is all that is compared
+fn normalize(text: &str) -> Vec<char> {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect()
+        })
+        .filter(|word: &String| !word.is_empty())
+        .collect();
+    words.join(" ").chars().collect()
+}
+
+fn mix(mut x: u64) -> u64 {
+    // splitmix64 finalizer
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn signature(text: &str) -> Vec<u64> {
+    let chars = normalize(text);
+    let shingles: Vec<u64> = if chars.len() <= SHINGLE {
+        vec![fnv1a(chars.iter().collect::<String>().as_bytes())]
+    } else {
+        chars
+            .windows(SHINGLE)
+            .map(|window| fnv1a(window.iter().collect::<String>().as_bytes()))
+            .collect()
+    };
+    (0..SIGNATURE_LEN as u64)
+        .map(|seed| {
+            shingles
+                .iter()
+                .map(|shingle| mix(shingle ^ mix(seed)))
+                .min()
+                .unwrap_or(u64::MAX)
+        })
+        .collect()
+}
+
+// Fingerprints of every entry, kept in step by the vault's entry helpers,
+// bucketed by exact hash and by signature band so lookups only compare
+// likely matches
+pub(crate) struct DedupIndex<K> {
+    pub(crate) config: DedupConfig,
+    fingerprints: HashMap<K, Fingerprint>,
+    exact: HashMap<u64, Vec<K>>,
+    bands: HashMap<(usize, u64), Vec<K>>,
+}
+
+impl<K: Eq + Hash + Clone> DedupIndex<K> {
+    fn new(config: DedupConfig) -> Self {
+        Self {
+            config,
+            fingerprints: HashMap::new(),
+            exact: HashMap::new(),
+            bands: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, key: K, resource: &Resource) {
+        self.insert_print(key, Fingerprint::of(resource));
+    }
+
+    fn insert_print(&mut self, key: K, print: Fingerprint) {
+        self.remove(&key);
+        self.exact.entry(print.hash).or_default().push(key.clone());
+        if let Some(signature) = &print.signature {
+            for band in bands(signature) {
+                self.bands.entry(band).or_default().push(key.clone());
+            }
+        }
+        self.fingerprints.insert(key, print);
+    }
+
+    pub(crate) fn remove(&mut self, key: &K) {
+        let Some(print) = self.fingerprints.remove(key) else {
+            return;
+        };
+        drop_from(&mut self.exact, print.hash, key);
+        if let Some(signature) = &print.signature {
+            for band in bands(signature) {
+                drop_from(&mut self.bands, band, key);
+            }
+        }
+    }
+
+    // The indexed key `resource` duplicates most closely, with their
+    // similarity. `content` gives the current value of an indexed key, or
+    // `None` to leave it out.
+    fn best_match<'a>(
+        &'a self,
+        probe: &Fingerprint,
+        resource: &Resource,
+        near_threshold: Option<f64>,
+        content: impl Fn(&K) -> Option<&'a Resource>,
+    ) -> Option<(&'a K, f64)> {
+        let mut candidates: HashSet<&K> =
+            self.exact.get(&probe.hash).into_iter().flatten().collect();
+        match (near_threshold, &probe.signature) {
+            (Some(threshold), Some(_)) if threshold < BANDED_FROM => {
+                candidates.extend(self.fingerprints.keys());
+            }
+            (Some(_), Some(signature)) => {
+                for band in bands(signature) {
+                    candidates.extend(self.bands.get(&band).into_iter().flatten());
+                }
+            }
+            _ => {}
+        }
+        candidates
+            .into_iter()
+            .filter_map(|key| {
+                let other = content(key)?;
+                let print = self.fingerprints.get(key)?;
+                Some((
+                    key,
+                    similarity((probe, resource), (print, other), near_threshold)?,
+                ))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+fn drop_from<B: Eq + Hash, K: PartialEq>(buckets: &mut HashMap<B, Vec<K>>, bucket: B, key: &K) {
+    if let Some(keys) = buckets.get_mut(&bucket) {
+        keys.retain(|other| other != key);
+        if keys.is_empty() {
+            buckets.remove(&bucket);
+        }
+    }
+}
+
+// What dedup leaves of a batch of changes: the changes still to apply, how
+// many copies each original absorbed, and `duplicate_of` links to add
+pub(crate) struct DedupPlan<K> {
+    pub(crate) changes: Vec<(K, Option<Resource>)>,
+    pub(crate) merged: HashMap<K, u64>,
+    pub(crate) links: Vec<(K, K)>,
+}
+
+// A stored entry found to duplicate another: `similarity` is 1.0 for exact copies
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Duplicate<K> {
+    pub key: K,
+    pub original: K,
+    pub similarity: f64,
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    // Turns duplicate detection on every write on or off
+    pub fn set_dedup(&mut self, config: Option<DedupConfig>) {
+        self.dedup = config.map(|config| {
+            let mut index = DedupIndex::new(config);
+            for (key, resource) in self.resources.iter() {
+                index.insert(key.clone(), resource);
+            }
+            index
+        });
+    }
+
+    pub fn dedup_config(&self) -> Option<&DedupConfig> {
+        self.dedup.as_ref().map(|index| &index.config)
+    }
+
+    // The live entry `resource` duplicates most closely, with their similarity
+    pub fn find_duplicate(
+        &self,
+        resource: &Resource,
+        near_threshold: Option<f64>,
+    ) -> Option<(&K, f64)> {
+        let probe = Fingerprint::of(resource);
+        match &self.dedup {
+            Some(index) => index.best_match(&probe, resource, near_threshold, |key| self.get(key)),
+            None => self
+                .iter()
+                .filter_map(|(key, other)| {
+                    let print = Fingerprint::of(other);
+                    Some((
+                        key,
+                        similarity((&probe, resource), (&print, other), near_threshold)?,
+                    ))
+                })
+                .max_by(|a, b| a.1.total_cmp(&b.1)),
+        }
+    }
+
+    // `add` with dedup on: applies the configured policy to duplicates
+    pub(crate) fn add_deduplicated(
+        &mut self,
+        key: K,
+        resource: Resource,
+    ) -> Result<(), VaultError> {
+        let Some(config) = self.dedup_config().copied() else {
+            return self.add_with_meta(key, resource, EntryMeta::new());
+        };
+        if self.contains_key(&key) {
+            return self.add_with_meta(key, resource, EntryMeta::new());
+        }
+        let Some((original, _)) = self.find_duplicate(&resource, config.near_threshold) else {
+            return self.add_with_meta(key, resource, EntryMeta::new());
+        };
+        let original = original.clone();
+        match config.policy {
+            DedupPolicy::Reject => Err(VaultError::Duplicate {
+                key: key.to_string(),
+                existing: original.to_string(),
+            }),
+            DedupPolicy::Merge => self.edit_meta(&original, |meta| {
+                meta.duplicates += 1;
+                meta.touch();
+            }),
+            DedupPolicy::Link => {
+                self.add_with_meta(key.clone(), resource, EntryMeta::new())?;
+                self.link_with(&key, DUPLICATE_OF, &original, OnDelete::Nullify)
+            }
+        }
+    }
+
+    // `update` with dedup on. An update that turns an entry into a copy of
+    // another is refused, linked, or under `Merge` removed and counted on
+    // the other entry, as `dedupe` would later.
+    pub(crate) fn update_deduplicated(
+        &mut self,
+        key: &K,
+        resource: Resource,
+    ) -> Result<Resource, VaultError> {
+        let original = match &self.dedup {
+            Some(index) if self.contains_key(key) => {
+                let probe = Fingerprint::of(&resource);
+                index
+                    .best_match(&probe, &resource, index.config.near_threshold, |other| {
+                        (other != key).then(|| self.get(other)).flatten()
+                    })
+                    .map(|(original, _)| (original.clone(), index.config.policy))
+            }
+            _ => None,
+        };
+        let Some((original, policy)) = original else {
+            return self.overwrite(key, resource);
+        };
+        match policy {
+            DedupPolicy::Reject => Err(VaultError::Duplicate {
+                key: key.to_string(),
+                existing: original.to_string(),
+            }),
+            DedupPolicy::Merge => {
+                let old = self.remove(key)?;
+                self.edit_meta(&original, |meta| {
+                    meta.duplicates += 1;
+                    meta.touch();
+                })?;
+                Ok(old)
+            }
+            DedupPolicy::Link => {
+                let old = self.overwrite(key, resource)?;
+                self.link_with(key, DUPLICATE_OF, &original, OnDelete::Nullify)?;
+                Ok(old)
+            }
+        }
+    }
+
+    // Applies the dedup policy to a transaction's net changes, checking each
+    // written value against the entries the batch leaves alone and against
+    // the values written before it. `Reject` fails the whole batch; under
+    // `Merge` a new key is dropped and an updated one removed.
+    pub(crate) fn dedup_changes(
+        &self,
+        changes: Vec<(K, Option<Resource>)>,
+    ) -> Result<DedupPlan<K>, VaultError> {
+        let mut plan = DedupPlan {
+            changes: Vec::with_capacity(changes.len()),
+            merged: HashMap::new(),
+            links: Vec::new(),
+        };
+        let Some(index) = &self.dedup else {
+            plan.changes = changes;
+            return Ok(plan);
+        };
+        let DedupConfig {
+            policy,
+            near_threshold,
+        } = index.config;
+
+        let touched: HashSet<&K> = changes.iter().map(|(key, _)| key).collect();
+        let mut batch = DedupIndex::new(index.config);
+        let mut written: HashMap<&K, &Resource> = HashMap::new();
+        let mut originals = Vec::with_capacity(changes.len());
+        for (key, value) in &changes {
+            let Some(resource) = value else {
+                originals.push(None);
+                continue;
+            };
+            let probe = Fingerprint::of(resource);
+            let stored = index.best_match(&probe, resource, near_threshold, |other| {
+                (!touched.contains(other))
+                    .then(|| self.get(other))
+                    .flatten()
+            });
+            let staged = batch.best_match(&probe, resource, near_threshold, |other| {
+                written.get(other).copied()
+            });
+            let original = stored
+                .into_iter()
+                .chain(staged)
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(original, _)| original.clone());
+            if let (Some(original), DedupPolicy::Reject) = (&original, policy) {
+                return Err(VaultError::Duplicate {
+                    key: key.to_string(),
+                    existing: original.to_string(),
+                });
+            }
+            if original.is_none() || policy == DedupPolicy::Link {
+                batch.insert_print(key.clone(), probe);
+                written.insert(key, resource);
+            }
+            originals.push(original);
+        }
+
+        for ((key, value), original) in changes.into_iter().zip(originals) {
+            let Some(original) = original else {
+                plan.changes.push((key, value));
+                continue;
+            };
+            match policy {
+                DedupPolicy::Merge => {
+                    *plan.merged.entry(original).or_default() += 1;
+                    if self.contains_key(&key) {
+                        plan.changes.push((key, None));
+                    }
+                }
+                _ => {
+                    plan.links.push((key.clone(), original));
+                    plan.changes.push((key, value));
+                }
+            }
+        }
+        Ok(plan)
+    }
+
+    // Every entry that duplicates an older one, oldest entries winning ties
+    pub fn duplicates(&self, near_threshold: Option<f64>) -> Vec<Duplicate<K>> {
+        let mut entries: Vec<(&K, &Resource, u64)> = self
+            .iter()
+            .map(|(key, resource)| {
+                let created = self.meta.get(key).map_or(0, |meta| meta.created_at);
+                (key, resource, created)
+            })
+            .collect();
+        entries.sort_by(|a, b| {
+            a.2.cmp(&b.2)
+                .then_with(|| a.0.to_string().cmp(&b.0.to_string()))
+        });
+
+        let mut originals = DedupIndex::new(DedupConfig {
+            near_threshold,
+            ..Default::default()
+        });
+        let mut found = Vec::new();
+        for (key, resource, _) in entries {
+            let print = Fingerprint::of(resource);
+            let best = originals
+                .best_match(&print, resource, near_threshold, |other| self.get(other))
+                .map(|(original, similarity)| (original.clone(), similarity));
+            match best {
+                Some((original, similarity)) => found.push(Duplicate {
+                    key: key.clone(),
+                    original,
+                    similarity,
+                }),
+                None => originals.insert_print(key.clone(), print),
+            }
+        }
+        found
+    }
+
+    // Removes every duplicate found by `duplicates`, counting it on its
+    // original, and returns what was removed
+    pub fn dedupe(&mut self, near_threshold: Option<f64>) -> Result<Vec<Duplicate<K>>, VaultError> {
+        let found = self.duplicates(near_threshold);
+        for duplicate in &found {
+            self.remove(&duplicate.key)?;
+            self.edit_meta(&duplicate.original, |meta| meta.duplicates += 1)?;
+        }
+        Ok(found)
+    }
+}
@@ -0,0 +1,264 @@
+use crate::backend::StorageBackend;
+use crate::entry::EntryMeta;
+use crate::error::VaultError;
+use crate::resource::Resource;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::hash::Hash;
+
+// A single difference between two vaults
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Change<K> {
+    Added {
+        key: K,
+        resource: Resource,
+    },
+    Removed {
+        key: K,
+        resource: Resource,
+    },
+    Changed {
+        key: K,
+        old: Resource,
+        new: Resource,
+    },
+}
+
+impl<K> Change<K> {
+    pub fn key(&self) -> &K {
+        match self {
+            Change::Added { key, .. }
+            | Change::Removed { key, .. }
+            | Change::Changed { key, .. } => key,
+        }
+    }
+}
+
+// Changes that turn one vault into another. Serialized, this is also the patch format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultDiff<K> {
+    pub changes: Vec<Change<K>>,
+}
+
+impl<K> VaultDiff<K> {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    // Returns (added, removed, changed) counts
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let mut counts = (0, 0, 0);
+        for change in &self.changes {
+            match change {
+                Change::Added { .. } => counts.0 += 1,
+                Change::Removed { .. } => counts.1 += 1,
+                Change::Changed { .. } => counts.2 += 1,
+            }
+        }
+        counts
+    }
+}
+
+// A key both sides modified differently since the common base
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict<K> {
+    pub key: K,
+    pub base: Option<Resource>,
+    pub ours: Option<Resource>,
+    pub theirs: Option<Resource>,
+    pub ours_meta: Option<EntryMeta>,
+    pub theirs_meta: Option<EntryMeta>,
+}
+
+// Custom conflict handler; returning `None` deletes the key from the result
+pub type ConflictResolver<K> = Box<dyn Fn(&Conflict<K>) -> Option<Resource>>;
+
+// How `merge` settles a conflict
+pub enum ConflictResolution<K> {
+    Ours,
+    Theirs,
+    NewestWins,
+    Custom(ConflictResolver<K>),
+}
+
+pub struct MergeOutcome<K>
+where
+    K: Eq + Hash + Display,
+{
+    pub vault: Vault<K>,
+    pub conflicts: Vec<Conflict<K>>,
+}
+
+// Which input a merged entry was taken from
+enum Pick {
+    Ours,
+    Theirs,
+    Fresh(Option<Resource>),
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    // Lists what has to happen to `self` to make it look like `other`
+    pub fn diff<C: StorageBackend<K>>(&self, other: &Vault<K, C>) -> VaultDiff<K> {
+        let mut changes = Vec::new();
+
+        // Entries past their time-to-live count as gone, purged or not
+        for (key, resource) in self.iter() {
+            match other.get(key) {
+                None => changes.push(Change::Removed {
+                    key: key.clone(),
+                    resource: resource.clone(),
+                }),
+                Some(new) if new != resource => changes.push(Change::Changed {
+                    key: key.clone(),
+                    old: resource.clone(),
+                    new: new.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+
+        for (key, resource) in other.iter() {
+            if self.get(key).is_none() {
+                changes.push(Change::Added {
+                    key: key.clone(),
+                    resource: resource.clone(),
+                });
+            }
+        }
+
+        changes.sort_by_key(|change| change.key().to_string());
+        VaultDiff { changes }
+    }
+
+    // Applies a patch produced by `diff` as a single transaction. Every change
+    // is checked against the current contents first, so a patch either applies
+    // completely or not at all.
+    pub fn apply_patch(&mut self, patch: &VaultDiff<K>) -> Result<(), VaultError> {
+        self.transaction(|tx| {
+            for change in &patch.changes {
+                let expected = match change {
+                    Change::Added { .. } => None,
+                    Change::Removed { resource, .. } => Some(resource),
+                    Change::Changed { old, .. } => Some(old),
+                };
+                if tx.get(change.key()) != expected {
+                    return Err(VaultError::InvalidInput(format!(
+                        "Patch does not apply: key '{}' has diverged",
+                        change.key()
+                    )));
+                }
+                match change {
+                    Change::Added { key, resource } => tx.add(key.clone(), resource.clone())?,
+                    Change::Removed { key, .. } => {
+                        tx.remove(key)?;
+                    }
+                    Change::Changed { key, new, .. } => {
+                        tx.update(key, new.clone())?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+// Three-way merge of two vaults that diverged from `base`. Keys changed on only
+// one side take that side's value; keys changed on both sides are settled by
+// `resolution` and reported in the outcome. The result keeps the location and
+// capacity of `ours`, and is always held in memory.
+pub fn merge<K, B>(
+    base: &Vault<K, B>,
+    ours: &Vault<K, B>,
+    theirs: &Vault<K, B>,
+    resolution: &ConflictResolution<K>,
+) -> Result<MergeOutcome<K>, VaultError>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    let mut keys: Vec<&K> = base
+        .iter()
+        .chain(ours.iter())
+        .chain(theirs.iter())
+        .map(|(key, _)| key)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    keys.sort_by_key(|key| key.to_string());
+
+    let mut merged = Vault::empty(ours.location.clone(), ours.storage_capacity.clone());
+    let mut conflicts = Vec::new();
+
+    for key in keys {
+        let b = base.get(key);
+        let o = ours.get(key);
+        let t = theirs.get(key);
+
+        let pick = if o == t || t == b {
+            Pick::Ours
+        } else if o == b {
+            Pick::Theirs
+        } else {
+            let conflict = Conflict {
+                key: key.clone(),
+                base: b.cloned(),
+                ours: o.cloned(),
+                theirs: t.cloned(),
+                ours_meta: ours.meta.get(key).cloned(),
+                theirs_meta: theirs.meta.get(key).cloned(),
+            };
+            let pick = resolve(&conflict, resolution);
+            conflicts.push(conflict);
+            pick
+        };
+
+        let (resource, meta) = match pick {
+            Pick::Ours => (o.cloned(), ours.meta.get(key).cloned()),
+            Pick::Theirs => (t.cloned(), theirs.meta.get(key).cloned()),
+            Pick::Fresh(resource) => (resource, None),
+        };
+        if let Some(resource) = resource {
+            merged.insert_entry(key.clone(), resource, meta.unwrap_or_default())?;
+        }
+    }
+    // Edges from either side survive as long as both of their ends do
+    merged.restore_edges(ours.edges().chain(theirs.edges()).cloned().collect());
+
+    // The merged vault as a whole is what does not fit
+    let usage = merged.current_usage();
+    let capacity = merged.storage_capacity.size_bytes();
+    if usage > capacity {
+        return Err(VaultError::VaultFull {
+            capacity,
+            current: usage,
+            new_size: 0,
+        });
+    }
+
+    Ok(MergeOutcome {
+        vault: merged,
+        conflicts,
+    })
+}
+
+fn resolve<K>(conflict: &Conflict<K>, resolution: &ConflictResolution<K>) -> Pick {
+    match resolution {
+        ConflictResolution::Ours => Pick::Ours,
+        ConflictResolution::Theirs => Pick::Theirs,
+        // A deletion carries no timestamp, so a surviving edit always beats it
+        ConflictResolution::NewestWins => match (&conflict.ours_meta, &conflict.theirs_meta) {
+            (Some(o), Some(t)) if t.updated_at > o.updated_at => Pick::Theirs,
+            (None, Some(_)) => Pick::Theirs,
+            _ => Pick::Ours,
+        },
+        ConflictResolution::Custom(callback) => Pick::Fresh(callback(conflict)),
+    }
+}
@@ -0,0 +1,447 @@
+use crate::backend::{StorageBackend, CACHE_ENTRIES};
+use crate::error::VaultError;
+use crate::memory::MemorySize;
+use crate::resource::Resource;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hash;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+// Compaction kicks in once dead records outweigh live ones and exceed this size
+const COMPACTION_THRESHOLD: u64 = 1024 * 1024;
+
+// One line of the data log
+#[derive(Deserialize)]
+enum DiskRecord<K> {
+    Put { key: K, resource: Resource },
+    Delete { key: K },
+}
+
+// Borrowing twin of `DiskRecord` used when writing
+#[derive(Serialize)]
+enum DiskRecordRef<'a, K> {
+    Put { key: &'a K, resource: &'a Resource },
+    Delete { key: &'a K },
+}
+
+// Where the latest record for a key lives in the log
+struct Slot {
+    offset: u64,
+    len: u64,
+    size: u64,
+    // Filled the first time the resource is read; dropped again by `flush`,
+    // or by the next write once too many are held
+    cache: OnceLock<Resource>,
+}
+
+impl Slot {
+    fn new(offset: u64, len: u64, size: u64) -> Self {
+        Self {
+            offset,
+            len,
+            size,
+            cache: OnceLock::new(),
+        }
+    }
+}
+
+// Log-structured backend: every change is appended to a data file as a JSON
+// line, and only an index of key -> file offset stays in RAM. Resources are
+// read from disk the first time they are needed and held while borrowed
+// from the backend; once more than `CACHE_ENTRIES` are, the next write lets
+// go of them. Superseded records are reclaimed by `compact`, which also runs
+// on its own once they pile up.
+pub struct DiskBackend<K> {
+    path: PathBuf,
+    // Behind a mutex so reads through `&self` can seek
+    file: Mutex<File>,
+    index: HashMap<K, Slot>,
+    end: u64,
+    live_bytes: u64,
+    dead_bytes: u64,
+    cached: AtomicUsize,
+    // Automatic compaction waits for this many dead bytes after a failure
+    compact_at: u64,
+    // A failed read or automatic compaction, reported by the next `flush`.
+    // Neither can fail the call it happened in: a read hands out no error,
+    // and a write is already durable when compaction runs.
+    deferred: Mutex<Option<VaultError>>,
+}
+
+impl<K> DiskBackend<K>
+where
+    K: Eq + Hash + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+{
+    // Opens (or creates) the data log at `path` and rebuilds the index from it.
+    // A torn record at the end, left by a crash mid-write, is cut off; a
+    // corrupt record anywhere else is an error, so nothing after it is lost.
+    pub fn open(path: &str) -> Result<Self, VaultError> {
+        let path = PathBuf::from(path);
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+
+        let mut backend = Self {
+            path,
+            file: Mutex::new(file.try_clone()?),
+            index: HashMap::new(),
+            end: 0,
+            live_bytes: 0,
+            dead_bytes: 0,
+            cached: AtomicUsize::new(0),
+            compact_at: COMPACTION_THRESHOLD,
+            deferred: Mutex::new(None),
+        };
+
+        let mut reader = BufReader::new(file);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)? as u64;
+            if read == 0 {
+                break;
+            }
+            // Only the last line can lack its newline
+            if line.last() != Some(&b'\n') {
+                backend.lock_file()?.set_len(backend.end)?;
+                break;
+            }
+            let record = serde_json::from_slice::<DiskRecord<K>>(&line).map_err(|e| {
+                VaultError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("corrupt record at offset {}: {}", backend.end, e),
+                ))
+            })?;
+            match record {
+                DiskRecord::Put { key, resource } => {
+                    let slot = Slot::new(backend.end, read, resource.size_bytes());
+                    backend.live_bytes += read;
+                    if let Some(old) = backend.index.insert(key, slot) {
+                        backend.live_bytes -= old.len;
+                        backend.dead_bytes += old.len;
+                    }
+                }
+                DiskRecord::Delete { key } => {
+                    backend.dead_bytes += read;
+                    if let Some(old) = backend.index.remove(&key) {
+                        backend.live_bytes -= old.len;
+                        backend.dead_bytes += old.len;
+                    }
+                }
+            }
+            backend.end += read;
+        }
+        Ok(backend)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Bytes taken up by superseded and deleted records
+    pub fn dead_bytes(&self) -> u64 {
+        self.dead_bytes
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.end
+    }
+
+    // Resources currently paged in
+    pub fn cached_entries(&self) -> usize {
+        self.cached.load(Ordering::Relaxed)
+    }
+
+    fn lock_file(&self) -> Result<std::sync::MutexGuard<'_, File>, VaultError> {
+        self.file
+            .lock()
+            .map_err(|_| VaultError::IoError(std::io::Error::other("data file lock poisoned")))
+    }
+
+    fn read_raw(&self, offset: u64, len: u64) -> Result<Vec<u8>, VaultError> {
+        let mut file = self.lock_file()?;
+        let mut buf = vec![0; len as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_slot(&self, slot: &Slot) -> Result<Resource, VaultError> {
+        let buf = self.read_raw(slot.offset, slot.len)?;
+        match serde_json::from_slice::<DiskRecord<K>>(&buf) {
+            Ok(DiskRecord::Put { resource, .. }) => Ok(resource),
+            _ => Err(VaultError::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("corrupt record at offset {}", slot.offset),
+            ))),
+        }
+    }
+
+    // Pages a resource in. A read failure leaves the entry out and is
+    // reported by the next `flush`.
+    fn load<'a>(&'a self, slot: &'a Slot) -> Option<&'a Resource> {
+        if let Some(resource) = slot.cache.get() {
+            return Some(resource);
+        }
+        match self.read_slot(slot) {
+            Ok(resource) => {
+                if slot.cache.set(resource).is_ok() {
+                    self.cached.fetch_add(1, Ordering::Relaxed);
+                }
+                slot.cache.get()
+            }
+            Err(e) => {
+                self.defer(e);
+                None
+            }
+        }
+    }
+
+    fn defer(&self, error: VaultError) {
+        if let Ok(mut deferred) = self.deferred.lock() {
+            deferred.get_or_insert(error);
+        }
+    }
+
+    // The latest resource for `slot`, without keeping it paged in
+    fn read_old(&self, slot: &Slot) -> Result<Resource, VaultError> {
+        match slot.cache.get() {
+            Some(cached) => Ok(cached.clone()),
+            None => self.read_slot(slot),
+        }
+    }
+
+    fn release_cache(&mut self) {
+        for slot in self.index.values_mut() {
+            slot.cache = OnceLock::new();
+        }
+        *self.cached.get_mut() = 0;
+    }
+
+    fn append(&mut self, record: &DiskRecordRef<'_, K>) -> Result<(u64, u64), VaultError> {
+        let mut line = serde_json::to_vec(record).map_err(|e| {
+            VaultError::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                e.to_string(),
+            ))
+        })?;
+        line.push(b'\n');
+        let offset = self.end;
+        let file = self
+            .file
+            .get_mut()
+            .map_err(|_| VaultError::IoError(std::io::Error::other("data file lock poisoned")))?;
+        file.write_all(&line)?;
+        self.end += line.len() as u64;
+        Ok((offset, line.len() as u64))
+    }
+
+    // Rewrites the log with only the latest record of each live key
+    pub fn compact(&mut self) -> Result<(), VaultError> {
+        let tmp_path = self.path.with_extension("compact");
+        let mut tmp = File::create(&tmp_path)?;
+        let mut offset = 0;
+        let mut moved = Vec::with_capacity(self.index.len());
+        for (key, slot) in &self.index {
+            let raw = self.read_raw(slot.offset, slot.len)?;
+            tmp.write_all(&raw)?;
+            moved.push((key.clone(), offset));
+            offset += slot.len;
+        }
+        tmp.sync_all()?;
+        drop(tmp);
+        fs::rename(&tmp_path, &self.path)?;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        *self.lock_file()? = file;
+        for (key, new_offset) in moved {
+            if let Some(slot) = self.index.get_mut(&key) {
+                slot.offset = new_offset;
+            }
+        }
+        self.end = offset;
+        self.dead_bytes = 0;
+        Ok(())
+    }
+
+    // Runs after a write has been appended, so a failure must not undo it.
+    // It is kept for `flush` to report, and compaction is tried again once
+    // the dead bytes have doubled.
+    fn maybe_compact(&mut self) {
+        if self.dead_bytes > self.compact_at && self.dead_bytes > self.live_bytes {
+            match self.compact() {
+                Ok(()) => self.compact_at = COMPACTION_THRESHOLD,
+                Err(e) => {
+                    self.compact_at = self.dead_bytes.saturating_mul(2);
+                    self.defer(e);
+                }
+            }
+        }
+    }
+
+    fn meta_path(&self) -> PathBuf {
+        self.path.with_extension("meta.json")
+    }
+
+    fn edges_path(&self) -> PathBuf {
+        self.path.with_extension("edges.json")
+    }
+}
+
+impl<K> StorageBackend<K> for DiskBackend<K>
+where
+    K: Eq + Hash + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+{
+    fn get(&self, key: &K) -> Option<&Resource> {
+        self.load(self.index.get(key)?)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.index.contains_key(key)
+    }
+
+    fn insert(&mut self, key: K, resource: Resource) -> Result<Option<Resource>, VaultError> {
+        if *self.cached.get_mut() > CACHE_ENTRIES {
+            self.release_cache();
+        }
+        let old = match self.index.get(&key) {
+            Some(slot) => Some(self.read_old(slot)?),
+            None => None,
+        };
+        let (offset, len) = self.append(&DiskRecordRef::Put {
+            key: &key,
+            resource: &resource,
+        })?;
+        self.live_bytes += len;
+        if let Some(previous) = self
+            .index
+            .insert(key, Slot::new(offset, len, resource.size_bytes()))
+        {
+            self.live_bytes -= previous.len;
+            self.dead_bytes += previous.len;
+            if previous.cache.get().is_some() {
+                *self.cached.get_mut() -= 1;
+            }
+        }
+        self.maybe_compact();
+        Ok(old)
+    }
+
+    fn remove(&mut self, key: &K) -> Result<Option<Resource>, VaultError> {
+        if *self.cached.get_mut() > CACHE_ENTRIES {
+            self.release_cache();
+        }
+        let old = match self.index.get(key) {
+            Some(slot) => self.read_old(slot)?,
+            None => return Ok(None),
+        };
+        let (_, len) = self.append(&DiskRecordRef::Delete { key })?;
+        self.dead_bytes += len;
+        if let Some(slot) = self.index.remove(key) {
+            self.live_bytes -= slot.len;
+            self.dead_bytes += slot.len;
+            if slot.cache.get().is_some() {
+                *self.cached.get_mut() -= 1;
+            }
+        }
+        self.maybe_compact();
+        Ok(Some(old))
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_> {
+        Box::new(self.index.keys())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &Resource)> + '_> {
+        Box::new(
+            self.index
+                .iter()
+                .filter_map(|(key, slot)| self.load(slot).map(|res| (key, res))),
+        )
+    }
+
+    fn size_of(&self, key: &K) -> Option<u64> {
+        self.index.get(key).map(|slot| slot.size)
+    }
+
+    // Syncs the data file and lets go of every paged-in resource, then
+    // reports a read or compaction that failed since the last flush
+    fn flush(&mut self) -> Result<(), VaultError> {
+        self.lock_file()?.sync_data()?;
+        self.release_cache();
+        match self.deferred.get_mut() {
+            Ok(deferred) => deferred.take().map_or(Ok(()), Err),
+            Err(_) => Err(VaultError::IoError(std::io::Error::other(
+                "deferred error lock poisoned",
+            ))),
+        }
+    }
+
+    fn load_meta(&self) -> Result<Option<Vec<u8>>, VaultError> {
+        let path = self.meta_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read(path)?))
+    }
+
+    fn store_meta(&mut self, meta: &[u8]) -> Result<(), VaultError> {
+        fs::write(self.meta_path(), meta)?;
+        Ok(())
+    }
+
+    fn load_edges(&self) -> Result<Option<Vec<u8>>, VaultError> {
+        let path = self.edges_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read(path)?))
+    }
+
+    fn store_edges(&mut self, edges: &[u8]) -> Result<(), VaultError> {
+        fs::write(self.edges_path(), edges)?;
+        Ok(())
+    }
+}
+
+impl<K> Vault<K, DiskBackend<K>>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+{
+    // Opens a vault whose entries live in the data log at `path`. Call `flush`
+    // to make changes durable and to keep entry metadata across reopens.
+    pub fn open_disk(
+        path: &str,
+        location: String,
+        capacity: MemorySize,
+    ) -> Result<Self, VaultError> {
+        Self::with_backend(location, capacity, DiskBackend::open(path)?)
+    }
+
+    // Reclaims the space of superseded records right away
+    pub fn compact(&mut self) -> Result<(), VaultError> {
+        self.resources.compact()
+    }
+
+    pub fn disk(&self) -> &DiskBackend<K> {
+        &self.resources
+    }
+}
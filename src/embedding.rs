@@ -0,0 +1,180 @@
+use crate::backend::StorageBackend;
+use crate::packed::fnv1a;
+use crate::resource::Resource;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+
+// Turns text into a fixed-length vector. Implement this to plug in a real
+// model; vectors are compared by cosine similarity, so their scale is free.
+pub trait Embedder: Send + Sync {
+    fn dimensions(&self) -> usize;
+
+    fn embed(&self, text: &str) -> Vec<f32>;
+}
+
+// Offline default: words and character n-grams hashed into a fixed number of
+// buckets ("feature hashing"), with a random sign per feature to even out
+// collisions. Texts sharing words and word fragments end up close together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashedEmbedder {
+    pub dimensions: usize,
+    // Length of the character n-grams taken from each word
+    pub ngram: usize,
+}
+
+impl Default for HashedEmbedder {
+    fn default() -> Self {
+        Self {
+            dimensions: 256,
+            ngram: 3,
+        }
+    }
+}
+
+impl HashedEmbedder {
+    fn add_feature(&self, vector: &mut [f32], feature: &str, weight: f32) {
+        let hash = fnv1a(feature.as_bytes());
+        let bucket = (hash % vector.len() as u64) as usize;
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        vector[bucket] += sign * weight;
+    }
+}
+
+impl Embedder for HashedEmbedder {
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimensions.max(1)];
+        for word in text.split_whitespace() {
+            let word: String = word
+                .chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect();
+            if word.is_empty() {
+                continue;
+            }
+            self.add_feature(&mut vector, &word, 1.0);
+            // Padded so prefixes and suffixes count as their own fragments
+            let padded: Vec<char> = format!("<{}>", word).chars().collect();
+            for gram in padded.windows(self.ngram.max(1)) {
+                self.add_feature(&mut vector, &gram.iter().collect::<String>(), 0.5);
+            }
+        }
+        normalize(&mut vector);
+        vector
+    }
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+// Cosine similarity in [-1, 1]; 0 when either vector is all zeros
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norms =
+        a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+// The text of resources that get embedded; sensor readings do not
+fn embeddable(resource: &Resource) -> Option<String> {
+    match resource {
+        Resource::TextMessage(_) | Resource::SystemLogs(_) => Some(resource.to_text()),
+        Resource::SensorData(_) => None,
+    }
+}
+
+// Vectors of every text entry, kept in step by the vault's entry helpers
+pub(crate) struct Embeddings<K> {
+    embedder: Box<dyn Embedder>,
+    vectors: HashMap<K, Vec<f32>>,
+}
+
+impl<K: Eq + Hash> Embeddings<K> {
+    pub(crate) fn insert(&mut self, key: K, resource: &Resource) {
+        match embeddable(resource) {
+            Some(text) => {
+                self.vectors.insert(key, self.embedder.embed(&text));
+            }
+            None => {
+                self.vectors.remove(&key);
+            }
+        }
+    }
+
+    pub(crate) fn remove(&mut self, key: &K) {
+        self.vectors.remove(key);
+    }
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    // Embeds every text entry now, and each one added or updated from here on
+    pub fn set_embedder<E: Embedder + 'static>(&mut self, embedder: E) {
+        let mut embeddings = Embeddings {
+            embedder: Box::new(embedder),
+            vectors: HashMap::new(),
+        };
+        for (key, resource) in self.resources.iter() {
+            embeddings.insert(key.clone(), resource);
+        }
+        self.embeddings = Some(embeddings);
+    }
+
+    // Stops embedding and drops the stored vectors
+    pub fn clear_embedder(&mut self) {
+        self.embeddings = None;
+    }
+
+    pub fn has_embedder(&self) -> bool {
+        self.embeddings.is_some()
+    }
+
+    pub fn embedding(&self, key: &K) -> Option<&[f32]> {
+        self.get(key)?;
+        Some(self.embeddings.as_ref()?.vectors.get(key)?.as_slice())
+    }
+
+    // Embeds `text` with the vault's embedder
+    pub fn embed(&self, text: &str) -> Option<Vec<f32>> {
+        Some(self.embeddings.as_ref()?.embedder.embed(text))
+    }
+
+    // The `n` live entries whose embeddings are most similar to `query`'s, best first
+    pub fn nearest(&self, query: &str, n: usize) -> Vec<(&K, f32)> {
+        let Some(embeddings) = &self.embeddings else {
+            return Vec::new();
+        };
+        let probe = embeddings.embedder.embed(query);
+        let mut scored: Vec<(&K, f32)> = embeddings
+            .vectors
+            .iter()
+            .filter(|(key, _)| self.get(key).is_some())
+            .map(|(key, vector)| (key, cosine_similarity(&probe, vector)))
+            .collect();
+        scored.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then_with(|| a.0.to_string().cmp(&b.0.to_string()))
+        });
+        scored.truncate(n);
+        scored
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Per-entry bookkeeping kept alongside each resource in a vault
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntryMeta {
+    pub created_at: u64, // milliseconds since the Unix epoch
+    pub updated_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    // How much the entry matters, from 0.0 to 1.0
+    #[serde(default = "default_importance")]
+    pub importance: f64,
+    // Zero in files written before access tracking; read as `updated_at`
+    #[serde(default)]
+    pub last_accessed: u64,
+    #[serde(default)]
+    pub access_count: u64,
+    // Copies merged into this entry by deduplication
+    #[serde(default)]
+    pub duplicates: u64,
+}
+
+const DEFAULT_IMPORTANCE: f64 = 0.5;
+
+fn default_importance() -> f64 {
+    DEFAULT_IMPORTANCE
+}
+
+impl EntryMeta {
+    pub fn new() -> Self {
+        let now = now_millis();
+        Self {
+            created_at: now,
+            updated_at: now,
+            expires_at: None,
+            importance: DEFAULT_IMPORTANCE,
+            last_accessed: now,
+            access_count: 0,
+            duplicates: 0,
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|deadline| deadline <= now)
+    }
+
+    pub fn touch(&mut self) {
+        self.updated_at = now_millis();
+    }
+
+    // Records a recall of the entry
+    pub fn record_access(&mut self) {
+        self.last_accessed = now_millis();
+        self.access_count += 1;
+    }
+
+    pub fn last_used(&self) -> u64 {
+        self.last_accessed.max(self.updated_at)
+    }
+}
+
+impl Default for EntryMeta {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Current wall-clock time in milliseconds since the Unix epoch
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -11,13 +11,45 @@ pub enum VaultError {
     },
     ResourceNotFound(String),
     InvalidInput(String),
+    // `key` holds the same content as the existing entry `existing`
+    Duplicate {
+        key: String,
+        existing: String,
+    },
+    // `principal` lacks `permission` on `key`; an empty key means the whole vault
+    PermissionDenied {
+        principal: String,
+        permission: String,
+        key: String,
+    },
+    // Adding `requested` to the `used` bytes or entries of `quota` would pass `limit`
+    QuotaExceeded {
+        quota: String,
+        limit: u64,
+        used: u64,
+        requested: u64,
+    },
     #[serde(skip)]
     IoError(std::io::Error),
 }
 
-impl fmt::Display for VaultError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let msg = match self {
+impl VaultError {
+    // Stable short name of the variant, for machine-readable error replies
+    pub fn kind(&self) -> &'static str {
+        match self {
+            VaultError::VaultFull { .. } => "vault_full",
+            VaultError::ResourceNotFound(_) => "not_found",
+            VaultError::InvalidInput(_) => "invalid_input",
+            VaultError::Duplicate { .. } => "duplicate",
+            VaultError::PermissionDenied { .. } => "permission_denied",
+            VaultError::QuotaExceeded { .. } => "quota_exceeded",
+            VaultError::IoError(_) => "io",
+        }
+    }
+
+    // The error text without terminal colors
+    pub fn message(&self) -> String {
+        match self {
             VaultError::VaultFull {
                 capacity,
                 current,
@@ -28,9 +60,42 @@ impl fmt::Display for VaultError {
             ),
             VaultError::ResourceNotFound(key) => format!("Resource '{}' not found", key),
             VaultError::InvalidInput(msg) => format!("Input error: {}", msg),
+            VaultError::Duplicate { key, existing } => {
+                format!("'{}' duplicates existing entry '{}'", key, existing)
+            }
+            VaultError::PermissionDenied {
+                principal,
+                permission,
+                key,
+            } if key.is_empty() => format!(
+                "'{}' lacks {} permission on the vault",
+                principal, permission
+            ),
+            VaultError::PermissionDenied {
+                principal,
+                permission,
+                key,
+            } => format!(
+                "'{}' lacks {} permission on '{}'",
+                principal, permission, key
+            ),
+            VaultError::QuotaExceeded {
+                quota,
+                limit,
+                used,
+                requested,
+            } => format!(
+                "Quota {} exceeded! Limit: {}, Used: {}, Requested: {}",
+                quota, limit, used, requested
+            ),
             VaultError::IoError(e) => format!("I/O error: {}", e),
-        };
-        write!(f, "{}", msg.red())
+        }
+    }
+}
+
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message().red())
     }
 }
 
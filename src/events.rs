@@ -0,0 +1,220 @@
+use crate::resource::{Resource, ResourceKind};
+use std::fmt::Display;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+// A single change to a vault, delivered to subscribers after it has been applied
+#[derive(Debug, Clone, PartialEq)]
+pub enum VaultEvent<K> {
+    Added {
+        key: K,
+        resource: Resource,
+    },
+    Updated {
+        key: K,
+        old: Resource,
+        new: Resource,
+    },
+    Removed {
+        key: K,
+        resource: Resource,
+    },
+    // Dropped by the vault itself to make room for new entries
+    Evicted {
+        key: K,
+        resource: Resource,
+    },
+    // Dropped because its time-to-live ran out
+    Expired {
+        key: K,
+        resource: Resource,
+    },
+}
+
+impl<K> VaultEvent<K> {
+    pub fn key(&self) -> &K {
+        match self {
+            VaultEvent::Added { key, .. }
+            | VaultEvent::Updated { key, .. }
+            | VaultEvent::Removed { key, .. }
+            | VaultEvent::Evicted { key, .. }
+            | VaultEvent::Expired { key, .. } => key,
+        }
+    }
+
+    // The resource before the change, if there was one
+    pub fn old_resource(&self) -> Option<&Resource> {
+        match self {
+            VaultEvent::Added { .. } => None,
+            VaultEvent::Updated { old, .. } => Some(old),
+            VaultEvent::Removed { resource, .. }
+            | VaultEvent::Evicted { resource, .. }
+            | VaultEvent::Expired { resource, .. } => Some(resource),
+        }
+    }
+
+    // The resource after the change, if it still exists
+    pub fn new_resource(&self) -> Option<&Resource> {
+        match self {
+            VaultEvent::Added { resource, .. } => Some(resource),
+            VaultEvent::Updated { new, .. } => Some(new),
+            _ => None,
+        }
+    }
+}
+
+// Restricts which events a subscriber receives. An empty filter matches everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventFilter {
+    pub key_prefix: Option<String>,
+    pub kinds: Vec<ResourceKind>,
+}
+
+impl EventFilter {
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn prefix(prefix: &str) -> Self {
+        Self {
+            key_prefix: Some(prefix.to_string()),
+            kinds: Vec::new(),
+        }
+    }
+
+    pub fn kind(mut self, kind: ResourceKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    pub fn matches<K: Display>(&self, event: &VaultEvent<K>) -> bool {
+        if let Some(prefix) = &self.key_prefix {
+            if !event.key().to_string().starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+        self.kinds.is_empty()
+            || [event.old_resource(), event.new_resource()]
+                .into_iter()
+                .flatten()
+                .any(|res| self.kinds.contains(&res.kind()))
+    }
+}
+
+// Why an entry left the vault; selects the event variant that is emitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Removal {
+    Removed,
+    Evicted,
+    Expired,
+}
+
+impl<K> VaultEvent<K> {
+    pub(crate) fn removal(cause: Removal, key: K, resource: Resource) -> Self {
+        match cause {
+            Removal::Removed => VaultEvent::Removed { key, resource },
+            Removal::Evicted => VaultEvent::Evicted { key, resource },
+            Removal::Expired => VaultEvent::Expired { key, resource },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+pub type EventCallback<K> = Box<dyn FnMut(&VaultEvent<K>) + Send>;
+
+enum Sink<K> {
+    Callback(EventCallback<K>),
+    Channel(Sender<VaultEvent<K>>),
+}
+
+struct Subscription<K> {
+    id: SubscriptionId,
+    filter: EventFilter,
+    sink: Sink<K>,
+}
+
+// The set of subscribers attached to one vault
+pub(crate) struct Observers<K> {
+    next_id: u64,
+    subscriptions: Vec<Subscription<K>>,
+    // Events queued while a batch is being applied
+    held: Option<Vec<VaultEvent<K>>>,
+}
+
+impl<K: Display + Clone> Observers<K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_id: 0,
+            subscriptions: Vec::new(),
+            held: None,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+
+    fn register(&mut self, filter: EventFilter, sink: Sink<K>) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscriptions.push(Subscription { id, filter, sink });
+        id
+    }
+
+    pub(crate) fn subscribe(
+        &mut self,
+        filter: EventFilter,
+        callback: EventCallback<K>,
+    ) -> SubscriptionId {
+        self.register(filter, Sink::Callback(callback))
+    }
+
+    pub(crate) fn channel(&mut self, filter: EventFilter) -> Receiver<VaultEvent<K>> {
+        let (sender, receiver) = mpsc::channel();
+        self.register(filter, Sink::Channel(sender));
+        receiver
+    }
+
+    pub(crate) fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let before = self.subscriptions.len();
+        self.subscriptions.retain(|sub| sub.id != id);
+        self.subscriptions.len() != before
+    }
+
+    pub(crate) fn hold(&mut self) {
+        self.held.get_or_insert_with(Vec::new);
+    }
+
+    pub(crate) fn release(&mut self) {
+        for event in self.held.take().unwrap_or_default() {
+            self.emit(event);
+        }
+    }
+
+    // Stops holding and drops what was held, for a batch that was undone
+    pub(crate) fn discard(&mut self) {
+        self.held = None;
+    }
+
+    // Delivers an event to every matching subscriber, dropping channels whose
+    // receiver has gone away
+    pub(crate) fn emit(&mut self, event: VaultEvent<K>) {
+        if let Some(held) = &mut self.held {
+            held.push(event);
+            return;
+        }
+        self.subscriptions.retain_mut(|sub| {
+            if !sub.filter.matches(&event) {
+                return true;
+            }
+            match &mut sub.sink {
+                Sink::Callback(callback) => {
+                    callback(&event);
+                    true
+                }
+                Sink::Channel(sender) => sender.send(event.clone()).is_ok(),
+            }
+        });
+    }
+}
@@ -0,0 +1,316 @@
+use crate::backend::StorageBackend;
+use crate::error::VaultError;
+use crate::journal::JournalOp;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::hash::Hash;
+
+// What happens to an edge when the entry it points to is removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum OnDelete {
+    // The target cannot be removed while the edge exists
+    Restrict,
+    // The entry the edge starts from is removed as well
+    Cascade,
+    // Only the edge goes away
+    #[default]
+    Nullify,
+}
+
+// A typed, directed relation between two entries, e.g. alice -works_at-> acme
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Edge<K> {
+    pub from: K,
+    pub relation: String,
+    pub to: K,
+    #[serde(default)]
+    pub on_delete: OnDelete,
+}
+
+impl<K: PartialEq> Edge<K> {
+    fn same_link(&self, from: &K, relation: &str, to: &K) -> bool {
+        self.from == *from && self.relation == relation && self.to == *to
+    }
+}
+
+// Edges indexed from both ends so removals can find what points at a key
+pub(crate) struct Graph<K> {
+    outgoing: HashMap<K, Vec<Edge<K>>>,
+    incoming: HashMap<K, Vec<Edge<K>>>,
+}
+
+impl<K: Eq + Hash + Clone> Graph<K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            outgoing: HashMap::new(),
+            incoming: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn edges(&self) -> impl Iterator<Item = &Edge<K>> {
+        self.outgoing.values().flatten()
+    }
+
+    pub(crate) fn outgoing(&self, key: &K) -> &[Edge<K>] {
+        self.outgoing.get(key).map_or(&[], Vec::as_slice)
+    }
+
+    pub(crate) fn incoming(&self, key: &K) -> &[Edge<K>] {
+        self.incoming.get(key).map_or(&[], Vec::as_slice)
+    }
+
+    // Adds an edge, or updates the delete behaviour of an existing one
+    pub(crate) fn insert(&mut self, edge: Edge<K>) {
+        self.remove(&edge.from, &edge.relation, &edge.to);
+        self.incoming
+            .entry(edge.to.clone())
+            .or_default()
+            .push(edge.clone());
+        self.outgoing
+            .entry(edge.from.clone())
+            .or_default()
+            .push(edge);
+    }
+
+    pub(crate) fn remove(&mut self, from: &K, relation: &str, to: &K) -> bool {
+        let Some(out) = self.outgoing.get_mut(from) else {
+            return false;
+        };
+        let before = out.len();
+        out.retain(|edge| !edge.same_link(from, relation, to));
+        if out.len() == before {
+            return false;
+        }
+        if out.is_empty() {
+            self.outgoing.remove(from);
+        }
+        if let Some(inc) = self.incoming.get_mut(to) {
+            inc.retain(|edge| !edge.same_link(from, relation, to));
+            if inc.is_empty() {
+                self.incoming.remove(to);
+            }
+        }
+        true
+    }
+
+    // The first edge that forbids removing `key`, ignoring edges from entries
+    // in `leaving`, which are being removed along with it
+    pub(crate) fn restricting(&self, key: &K, leaving: &HashSet<&K>) -> Option<&Edge<K>> {
+        self.incoming(key).iter().find(|edge| {
+            edge.on_delete == OnDelete::Restrict
+                && edge.from != *key
+                && !leaving.contains(&edge.from)
+        })
+    }
+
+    // Drops every edge touching `key` and returns the entries that have to go
+    // with it because of cascading edges
+    pub(crate) fn detach(&mut self, key: &K) -> Vec<K> {
+        for edge in self.outgoing.remove(key).unwrap_or_default() {
+            if let Some(inc) = self.incoming.get_mut(&edge.to) {
+                inc.retain(|other| other.from != *key);
+                if inc.is_empty() {
+                    self.incoming.remove(&edge.to);
+                }
+            }
+        }
+        let mut dependents = Vec::new();
+        for edge in self.incoming.remove(key).unwrap_or_default() {
+            if let Some(out) = self.outgoing.get_mut(&edge.from) {
+                out.retain(|other| other.to != *key);
+                if out.is_empty() {
+                    self.outgoing.remove(&edge.from);
+                }
+            }
+            if edge.on_delete == OnDelete::Cascade {
+                dependents.push(edge.from);
+            }
+        }
+        dependents
+    }
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    // Links two existing entries; removing `to` later just drops the edge
+    pub fn link(&mut self, from: &K, relation: &str, to: &K) -> Result<(), VaultError> {
+        self.link_with(from, relation, to, OnDelete::Nullify)
+    }
+
+    pub fn link_with(
+        &mut self,
+        from: &K,
+        relation: &str,
+        to: &K,
+        on_delete: OnDelete,
+    ) -> Result<(), VaultError> {
+        for key in [from, to] {
+            if !self.contains_key(key) {
+                return Err(VaultError::ResourceNotFound(key.to_string()));
+            }
+        }
+        if relation.trim().is_empty() {
+            return Err(VaultError::InvalidInput(
+                "Relation must not be empty".to_string(),
+            ));
+        }
+        let edge = Edge {
+            from: from.clone(),
+            relation: relation.to_string(),
+            to: to.clone(),
+            on_delete,
+        };
+        self.log(&[JournalOp::Link { edge: edge.clone() }])?;
+        self.graph.insert(edge);
+        Ok(())
+    }
+
+    // Returns whether the edge existed
+    pub fn unlink(&mut self, from: &K, relation: &str, to: &K) -> Result<bool, VaultError> {
+        if !self
+            .graph
+            .outgoing(from)
+            .iter()
+            .any(|e| e.same_link(from, relation, to))
+        {
+            return Ok(false);
+        }
+        self.log(&[JournalOp::Unlink {
+            from: from.clone(),
+            relation: relation.to_string(),
+            to: to.clone(),
+        }])?;
+        Ok(self.graph.remove(from, relation, to))
+    }
+
+    // Edges starting at `key`
+    pub fn neighbors(&self, key: &K) -> &[Edge<K>] {
+        self.graph.outgoing(key)
+    }
+
+    // Edges pointing at `key`
+    pub fn referrers(&self, key: &K) -> &[Edge<K>] {
+        self.graph.incoming(key)
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = &Edge<K>> {
+        self.graph.edges()
+    }
+
+    // Every entry `remove(key)` would take out: the key itself and whatever
+    // depends on it through cascading edges, transitively
+    pub fn removal_set(&self, key: &K) -> Vec<&K> {
+        let mut seen: HashSet<&K> = HashSet::new();
+        let mut pending = vec![key];
+        let mut removed = Vec::new();
+        while let Some(next) = pending.pop() {
+            let Some((stored, _)) = self.meta.get_key_value(next) else {
+                continue;
+            };
+            if !seen.insert(stored) {
+                continue;
+            }
+            removed.push(stored);
+            pending.extend(
+                self.graph
+                    .incoming(next)
+                    .iter()
+                    .filter(|edge| edge.on_delete == OnDelete::Cascade)
+                    .map(|edge| &edge.from),
+            );
+        }
+        removed
+    }
+
+    // Entries reachable from `start` along outgoing edges within `max_depth`
+    // hops, with their distance, nearest first
+    pub fn traverse(&self, start: &K, max_depth: usize) -> Vec<(&K, usize)> {
+        let mut seen: HashSet<&K> = HashSet::from([start]);
+        let mut queue = VecDeque::from([(start, 0)]);
+        let mut reached = Vec::new();
+        while let Some((key, depth)) = queue.pop_front() {
+            if depth == max_depth {
+                continue;
+            }
+            for edge in self.graph.outgoing(key) {
+                if seen.insert(&edge.to) {
+                    reached.push((&edge.to, depth + 1));
+                    queue.push_back((&edge.to, depth + 1));
+                }
+            }
+        }
+        reached
+    }
+
+    // Shortest chain of edges leading from `from` to `to`
+    pub fn find_path(&self, from: &K, to: &K) -> Option<Vec<&Edge<K>>> {
+        if from == to {
+            return Some(Vec::new());
+        }
+        let mut via: HashMap<&K, &Edge<K>> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(key) = queue.pop_front() {
+            for edge in self.graph.outgoing(key) {
+                if edge.to == *from || via.contains_key(&edge.to) {
+                    continue;
+                }
+                via.insert(&edge.to, edge);
+                if edge.to == *to {
+                    let mut path = vec![edge];
+                    let mut at = &edge.from;
+                    while at != from {
+                        let step = via[at];
+                        path.push(step);
+                        at = &step.from;
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                queue.push_back(&edge.to);
+            }
+        }
+        None
+    }
+
+    // Fails if removing `key` would break a restricting edge: one pointing at
+    // it, or at anything its cascading edges take along, from an entry that
+    // stays. Entries in `leaving` are being removed as well.
+    pub(crate) fn check_removal<'a>(
+        &'a self,
+        key: &'a K,
+        leaving: &HashSet<&'a K>,
+    ) -> Result<(), VaultError> {
+        let removed = self.removal_set(key);
+        let mut gone = leaving.clone();
+        gone.extend(removed.iter().copied());
+        for member in removed {
+            self.check_restrict(member, &gone)?;
+        }
+        Ok(())
+    }
+
+    // Everything removing each of `keys` takes along, the keys included
+    pub(crate) fn removal_closure<'a>(
+        &'a self,
+        keys: impl Iterator<Item = &'a K>,
+    ) -> HashSet<&'a K> {
+        keys.flat_map(|key| self.removal_set(key)).collect()
+    }
+
+    // Fails if a restricting edge from an entry outside `leaving` points at `key`
+    fn check_restrict(&self, key: &K, leaving: &HashSet<&K>) -> Result<(), VaultError> {
+        match self.graph.restricting(key, leaving) {
+            Some(edge) => Err(VaultError::InvalidInput(format!(
+                "'{}' is still referenced by '{}' ({})",
+                key, edge.from, edge.relation
+            ))),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,537 @@
+use crate::acl::{Access, AccessPolicy, Permission};
+use crate::error::VaultError;
+use crate::events::{EventFilter, SubscriptionId, VaultEvent};
+use crate::memory::MemorySize;
+use crate::resource::Resource;
+use crate::vault::Vault;
+use proto::vault_server::VaultServer;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::watch;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream, WatchStream};
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+// Request metadata carrying the caller's API key
+pub const API_KEY_HEADER: &str = "x-api-key";
+// Change events a `Watch` stream may fall behind by before it is closed
+pub const WATCH_BUFFER: usize = 1024;
+
+// Message types of proto/vault.proto, plus the generated `vault_server` and
+// `vault_client` modules
+pub mod proto {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Resource {
+        #[prost(oneof = "resource::Value", tags = "1, 2, 3")]
+        pub value: Option<resource::Value>,
+    }
+
+    pub mod resource {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Value {
+            #[prost(string, tag = "1")]
+            TextMessage(String),
+            #[prost(double, tag = "2")]
+            SensorData(f64),
+            #[prost(message, tag = "3")]
+            SystemLogs(super::Logs),
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Logs {
+        #[prost(string, repeated, tag = "1")]
+        pub lines: Vec<String>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Entry {
+        #[prost(string, tag = "1")]
+        pub key: String,
+        #[prost(message, optional, tag = "2")]
+        pub resource: Option<Resource>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct AddRequest {
+        #[prost(string, tag = "1")]
+        pub key: String,
+        #[prost(message, optional, tag = "2")]
+        pub resource: Option<Resource>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct AddResponse {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct KeyRequest {
+        #[prost(string, tag = "1")]
+        pub key: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct UpdateRequest {
+        #[prost(string, tag = "1")]
+        pub key: String,
+        #[prost(message, optional, tag = "2")]
+        pub resource: Option<Resource>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ListRequest {
+        #[prost(string, tag = "1")]
+        pub prefix: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SearchRequest {
+        #[prost(string, tag = "1")]
+        pub query: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct EntryList {
+        #[prost(message, repeated, tag = "1")]
+        pub entries: Vec<Entry>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SummaryRequest {}
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SummaryResponse {
+        #[prost(string, tag = "1")]
+        pub location: String,
+        #[prost(uint64, tag = "2")]
+        pub capacity_bytes: u64,
+        #[prost(uint64, tag = "3")]
+        pub used_bytes: u64,
+        #[prost(uint64, tag = "4")]
+        pub entries: u64,
+        #[prost(uint64, tag = "5")]
+        pub text_messages: u64,
+        #[prost(uint64, tag = "6")]
+        pub sensor_readings: u64,
+        #[prost(uint64, tag = "7")]
+        pub system_logs: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct WatchRequest {
+        #[prost(string, tag = "1")]
+        pub key_prefix: String,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct ChangeEvent {
+        #[prost(enumeration = "change_event::Kind", tag = "1")]
+        pub kind: i32,
+        #[prost(string, tag = "2")]
+        pub key: String,
+        #[prost(message, optional, tag = "3")]
+        pub old: Option<Resource>,
+        #[prost(message, optional, tag = "4")]
+        pub new: Option<Resource>,
+    }
+
+    pub mod change_event {
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+        #[repr(i32)]
+        pub enum Kind {
+            Unspecified = 0,
+            Added = 1,
+            Updated = 2,
+            Removed = 3,
+            Evicted = 4,
+            Expired = 5,
+        }
+    }
+
+    include!(concat!(env!("OUT_DIR"), "/memoria.v1.Vault.rs"));
+}
+
+impl From<&Resource> for proto::Resource {
+    fn from(resource: &Resource) -> Self {
+        use proto::resource::Value;
+        let value = match resource {
+            Resource::TextMessage(text) => Value::TextMessage(text.clone()),
+            Resource::SensorData(value) => Value::SensorData(*value),
+            Resource::SystemLogs(lines) => Value::SystemLogs(proto::Logs {
+                lines: lines.clone(),
+            }),
+        };
+        Self { value: Some(value) }
+    }
+}
+
+impl TryFrom<proto::Resource> for Resource {
+    type Error = VaultError;
+
+    fn try_from(resource: proto::Resource) -> Result<Self, VaultError> {
+        use proto::resource::Value;
+        match resource.value {
+            Some(Value::TextMessage(text)) => Ok(Resource::TextMessage(text)),
+            Some(Value::SensorData(value)) => Ok(Resource::SensorData(value)),
+            Some(Value::SystemLogs(logs)) => Ok(Resource::SystemLogs(logs.lines)),
+            None => Err(VaultError::InvalidInput(
+                "Resource has no value".to_string(),
+            )),
+        }
+    }
+}
+
+impl From<VaultError> for Status {
+    fn from(error: VaultError) -> Self {
+        let message = error.message();
+        match error {
+            VaultError::ResourceNotFound(_) => Status::not_found(message),
+            VaultError::InvalidInput(_) => Status::invalid_argument(message),
+            VaultError::VaultFull { .. } | VaultError::QuotaExceeded { .. } => {
+                Status::resource_exhausted(message)
+            }
+            VaultError::Duplicate { .. } => Status::already_exists(message),
+            VaultError::PermissionDenied { .. } => Status::permission_denied(message),
+            VaultError::IoError(_) => Status::internal(message),
+        }
+    }
+}
+
+fn resource_arg(resource: Option<proto::Resource>) -> Result<Resource, Status> {
+    let resource = resource.ok_or_else(|| Status::invalid_argument("Missing resource"))?;
+    Ok(Resource::try_from(resource)?)
+}
+
+fn entry(key: String, resource: &Resource) -> proto::Entry {
+    proto::Entry {
+        key,
+        resource: Some(resource.into()),
+    }
+}
+
+fn change_event(event: VaultEvent<String>) -> proto::ChangeEvent {
+    use proto::change_event::Kind;
+    let kind = match &event {
+        VaultEvent::Added { .. } => Kind::Added,
+        VaultEvent::Updated { .. } => Kind::Updated,
+        VaultEvent::Removed { .. } => Kind::Removed,
+        VaultEvent::Evicted { .. } => Kind::Evicted,
+        VaultEvent::Expired { .. } => Kind::Expired,
+    };
+    proto::ChangeEvent {
+        kind: kind as i32,
+        key: event.key().clone(),
+        old: event.old_resource().map(Into::into),
+        new: event.new_resource().map(Into::into),
+    }
+}
+
+// Change events for one Watch call. Ends when the server starts shutting
+// down, since graceful shutdown waits for open streams. Dropping the stream,
+// as tonic does when the client hangs up, ends the vault subscription.
+pub struct EventStream {
+    events: ReceiverStream<proto::ChangeEvent>,
+    // Set once an event did not fit in the buffer
+    lagging: Arc<AtomicBool>,
+    closing: WatchStream<bool>,
+    vault: Arc<Mutex<Vault<String>>>,
+    subscription: SubscriptionId,
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        if let Ok(mut vault) = self.vault.lock() {
+            vault.unsubscribe(self.subscription);
+        }
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Result<proto::ChangeEvent, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        while let Poll::Ready(closing) = Pin::new(&mut self.closing).poll_next(cx) {
+            if closing != Some(false) {
+                return Poll::Ready(None);
+            }
+        }
+        match Pin::new(&mut self.events).poll_next(cx) {
+            Poll::Ready(event) => Poll::Ready(event.map(Ok)),
+            // A watcher that fell behind gets what was buffered, then an
+            // error instead of a stream with gaps in it
+            Poll::Pending if self.lagging.load(Ordering::Relaxed) => {
+                self.events.close();
+                Poll::Ready(Some(Err(Status::aborted(
+                    "watch fell too far behind; changes were dropped",
+                ))))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// The `memoria.v1.Vault` service over a shared vault
+struct VaultService {
+    vault: Arc<Mutex<Vault<String>>>,
+    closing: watch::Receiver<bool>,
+    policy: Option<Arc<AccessPolicy>>,
+    watch_buffer: usize,
+}
+
+impl VaultService {
+    fn lock(&self) -> Result<MutexGuard<'_, Vault<String>>, Status> {
+        self.vault
+            .lock()
+            .map_err(|_| Status::internal("vault lock poisoned"))
+    }
+
+    // Under an access policy every call carries its API key in `x-api-key`
+    fn access<T>(&self, request: &Request<T>) -> Result<Access, Status> {
+        let mut access = Access::new(self.policy.clone());
+        if access.enforced() {
+            let api_key = request
+                .metadata()
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .ok_or_else(|| Status::unauthenticated("Missing x-api-key"))?;
+            access
+                .authenticate(api_key)
+                .map_err(|e| Status::unauthenticated(e.message()))?;
+        }
+        Ok(access)
+    }
+}
+
+#[tonic::async_trait]
+impl proto::vault_server::Vault for VaultService {
+    async fn add(
+        &self,
+        request: Request<proto::AddRequest>,
+    ) -> Result<Response<proto::AddResponse>, Status> {
+        let access = self.access(&request)?;
+        let request = request.into_inner();
+        access.check(Permission::Write, &request.key)?;
+        let resource = resource_arg(request.resource)?;
+        let mut vault = self.lock()?;
+        vault.set_actor(access.actor());
+        vault.add(request.key, resource)?;
+        Ok(Response::new(proto::AddResponse {}))
+    }
+
+    async fn get(
+        &self,
+        request: Request<proto::KeyRequest>,
+    ) -> Result<Response<proto::Entry>, Status> {
+        let access = self.access(&request)?;
+        let key = request.into_inner().key;
+        access.check(Permission::Read, &key)?;
+        let vault = self.lock()?;
+        let resource = vault
+            .get(&key)
+            .ok_or_else(|| VaultError::ResourceNotFound(key.clone()))?;
+        Ok(Response::new(entry(key, resource)))
+    }
+
+    async fn update(
+        &self,
+        request: Request<proto::UpdateRequest>,
+    ) -> Result<Response<proto::Entry>, Status> {
+        let access = self.access(&request)?;
+        let request = request.into_inner();
+        access.check(Permission::Write, &request.key)?;
+        let resource = resource_arg(request.resource)?;
+        let mut vault = self.lock()?;
+        vault.set_actor(access.actor());
+        let old = vault.update(&request.key, resource)?;
+        Ok(Response::new(entry(request.key, &old)))
+    }
+
+    async fn remove(
+        &self,
+        request: Request<proto::KeyRequest>,
+    ) -> Result<Response<proto::Entry>, Status> {
+        let access = self.access(&request)?;
+        let key = request.into_inner().key;
+        let mut vault = self.lock()?;
+        access.check_removal(&vault, &key)?;
+        vault.set_actor(access.actor());
+        let removed = vault.remove(&key)?;
+        Ok(Response::new(entry(key, &removed)))
+    }
+
+    async fn list(
+        &self,
+        request: Request<proto::ListRequest>,
+    ) -> Result<Response<proto::EntryList>, Status> {
+        let access = self.access(&request)?;
+        let prefix = request.into_inner().prefix;
+        let vault = self.lock()?;
+        let mut entries: Vec<proto::Entry> = vault
+            .iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .filter(|(key, _)| access.allows(Permission::Read, key))
+            .map(|(key, resource)| entry(key.clone(), resource))
+            .collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(Response::new(proto::EntryList { entries }))
+    }
+
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::EntryList>, Status> {
+        let access = self.access(&request)?;
+        let query = request.into_inner().query;
+        let vault = self.lock()?;
+        let entries = vault
+            .search(&query)
+            .into_iter()
+            .filter(|(key, _)| access.allows(Permission::Read, key))
+            .map(|(key, resource)| entry(key.clone(), resource))
+            .collect();
+        Ok(Response::new(proto::EntryList { entries }))
+    }
+
+    async fn summary(
+        &self,
+        request: Request<proto::SummaryRequest>,
+    ) -> Result<Response<proto::SummaryResponse>, Status> {
+        self.access(&request)?.check(Permission::Read, "")?;
+        let vault = self.lock()?;
+        let mut summary = proto::SummaryResponse {
+            location: vault.location.clone(),
+            capacity_bytes: vault.storage_capacity.size_bytes(),
+            used_bytes: vault.current_usage(),
+            entries: vault.len() as u64,
+            ..Default::default()
+        };
+        for (_, resource) in vault.iter() {
+            match resource {
+                Resource::TextMessage(_) => summary.text_messages += 1,
+                Resource::SensorData(_) => summary.sensor_readings += 1,
+                Resource::SystemLogs(_) => summary.system_logs += 1,
+            }
+        }
+        Ok(Response::new(summary))
+    }
+
+    type WatchStream = EventStream;
+
+    async fn watch(
+        &self,
+        request: Request<proto::WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let access = self.access(&request)?;
+        let prefix = request.into_inner().key_prefix;
+        let (sender, receiver) = tokio::sync::mpsc::channel(self.watch_buffer);
+        let lagging = Arc::new(AtomicBool::new(false));
+        let lagged = Arc::clone(&lagging);
+        // Events are queued from inside the vault's mutation, so sending must
+        // not block; when the buffer is full the stream is marked for closing
+        // and gets nothing more. Changes to keys the client cannot read are
+        // skipped.
+        let subscription = self
+            .lock()?
+            .subscribe(EventFilter::prefix(&prefix), move |event| {
+                if lagged.load(Ordering::Relaxed) || !access.allows(Permission::Read, event.key()) {
+                    return;
+                }
+                if let Err(TrySendError::Full(_)) = sender.try_send(change_event(event.clone())) {
+                    lagged.store(true, Ordering::Relaxed);
+                }
+            });
+        Ok(Response::new(EventStream {
+            events: ReceiverStream::new(receiver),
+            lagging,
+            closing: WatchStream::new(self.closing.clone()),
+            vault: Arc::clone(&self.vault),
+            subscription,
+        }))
+    }
+}
+
+// Serves a `Vault<String>` over gRPC. The vault is saved when the server
+// shuts down, if a save path is set.
+pub struct GrpcServer {
+    vault: Arc<Mutex<Vault<String>>>,
+    save_path: Option<String>,
+    policy: Option<Arc<AccessPolicy>>,
+    watch_buffer: usize,
+}
+
+impl GrpcServer {
+    pub fn new(vault: Vault<String>, save_path: Option<String>) -> Self {
+        Self {
+            vault: Arc::new(Mutex::new(vault)),
+            save_path,
+            policy: None,
+            watch_buffer: WATCH_BUFFER,
+        }
+    }
+
+    // Requires an API key in the `x-api-key` metadata of every call, and
+    // checks each call against the principal's permissions
+    pub fn with_policy(mut self, policy: AccessPolicy) -> Self {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    // How many change events a `Watch` stream may fall behind by before it
+    // ends with `ABORTED`; `WATCH_BUFFER` by default
+    pub fn with_watch_buffer(mut self, events: usize) -> Self {
+        self.watch_buffer = events.max(1);
+        self
+    }
+
+    // Records every change clients make in the audit log at `path`, under the
+    // name of the principal making it
+    pub fn with_audit_log(self, path: &str) -> Result<Self, VaultError> {
+        self.vault
+            .lock()
+            .map_err(|_| VaultError::IoError(std::io::Error::other("vault lock poisoned")))?
+            .open_audit_log(path)?;
+        Ok(self)
+    }
+
+    // Saves back to `path` on shutdown
+    pub fn open(path: &str, location: String, capacity: MemorySize) -> Result<Self, VaultError> {
+        let vault = Vault::open_or_create(path, location, capacity)?;
+        Ok(Self::new(vault, Some(path.to_string())))
+    }
+
+    // Accepts clients on `listener` until `shutdown` completes
+    pub async fn serve<F>(self, listener: TcpListener, shutdown: F) -> Result<(), VaultError>
+    where
+        F: Future<Output = ()>,
+    {
+        let (close, closing) = watch::channel(false);
+        let service = VaultService {
+            vault: self.vault.clone(),
+            closing,
+            policy: self.policy.clone(),
+            watch_buffer: self.watch_buffer,
+        };
+        let shutdown = async move {
+            shutdown.await;
+            let _ = close.send(true);
+        };
+        tonic::transport::Server::builder()
+            .add_service(VaultServer::new(service))
+            .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown)
+            .await
+            .map_err(|e| VaultError::IoError(std::io::Error::other(e)))?;
+
+        let vault = self
+            .vault
+            .lock()
+            .map_err(|_| VaultError::IoError(std::io::Error::other("vault lock poisoned")))?;
+        match &self.save_path {
+            Some(path) => vault.save_to_file(path),
+            None => Ok(()),
+        }
+    }
+}
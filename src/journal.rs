@@ -0,0 +1,187 @@
+use crate::backend::StorageBackend;
+use crate::entry::EntryMeta;
+use crate::error::VaultError;
+use crate::events::Removal;
+use crate::graph::Edge;
+use crate::resource::Resource;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs::{self, File, OpenOptions};
+use std::hash::Hash;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+// One change recorded in the write-ahead journal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum JournalOp<K> {
+    Put {
+        key: K,
+        resource: Resource,
+        meta: EntryMeta,
+    },
+    Delete {
+        key: K,
+    },
+    Link {
+        edge: Edge<K>,
+    },
+    Unlink {
+        from: K,
+        relation: String,
+        to: K,
+    },
+}
+
+// A journal line. Everything in `ops` was committed together.
+#[derive(Deserialize)]
+struct JournalRecord<K> {
+    ops: Vec<JournalOp<K>>,
+}
+
+// Borrowing twin of `JournalRecord` used when writing
+#[derive(Serialize)]
+struct JournalRecordRef<'a, K> {
+    ops: &'a [JournalOp<K>],
+}
+
+// Append-only log of committed changes, one JSON record per line
+pub(crate) struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+impl Journal {
+    pub(crate) fn open(path: &Path) -> Result<Self, VaultError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        // Cut off a torn final record so new records start on a fresh line
+        let contents = fs::read(path)?;
+        if contents.last().is_some_and(|byte| *byte != b'\n') {
+            let keep = contents
+                .iter()
+                .rposition(|byte| *byte == b'\n')
+                .map_or(0, |i| i + 1);
+            file.set_len(keep as u64)?;
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+        })
+    }
+
+    // Reads back every complete record. A torn final line from a crash
+    // mid-write is ignored, since that record was never acknowledged.
+    pub(crate) fn read<K>(path: &Path) -> Result<Vec<Vec<JournalOp<K>>>, VaultError>
+    where
+        K: for<'de> Deserialize<'de>,
+    {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let lines: Vec<String> = BufReader::new(File::open(path)?)
+            .lines()
+            .collect::<Result<_, _>>()?;
+        let mut records = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<JournalRecord<K>>(line) {
+                Ok(record) => records.push(record.ops),
+                Err(_) if index == lines.len() - 1 => break,
+                Err(e) => {
+                    return Err(VaultError::InvalidInput(format!(
+                        "Corrupt journal record on line {}: {}",
+                        index + 1,
+                        e
+                    )))
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    // Writes one record and waits for it to reach the disk
+    pub(crate) fn append<K: Serialize>(&mut self, ops: &[JournalOp<K>]) -> Result<(), VaultError> {
+        let mut line = serde_json::to_string(&JournalRecordRef { ops }).map_err(|e| {
+            VaultError::IoError(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                e.to_string(),
+            ))
+        })?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    pub(crate) fn truncate(&mut self) -> Result<(), VaultError> {
+        self.file = File::create(&self.path)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    // Replays an existing journal onto this vault, then records every later
+    // mutation there before applying it
+    pub fn open_journal(&mut self, path: &str) -> Result<(), VaultError> {
+        let path = Path::new(path);
+        // Replay restores changes that were audited when first made
+        let audit = self.audit.take();
+        let replayed = Journal::read::<K>(path).and_then(|records| self.replay(records));
+        self.audit = audit;
+        replayed?;
+        self.journal = Some(Journal::open(path)?);
+        Ok(())
+    }
+
+    fn replay(&mut self, records: Vec<Vec<JournalOp<K>>>) -> Result<(), VaultError> {
+        for ops in records {
+            for op in ops {
+                match op {
+                    JournalOp::Put {
+                        key,
+                        resource,
+                        meta,
+                    } => self.restore_entry(key, resource, meta)?,
+                    JournalOp::Delete { key } => {
+                        self.remove_entry(&key, Removal::Removed)?;
+                    }
+                    JournalOp::Link { edge } => self.graph.insert(edge),
+                    JournalOp::Unlink { from, relation, to } => {
+                        self.graph.remove(&from, &relation, &to);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Saves a full snapshot and empties the journal, which it now supersedes
+    pub fn checkpoint(&mut self, path: &str) -> Result<(), VaultError> {
+        self.save_to_file(path)?;
+        if let Some(journal) = &mut self.journal {
+            journal.truncate()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn log(&mut self, ops: &[JournalOp<K>]) -> Result<(), VaultError> {
+        match &mut self.journal {
+            Some(journal) if !ops.is_empty() => journal.append(ops),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn is_journaling(&self) -> bool {
+        self.journal.is_some()
+    }
+}
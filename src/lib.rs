@@ -1,9 +1,77 @@
 mod resource;
-pub use resource::Resource;
+pub use resource::{Resource, ResourceKind};
 mod memory;
 pub use memory::MemorySize;
 mod vault;
 pub use vault::{Vault, VaultMetadata};
+mod snapshot;
+pub use snapshot::VaultSnapshot;
+mod entry;
+pub use entry::EntryMeta;
+mod events;
+pub use events::{EventCallback, EventFilter, SubscriptionId, VaultEvent};
+mod journal;
+pub use journal::JournalOp;
+mod transaction;
+pub use transaction::Transaction;
+mod diff;
+pub use diff::{
+    merge, Change, Conflict, ConflictResolution, ConflictResolver, MergeOutcome, VaultDiff,
+};
+mod backend;
+pub use backend::{MemoryBackend, StorageBackend};
+mod disk;
+pub use disk::DiskBackend;
+mod packed;
+pub use packed::PackedVault;
+mod retention;
+pub use retention::{DecayFunction, RetentionPolicy};
+mod tokenizer;
+pub use tokenizer::{ApproxTokenizer, Tokenizer, WhitespaceTokenizer};
+mod context;
+pub use context::{Context, ContextBuilder};
+mod session;
+pub use session::{Budget, Role, SessionStore, Summarizer, Turn};
+mod graph;
+pub use graph::{Edge, OnDelete};
+mod embedding;
+pub use embedding::{cosine_similarity, Embedder, HashedEmbedder};
+mod recall;
+pub use recall::{Explanation, Fusion, RecallHit, RecallOptions, Signal};
+mod dedup;
+pub use dedup::{DedupConfig, DedupPolicy, Duplicate, DUPLICATE_OF};
+mod audit;
+pub use audit::{resource_digest, AuditLog, AuditOp, AuditQuery, AuditRecord};
+mod stats;
+pub use stats::{AgeBucket, EntrySize, KeyLengths, KindStats, VaultStats};
+mod quota;
+pub use quota::{Quota, QuotaScope, QuotaUsage, Quotas};
+mod acl;
+pub use acl::{AccessPolicy, AuthorizedVault, Grant, Permission, Principal};
+mod mcp;
+pub use mcp::{resource_from_json, resource_to_json, McpServer};
+mod resp;
+pub use resp::RespServer;
+#[cfg(unix)]
+mod daemon;
+#[cfg(unix)]
+pub use daemon::{Daemon, DaemonClient, Request, Response, WireError};
+mod tiers;
+pub use tiers::{
+    ConsolidationCallback, MemorySystem, PromotionRules, Recollection, Tier, TierCapacities,
+};
+#[cfg(feature = "async")]
+mod async_vault;
+#[cfg(feature = "async")]
+pub use async_vault::{AsyncVault, Flusher};
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "grpc")]
+pub use grpc::GrpcServer;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::{migrate_json_to_sqlite, migrate_sqlite_to_json, SqliteBackend};
 pub mod error;
 pub mod ui;
 pub use error::VaultError;
@@ -11,6 +79,7 @@ pub use error::VaultError;
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
 
     #[test]
     fn test_new_vault() {
@@ -194,6 +263,21 @@ mod tests {
         // Verify file exists and can be read
         assert!(std::path::Path::new(temp_file).exists());
         let _ = std::fs::remove_file(temp_file);
+
+        // A save that cannot be renamed into place cleans up after itself
+        let dir = "/tmp/test_vault_save_dir";
+        std::fs::create_dir_all(dir).unwrap();
+        assert!(vault.save_to_file(dir).is_err());
+        let leftovers = std::fs::read_dir("/tmp")
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.starts_with("test_vault_save_dir.") && name.ends_with(".tmp")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+        let _ = std::fs::remove_dir(dir);
     }
 
     #[test]
@@ -224,4 +308,2535 @@ mod tests {
 
         let _ = std::fs::remove_file(temp_file);
     }
+
+    #[test]
+    fn test_vault_diff() {
+        let mut a = Vault::<String>::new("A".to_string(), MemorySize::MB(1));
+        a.add("same".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        a.add("gone".to_string(), Resource::SensorData(2.0))
+            .unwrap();
+        a.add("edit".to_string(), Resource::TextMessage("old".to_string()))
+            .unwrap();
+
+        let mut b = Vault::<String>::new("B".to_string(), MemorySize::MB(1));
+        b.add("same".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        b.add("edit".to_string(), Resource::TextMessage("new".to_string()))
+            .unwrap();
+        b.add("fresh".to_string(), Resource::SensorData(3.0))
+            .unwrap();
+
+        let diff = a.diff(&b);
+        assert_eq!(diff.counts(), (1, 1, 1));
+        assert_eq!(
+            diff.changes,
+            vec![
+                Change::Changed {
+                    key: "edit".to_string(),
+                    old: Resource::TextMessage("old".to_string()),
+                    new: Resource::TextMessage("new".to_string()),
+                },
+                Change::Added {
+                    key: "fresh".to_string(),
+                    resource: Resource::SensorData(3.0),
+                },
+                Change::Removed {
+                    key: "gone".to_string(),
+                    resource: Resource::SensorData(2.0),
+                },
+            ]
+        );
+        assert!(a.diff(&a).is_empty());
+
+        // An expired entry is already gone, even before it is purged
+        a.set_ttl(&"gone".to_string(), Some(std::time::Duration::ZERO))
+            .unwrap();
+        assert_eq!(a.diff(&b).counts(), (1, 0, 1));
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let mut a = Vault::<String>::new("A".to_string(), MemorySize::MB(1));
+        a.add("gone".to_string(), Resource::SensorData(2.0))
+            .unwrap();
+        a.add("edit".to_string(), Resource::TextMessage("old".to_string()))
+            .unwrap();
+        let mut b = Vault::<String>::new("B".to_string(), MemorySize::MB(1));
+        b.add("edit".to_string(), Resource::TextMessage("new".to_string()))
+            .unwrap();
+        b.add("fresh".to_string(), Resource::SensorData(3.0))
+            .unwrap();
+
+        // The patch survives a JSON round-trip and reproduces `b`
+        let json = serde_json::to_string(&a.diff(&b)).unwrap();
+        let patch: VaultDiff<String> = serde_json::from_str(&json).unwrap();
+        let mut target = Vault::<String>::new("Target".to_string(), MemorySize::MB(1));
+        target
+            .add("gone".to_string(), Resource::SensorData(2.0))
+            .unwrap();
+        target
+            .add("edit".to_string(), Resource::TextMessage("old".to_string()))
+            .unwrap();
+        target.apply_patch(&patch).unwrap();
+        assert!(target.diff(&b).is_empty());
+
+        // Applying it again fails without touching anything
+        let res = target.apply_patch(&patch);
+        assert!(matches!(res, Err(VaultError::InvalidInput(_))));
+        assert!(target.diff(&b).is_empty());
+    }
+
+    #[test]
+    fn test_merge_one_sided_changes() {
+        let mut base = Vault::<String>::new("Base".to_string(), MemorySize::MB(1));
+        base.add("a".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        base.add("b".to_string(), Resource::SensorData(2.0))
+            .unwrap();
+
+        let mut ours = Vault::<String>::new("Ours".to_string(), MemorySize::MB(1));
+        ours.add("a".to_string(), Resource::SensorData(10.0))
+            .unwrap();
+        ours.add("b".to_string(), Resource::SensorData(2.0))
+            .unwrap();
+
+        let mut theirs = Vault::<String>::new("Theirs".to_string(), MemorySize::MB(1));
+        theirs
+            .add("a".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        theirs
+            .add("c".to_string(), Resource::SensorData(3.0))
+            .unwrap();
+
+        let outcome = merge(&base, &ours, &theirs, &ConflictResolution::Ours).unwrap();
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(outcome.vault.location, "Ours");
+        assert_eq!(
+            outcome.vault.get(&"a".to_string()),
+            Some(&Resource::SensorData(10.0))
+        );
+        assert_eq!(outcome.vault.get(&"b".to_string()), None);
+        assert_eq!(
+            outcome.vault.get(&"c".to_string()),
+            Some(&Resource::SensorData(3.0))
+        );
+
+        // Both sides fit on their own, the merge does not
+        let text = Resource::TextMessage("x".repeat(600));
+        let mut ours = Vault::<String>::new("Ours".to_string(), MemorySize::KB(1));
+        ours.add("mine".to_string(), text.clone()).unwrap();
+        let mut theirs = Vault::<String>::new("Theirs".to_string(), MemorySize::KB(1));
+        theirs.add("yours".to_string(), text).unwrap();
+        let empty = Vault::<String>::new("Base".to_string(), MemorySize::KB(1));
+        let res = merge(&empty, &ours, &theirs, &ConflictResolution::Ours);
+        assert!(matches!(
+            res,
+            Err(VaultError::VaultFull {
+                capacity: 1024,
+                current: 1200,
+                new_size: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn test_merge_conflict_resolution() {
+        let mut base = Vault::<String>::new("Base".to_string(), MemorySize::MB(1));
+        base.add("k".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        let mut ours = Vault::<String>::new("Ours".to_string(), MemorySize::MB(1));
+        ours.add("k".to_string(), Resource::SensorData(2.0))
+            .unwrap();
+        let mut theirs = Vault::<String>::new("Theirs".to_string(), MemorySize::MB(1));
+        theirs
+            .add("k".to_string(), Resource::SensorData(3.0))
+            .unwrap();
+        let key = "k".to_string();
+
+        let outcome = merge(&base, &ours, &theirs, &ConflictResolution::Ours).unwrap();
+        assert_eq!(outcome.conflicts.len(), 1);
+        assert_eq!(outcome.conflicts[0].base, Some(Resource::SensorData(1.0)));
+        assert_eq!(outcome.vault.get(&key), Some(&Resource::SensorData(2.0)));
+
+        let outcome = merge(&base, &ours, &theirs, &ConflictResolution::Theirs).unwrap();
+        assert_eq!(outcome.vault.get(&key), Some(&Resource::SensorData(3.0)));
+
+        ours.meta.get_mut(&key).unwrap().updated_at = 100;
+        theirs.meta.get_mut(&key).unwrap().updated_at = 200;
+        let outcome = merge(&base, &ours, &theirs, &ConflictResolution::NewestWins).unwrap();
+        assert_eq!(outcome.vault.get(&key), Some(&Resource::SensorData(3.0)));
+        assert_eq!(outcome.vault.entry_meta(&key).unwrap().updated_at, 200);
+
+        let sum = ConflictResolution::Custom(Box::new(|c: &Conflict<String>| {
+            match (&c.ours, &c.theirs) {
+                (Some(Resource::SensorData(o)), Some(Resource::SensorData(t))) => {
+                    Some(Resource::SensorData(o + t))
+                }
+                _ => None,
+            }
+        }));
+        let outcome = merge(&base, &ours, &theirs, &sum).unwrap();
+        assert_eq!(outcome.vault.get(&key), Some(&Resource::SensorData(5.0)));
+    }
+
+    #[test]
+    fn test_event_callbacks() {
+        use std::sync::{Arc, Mutex};
+
+        let mut vault = Vault::<String>::new("Events".to_string(), MemorySize::KB(1));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let id = vault.subscribe(EventFilter::all(), move |event| {
+            sink.lock().unwrap().push(event.clone())
+        });
+
+        let key = "note".to_string();
+        vault
+            .add(key.clone(), Resource::TextMessage("a".to_string()))
+            .unwrap();
+        vault
+            .update(&key, Resource::TextMessage("b".to_string()))
+            .unwrap();
+        // Rejected mutations stay silent
+        assert!(vault
+            .add("big".to_string(), Resource::TextMessage("x".repeat(2000)))
+            .is_err());
+        assert!(vault.remove(&"missing".to_string()).is_err());
+        vault.remove(&key).unwrap();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                VaultEvent::Added {
+                    key: key.clone(),
+                    resource: Resource::TextMessage("a".to_string()),
+                },
+                VaultEvent::Updated {
+                    key: key.clone(),
+                    old: Resource::TextMessage("a".to_string()),
+                    new: Resource::TextMessage("b".to_string()),
+                },
+                VaultEvent::Removed {
+                    key: key.clone(),
+                    resource: Resource::TextMessage("b".to_string()),
+                },
+            ]
+        );
+
+        assert!(vault.unsubscribe(id));
+        vault.add(key, Resource::SensorData(1.0)).unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_event_channel_filters() {
+        let mut vault = Vault::<String>::new("Events".to_string(), MemorySize::KB(1));
+        let agent = vault.watch(EventFilter::prefix("agent/"));
+        let sensors = vault.watch(EventFilter::all().kind(ResourceKind::SensorData));
+
+        vault
+            .add(
+                "agent/goal".to_string(),
+                Resource::TextMessage("x".to_string()),
+            )
+            .unwrap();
+        vault
+            .add("agent/temp".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        vault
+            .add("other".to_string(), Resource::SensorData(2.0))
+            .unwrap();
+
+        let agent_keys: Vec<String> = agent.try_iter().map(|e| e.key().clone()).collect();
+        assert_eq!(agent_keys, vec!["agent/goal", "agent/temp"]);
+        let sensor_keys: Vec<String> = sensors.try_iter().map(|e| e.key().clone()).collect();
+        assert_eq!(sensor_keys, vec!["agent/temp", "other"]);
+
+        // Dropped receivers are pruned instead of failing the mutation
+        drop(agent);
+        vault.remove(&"agent/goal".to_string()).unwrap();
+    }
+
+    #[test]
+    fn test_ttl_expiry_event() {
+        use std::time::Duration;
+
+        let mut vault = Vault::<String>::new("TTL".to_string(), MemorySize::KB(1));
+        let events = vault.watch(EventFilter::all());
+        let key = "session".to_string();
+        vault.add(key.clone(), Resource::SensorData(1.0)).unwrap();
+        vault.set_ttl(&key, Some(Duration::from_secs(60))).unwrap();
+        assert!(vault.ttl(&key).unwrap() > Duration::from_secs(50));
+        assert!(vault.purge_expired().is_empty());
+
+        vault.set_ttl(&key, Some(Duration::ZERO)).unwrap();
+        assert_eq!(vault.get(&key), None);
+        assert_eq!(vault.purge_expired(), vec![key.clone()]);
+        assert!(vault.is_empty());
+
+        let kinds: Vec<_> = events.try_iter().collect();
+        assert!(matches!(kinds[0], VaultEvent::Added { .. }));
+        assert!(matches!(kinds[1], VaultEvent::Expired { .. }));
+        assert_eq!(kinds.len(), 2);
+    }
+
+    #[test]
+    fn test_transaction_commit() {
+        let mut vault = Vault::<String>::new("Tx".to_string(), MemorySize::KB(1));
+        vault
+            .add("old".to_string(), Resource::TextMessage("bye".to_string()))
+            .unwrap();
+
+        let count = vault
+            .transaction(|tx| {
+                tx.add("a".to_string(), Resource::SensorData(1.0))?;
+                tx.add("b".to_string(), Resource::SensorData(2.0))?;
+                tx.remove(&"old".to_string())?;
+                assert_eq!(tx.get(&"a".to_string()), Some(&Resource::SensorData(1.0)));
+                assert_eq!(tx.get(&"old".to_string()), None);
+                Ok(2)
+            })
+            .unwrap();
+
+        assert_eq!(count, 2);
+        assert_eq!(vault.len(), 2);
+        assert_eq!(vault.get(&"old".to_string()), None);
+    }
+
+    #[test]
+    fn test_transaction_rollback() {
+        let mut vault = Vault::<String>::new("Tx".to_string(), MemorySize::KB(1));
+        let events = vault.watch(EventFilter::all());
+
+        // The third add pushes the batch over capacity, so none of it lands
+        let res = vault.transaction(|tx| {
+            tx.add("a".to_string(), Resource::TextMessage("x".repeat(400)))?;
+            tx.add("b".to_string(), Resource::TextMessage("x".repeat(400)))?;
+            tx.add("c".to_string(), Resource::TextMessage("x".repeat(400)))?;
+            Ok(())
+        });
+        assert!(matches!(res, Err(VaultError::VaultFull { .. })));
+
+        // An error from the closure discards everything staged before it
+        let res = vault.transaction(|tx| {
+            tx.add("a".to_string(), Resource::SensorData(1.0))?;
+            tx.remove(&"missing".to_string())?;
+            Ok(())
+        });
+        assert!(matches!(res, Err(VaultError::ResourceNotFound(_))));
+
+        assert!(vault.is_empty());
+        assert_eq!(events.try_iter().count(), 0);
+    }
+
+    // Refuses to store keys starting with "bad", like a disk that fails mid-batch
+    struct FlakyBackend(MemoryBackend<String>);
+
+    impl StorageBackend<String> for FlakyBackend {
+        fn get(&self, key: &String) -> Option<&Resource> {
+            self.0.get(key)
+        }
+
+        fn contains_key(&self, key: &String) -> bool {
+            self.0.contains_key(key)
+        }
+
+        fn insert(
+            &mut self,
+            key: String,
+            resource: Resource,
+        ) -> Result<Option<Resource>, VaultError> {
+            if key.starts_with("bad") {
+                return Err(VaultError::IoError(std::io::Error::other("disk full")));
+            }
+            self.0.insert(key, resource)
+        }
+
+        fn remove(&mut self, key: &String) -> Result<Option<Resource>, VaultError> {
+            self.0.remove(key)
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+
+        fn keys(&self) -> Box<dyn Iterator<Item = &String> + '_> {
+            self.0.keys()
+        }
+
+        fn iter(&self) -> Box<dyn Iterator<Item = (&String, &Resource)> + '_> {
+            self.0.iter()
+        }
+    }
+
+    #[test]
+    fn test_transaction_undone_on_backend_failure() {
+        let mut vault = Vault::with_backend(
+            "Tx".to_string(),
+            MemorySize::KB(1),
+            FlakyBackend(MemoryBackend::new()),
+        )
+        .unwrap();
+        vault
+            .add("keep".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        vault
+            .add("old".to_string(), Resource::SensorData(2.0))
+            .unwrap();
+        let events = vault.watch(EventFilter::all());
+
+        // The first two changes are applied before the third fails, then undone
+        let res = vault.transaction(|tx| {
+            tx.update(&"keep".to_string(), Resource::SensorData(10.0))?;
+            tx.remove(&"old".to_string())?;
+            tx.add("bad".to_string(), Resource::SensorData(3.0))
+        });
+        assert!(matches!(res, Err(VaultError::IoError(_))));
+        assert_eq!(
+            vault.get(&"keep".to_string()),
+            Some(&Resource::SensorData(1.0))
+        );
+        assert_eq!(
+            vault.get(&"old".to_string()),
+            Some(&Resource::SensorData(2.0))
+        );
+        assert_eq!(vault.len(), 2);
+        assert_eq!(events.try_iter().count(), 0);
+
+        // Events are no longer held afterwards
+        vault
+            .transaction(|tx| tx.add("new".to_string(), Resource::SensorData(4.0)))
+            .unwrap();
+        assert_eq!(events.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn test_indexes_untouched_by_failed_write() {
+        let text = |t: &str| Resource::TextMessage(t.to_string());
+        let mut vault = Vault::with_backend(
+            "Flaky".to_string(),
+            MemorySize::KB(1),
+            FlakyBackend(MemoryBackend::new()),
+        )
+        .unwrap();
+        vault.set_dedup(Some(DedupConfig::default()));
+        vault.set_quotas(Some(Quotas {
+            limits: vec![Quota::kind(ResourceKind::TextMessage).max_entries(1)],
+            max_entry_bytes: None,
+        }));
+
+        // The refused write leaves nothing behind for dedup or the quota to count
+        assert!(matches!(
+            vault.add("bad".to_string(), text("Alice works at Acme")),
+            Err(VaultError::IoError(_))
+        ));
+        vault
+            .add("good".to_string(), text("Alice works at Acme"))
+            .unwrap();
+        assert_eq!(vault.quota_usage()[0].entries, 1);
+    }
+
+    #[test]
+    fn test_transaction_net_capacity() {
+        let mut vault = Vault::<String>::new("Tx".to_string(), MemorySize::KB(1));
+        vault
+            .add("big".to_string(), Resource::TextMessage("x".repeat(900)))
+            .unwrap();
+
+        // Adding first would overflow on its own, but the batch as a whole fits
+        vault
+            .transaction(|tx| {
+                tx.add("new".to_string(), Resource::TextMessage("y".repeat(900)))?;
+                tx.remove(&"big".to_string())?;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(vault.current_usage(), 900);
+        assert!(vault.get(&"new".to_string()).is_some());
+    }
+
+    #[test]
+    fn test_journal_replay() {
+        let path = "/tmp/test_vault_journal.wal";
+        let _ = std::fs::remove_file(path);
+
+        let mut vault = Vault::<String>::new("Journal".to_string(), MemorySize::KB(1));
+        vault.open_journal(path).unwrap();
+        vault
+            .add("a".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        vault
+            .transaction(|tx| {
+                tx.add("b".to_string(), Resource::SensorData(2.0))?;
+                tx.update(&"a".to_string(), Resource::SensorData(3.0))?;
+                Ok(())
+            })
+            .unwrap();
+        vault.remove(&"b".to_string()).unwrap();
+
+        // One record per mutation, one for the whole transaction
+        let contents = std::fs::read_to_string(path).unwrap();
+        assert_eq!(contents.lines().count(), 3);
+
+        // A crash mid-write leaves a torn line, which replay skips
+        std::fs::write(path, format!("{}{{\"ops\":[{{\"Del", contents)).unwrap();
+
+        let mut restored = Vault::<String>::new("Journal".to_string(), MemorySize::KB(1));
+        restored.open_journal(path).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert_eq!(
+            restored.get(&"a".to_string()),
+            Some(&Resource::SensorData(3.0))
+        );
+
+        // A checkpoint supersedes the journal
+        restored
+            .add("c".to_string(), Resource::SensorData(4.0))
+            .unwrap();
+        restored.checkpoint("/tmp/test_vault_journal.json").unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "");
+
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file("/tmp/test_vault_journal.json");
+    }
+
+    #[test]
+    fn test_add_many() {
+        let mut vault = Vault::<String>::new("Batch".to_string(), MemorySize::KB(1));
+        vault
+            .add("taken".to_string(), Resource::SensorData(0.0))
+            .unwrap();
+
+        let results = vault.add_many(vec![
+            ("a".to_string(), Resource::SensorData(1.0)),
+            ("taken".to_string(), Resource::SensorData(2.0)),
+            ("a".to_string(), Resource::SensorData(3.0)),
+            ("big".to_string(), Resource::TextMessage("x".repeat(2000))),
+            ("b".to_string(), Resource::SensorData(4.0)),
+        ]);
+
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(VaultError::InvalidInput(_))));
+        assert!(matches!(results[2], Err(VaultError::InvalidInput(_))));
+        assert!(matches!(results[3], Err(VaultError::VaultFull { .. })));
+        assert!(results[4].is_ok());
+        assert_eq!(vault.len(), 3);
+        assert_eq!(
+            vault.get(&"a".to_string()),
+            Some(&Resource::SensorData(1.0))
+        );
+    }
+
+    #[test]
+    fn test_get_and_remove_many() {
+        let mut vault = Vault::<String>::new("Batch".to_string(), MemorySize::KB(1));
+        vault.add_many((0..5).map(|i| (format!("k{}", i), Resource::SensorData(i as f64))));
+
+        let keys = vec!["k1".to_string(), "nope".to_string(), "k3".to_string()];
+        assert_eq!(
+            vault.get_many(&keys),
+            vec![
+                Some(&Resource::SensorData(1.0)),
+                None,
+                Some(&Resource::SensorData(3.0))
+            ]
+        );
+
+        let keys = vec!["k1".to_string(), "k1".to_string(), "nope".to_string()];
+        let results = vault.remove_many(&keys);
+        assert_eq!(results[0].as_ref().unwrap(), &Resource::SensorData(1.0));
+        assert!(matches!(results[1], Err(VaultError::ResourceNotFound(_))));
+        assert!(matches!(results[2], Err(VaultError::ResourceNotFound(_))));
+        assert_eq!(vault.len(), 4);
+    }
+
+    #[test]
+    fn test_usage_counter_tracks_mutations() {
+        let mut vault = Vault::<String>::new("Usage".to_string(), MemorySize::KB(1));
+        let full_scan =
+            |vault: &Vault<String>| -> u64 { vault.iter().map(|(_, res)| res.size_bytes()).sum() };
+
+        vault
+            .add("a".to_string(), Resource::TextMessage("hello".to_string()))
+            .unwrap();
+        vault.add_many(vec![
+            ("b".to_string(), Resource::SensorData(1.0)),
+            (
+                "c".to_string(),
+                Resource::SystemLogs(vec!["one".to_string()]),
+            ),
+        ]);
+        vault
+            .update(&"a".to_string(), Resource::TextMessage("hi".to_string()))
+            .unwrap();
+        vault.remove(&"b".to_string()).unwrap();
+        vault
+            .transaction(|tx| tx.add("d".to_string(), Resource::SensorData(2.0)))
+            .unwrap();
+
+        assert_eq!(vault.current_usage(), full_scan(&vault));
+        assert_eq!(vault.current_usage(), 2 + 3 + 8);
+    }
+
+    #[test]
+    fn test_batch_journal_record() {
+        let path = "/tmp/test_vault_batch.wal";
+        let _ = std::fs::remove_file(path);
+
+        let mut vault = Vault::<String>::new("Batch".to_string(), MemorySize::KB(1));
+        vault.open_journal(path).unwrap();
+        vault.add_many((0..10).map(|i| (format!("k{}", i), Resource::SensorData(i as f64))));
+        vault.remove_many(&["k0".to_string(), "k1".to_string()]);
+        assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 2);
+
+        let mut restored = Vault::<String>::new("Batch".to_string(), MemorySize::KB(1));
+        restored.open_journal(path).unwrap();
+        assert_eq!(restored.len(), 8);
+        assert_eq!(restored.current_usage(), 64);
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let mut vault = Vault::<String>::new("Snap".to_string(), MemorySize::KB(1));
+        vault
+            .add("a".to_string(), Resource::TextMessage("before".to_string()))
+            .unwrap();
+        vault
+            .add("b".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+
+        let snapshot = vault.snapshot();
+        vault
+            .update(&"a".to_string(), Resource::TextMessage("after".to_string()))
+            .unwrap();
+        vault.remove(&"b".to_string()).unwrap();
+        vault
+            .add("c".to_string(), Resource::SensorData(2.0))
+            .unwrap();
+
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(
+            snapshot.get(&"a".to_string()),
+            Some(&Resource::TextMessage("before".to_string()))
+        );
+        assert_eq!(
+            snapshot.get(&"b".to_string()),
+            Some(&Resource::SensorData(1.0))
+        );
+        assert!(!snapshot.contains_key(&"c".to_string()));
+        assert_eq!(snapshot.current_usage(), 14);
+        snapshot.summary();
+
+        // Snapshots can be shared with another thread while the vault moves on
+        let copy = snapshot.clone();
+        let handle = std::thread::spawn(move || copy.keys().count());
+        assert_eq!(handle.join().unwrap(), 2);
+
+        // An entry already expired when the snapshot is taken is not counted
+        vault
+            .set_ttl(&"c".to_string(), Some(std::time::Duration::ZERO))
+            .unwrap();
+        let snapshot = vault.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        let stats = snapshot.stats();
+        assert_eq!(stats.entries, 1);
+        assert_eq!(stats.kinds[1].entries, 0);
+        assert_eq!(stats.to_string(), vault.stats().to_string());
+    }
+
+    #[test]
+    fn test_search() {
+        let mut vault = Vault::<String>::new("Search".to_string(), MemorySize::KB(1));
+        vault
+            .add(
+                "greeting".to_string(),
+                Resource::TextMessage("Hello World".to_string()),
+            )
+            .unwrap();
+        vault
+            .add(
+                "syslog".to_string(),
+                Resource::SystemLogs(vec!["boot ok".to_string(), "WORLD ready".to_string()]),
+            )
+            .unwrap();
+        vault
+            .add("temp".to_string(), Resource::SensorData(21.5))
+            .unwrap();
+
+        let keys: Vec<&String> = vault.search("world").into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["greeting", "syslog"]);
+        assert_eq!(vault.search("TEMP").len(), 1);
+        assert_eq!(vault.search("21.5").len(), 1);
+
+        let snapshot = vault.snapshot();
+        vault.remove(&"greeting".to_string()).unwrap();
+        assert_eq!(snapshot.search("world").len(), 2);
+        assert_eq!(vault.search("world").len(), 1);
+    }
+
+    // Behaviour every storage backend has to share
+    fn run_backend_suite<B: StorageBackend<String>>(vault: &mut Vault<String, B>) {
+        let key = |k: &str| k.to_string();
+        assert!(vault.is_empty());
+        vault
+            .add(key("a"), Resource::TextMessage("alpha".to_string()))
+            .unwrap();
+        vault.add(key("b"), Resource::SensorData(1.5)).unwrap();
+        assert!(vault.add(key("a"), Resource::SensorData(0.0)).is_err());
+        assert_eq!(vault.len(), 2);
+        assert_eq!(vault.current_usage(), 13);
+
+        let old = vault
+            .update(&key("a"), Resource::TextMessage("alphabet".to_string()))
+            .unwrap();
+        assert_eq!(old, Resource::TextMessage("alpha".to_string()));
+        assert_eq!(vault.current_usage(), 16);
+
+        vault
+            .transaction(|tx| {
+                tx.remove(&key("b"))?;
+                tx.add(key("c"), Resource::SystemLogs(vec!["ok".to_string()]))
+            })
+            .unwrap();
+        assert!(!vault.contains_key(&key("b")));
+        assert_eq!(vault.search("ok").len(), 1);
+        assert_eq!(vault.remove(&key("a")).unwrap().size_bytes(), 8);
+        assert_eq!(vault.current_usage(), 2);
+        assert!(vault.remove(&key("a")).is_err());
+        vault.flush().unwrap();
+        assert_eq!(vault.len(), 1);
+        assert_eq!(
+            vault.get(&key("c")),
+            Some(&Resource::SystemLogs(vec!["ok".to_string()]))
+        );
+    }
+
+    #[test]
+    fn test_memory_backend_suite() {
+        let mut vault = Vault::<String>::new("Memory".to_string(), MemorySize::KB(1));
+        run_backend_suite(&mut vault);
+    }
+
+    #[test]
+    fn test_disk_backend_suite() {
+        let path = "/tmp/test_vault_disk_suite.log";
+        let _ = std::fs::remove_file(path);
+        let mut vault = Vault::open_disk(path, "Disk".to_string(), MemorySize::KB(1)).unwrap();
+        run_backend_suite(&mut vault);
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file("/tmp/test_vault_disk_suite.meta.json");
+    }
+
+    #[test]
+    fn test_disk_backend_corrupt_record() {
+        let path = "/tmp/test_vault_disk_corrupt.log";
+        let _ = std::fs::remove_file(path);
+        let mut backend = DiskBackend::<String>::open(path).unwrap();
+        for key in ["a", "b", "c"] {
+            backend
+                .insert(key.to_string(), Resource::SensorData(1.0))
+                .unwrap();
+        }
+        drop(backend);
+
+        // Damage the first record; the two after it must not be cut off
+        let log = std::fs::read_to_string(path).unwrap();
+        let damaged = log.replacen("Put", "Pxt", 1);
+        std::fs::write(path, &damaged).unwrap();
+        let res = DiskBackend::<String>::open(path);
+        assert!(matches!(res, Err(VaultError::IoError(e)) if e.to_string().contains("offset 0")));
+        assert_eq!(std::fs::read_to_string(path).unwrap(), damaged);
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_disk_backend_cache_and_deferred_errors() {
+        let path = "/tmp/test_vault_disk_deferred.log";
+        let blocker = "/tmp/test_vault_disk_deferred.compact";
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_dir(blocker);
+        let mut backend = DiskBackend::<String>::open(path).unwrap();
+
+        // A full scan pages everything in; the next write lets go of it
+        for i in 0..5000 {
+            backend
+                .insert(format!("k{}", i), Resource::SensorData(i as f64))
+                .unwrap();
+        }
+        assert_eq!(backend.iter().count(), 5000);
+        assert_eq!(backend.cached_entries(), 5000);
+        backend
+            .insert("k0".to_string(), Resource::SensorData(-1.0))
+            .unwrap();
+        assert_eq!(backend.cached_entries(), 0);
+        backend.flush().unwrap();
+
+        // A record damaged after opening reads as missing, and flush says why
+        let log = std::fs::read_to_string(path).unwrap();
+        let mut damaged = log.clone();
+        let at = log.rfind("SensorData").unwrap();
+        damaged.replace_range(at..at + 10, "SensorDbta");
+        std::fs::write(path, damaged).unwrap();
+        assert_eq!(backend.iter().count(), 4999);
+        let res = backend.flush();
+        assert!(
+            matches!(res, Err(VaultError::IoError(e)) if e.kind() == std::io::ErrorKind::InvalidData)
+        );
+        backend.flush().unwrap();
+        std::fs::write(path, log).unwrap();
+
+        // A compaction that cannot run does not fail the write that set it off
+        std::fs::create_dir(blocker).unwrap();
+        let big = Resource::TextMessage("x".repeat(1024 * 1024));
+        for _ in 0..3 {
+            backend.insert("big".to_string(), big.clone()).unwrap();
+        }
+        assert!(backend.dead_bytes() > 2 * 1024 * 1024);
+        assert_eq!(backend.get(&"big".to_string()), Some(&big));
+        assert!(backend.flush().is_err());
+        std::fs::remove_dir(blocker).unwrap();
+        backend.compact().unwrap();
+        assert_eq!(backend.dead_bytes(), 0);
+
+        drop(backend);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_disk_backend_reopen() {
+        let path = "/tmp/test_vault_disk_reopen.log";
+        let meta_path = "/tmp/test_vault_disk_reopen.meta.json";
+        let edges_path = "/tmp/test_vault_disk_reopen.edges.json";
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(meta_path);
+        let _ = std::fs::remove_file(edges_path);
+        let key = "note".to_string();
+
+        let mut vault: Vault<String, DiskBackend<String>> =
+            Vault::open_disk(path, "Disk".to_string(), MemorySize::KB(1)).unwrap();
+        vault
+            .add(key.clone(), Resource::TextMessage("kept".to_string()))
+            .unwrap();
+        vault
+            .add("gone".to_string(), Resource::SensorData(2.0))
+            .unwrap();
+        vault.remove(&"gone".to_string()).unwrap();
+        vault.link(&key, "self", &key).unwrap();
+        let created_at = vault.entry_meta(&key).unwrap().created_at;
+        vault.flush().unwrap();
+        drop(vault);
+
+        // A torn record from a crash mid-append is dropped on open
+        let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+        std::io::Write::write_all(&mut file, b"{\"Put\":{\"key\":\"half").unwrap();
+        drop(file);
+
+        let mut vault: Vault<String, DiskBackend<String>> =
+            Vault::open_disk(path, "Disk".to_string(), MemorySize::KB(1)).unwrap();
+        assert_eq!(vault.len(), 1);
+        assert_eq!(vault.current_usage(), 4);
+        assert_eq!(vault.entry_meta(&key).unwrap().created_at, created_at);
+        assert_eq!(vault.neighbors(&key).len(), 1);
+        assert_eq!(
+            vault.get(&key),
+            Some(&Resource::TextMessage("kept".to_string()))
+        );
+
+        let before = vault.disk().file_size();
+        assert!(vault.disk().dead_bytes() > 0);
+        vault.compact().unwrap();
+        assert!(vault.disk().file_size() < before);
+        assert_eq!(vault.disk().dead_bytes(), 0);
+        vault
+            .add("more".to_string(), Resource::SensorData(3.0))
+            .unwrap();
+        assert_eq!(
+            vault.get(&key),
+            Some(&Resource::TextMessage("kept".to_string()))
+        );
+        assert_eq!(
+            vault.get(&"more".to_string()),
+            Some(&Resource::SensorData(3.0))
+        );
+
+        let _ = std::fs::remove_file(path);
+        let _ = std::fs::remove_file(meta_path);
+        let _ = std::fs::remove_file(edges_path);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_backend_suite() {
+        let path = "/tmp/test_vault_sqlite_suite.db";
+        let _ = std::fs::remove_file(path);
+        let mut vault = Vault::open_sqlite(path, "SQLite".to_string(), MemorySize::KB(1)).unwrap();
+        run_backend_suite(&mut vault);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_reopen_cache_and_read_errors() {
+        let path = "/tmp/test_vault_sqlite_reopen.db";
+        let _ = std::fs::remove_file(path);
+        let mut vault = Vault::open_sqlite(path, "Ops".to_string(), MemorySize::MB(1)).unwrap();
+        vault
+            .transaction(|tx| {
+                for i in 0..5000 {
+                    tx.add(format!("k{}", i), Resource::SensorData(i as f64))?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        drop(vault);
+
+        // Reopening keeps the stored settings rather than the ones passed in
+        let mut vault = Vault::open_sqlite(path, "Other".to_string(), MemorySize::KB(1)).unwrap();
+        assert_eq!(vault.location, "Ops");
+        assert_eq!(vault.storage_capacity, MemorySize::MB(1));
+
+        // A full scan pages everything in; the next write lets go of it
+        assert_eq!(vault.iter().count(), 5000);
+        vault
+            .update(&"k0".to_string(), Resource::SensorData(-1.0))
+            .unwrap();
+        assert!(vault.resources.cached_entries() < 10);
+        vault.flush().unwrap();
+
+        // A row damaged behind the vault's back reads as missing, and flush says why
+        let conn = rusqlite::Connection::open(path).unwrap();
+        conn.execute(
+            "UPDATE entries SET resource = 'garbage' WHERE label = 'k1'",
+            [],
+        )
+        .unwrap();
+        assert_eq!(vault.get(&"k1".to_string()), None);
+        assert!(vault.flush().is_err());
+        vault.flush().unwrap();
+
+        drop(vault);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_tags_and_migration() {
+        let json = "/tmp/test_vault_sqlite_migrate.json";
+        let db = "/tmp/test_vault_sqlite_migrate.db";
+        let _ = std::fs::remove_file(db);
+        let key = "note".to_string();
+
+        let mut original = Vault::<String>::new("Ops".to_string(), MemorySize::KB(1));
+        original
+            .add(key.clone(), Resource::TextMessage("hello".to_string()))
+            .unwrap();
+        original
+            .add("t".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        original.link(&key, "about", &"t".to_string()).unwrap();
+        original.save_to_file(json).unwrap();
+        assert_eq!(migrate_json_to_sqlite::<String>(json, db).unwrap(), 2);
+        // A refused migration leaves the existing vault's settings alone
+        let other = "/tmp/test_vault_sqlite_migrate_other.json";
+        Vault::<String>::empty("Other".to_string(), MemorySize::MB(1))
+            .save_to_file(other)
+            .unwrap();
+        assert!(migrate_json_to_sqlite::<String>(other, db).is_err());
+        let _ = std::fs::remove_file(other);
+
+        let mut vault = Vault::<String, SqliteBackend<String>>::load_sqlite(db).unwrap();
+        assert_eq!(vault.location, "Ops");
+        assert_eq!(vault.current_usage(), 13);
+        assert_eq!(vault.neighbors(&key)[0].relation, "about");
+        assert_eq!(
+            vault.entry_meta(&key).unwrap().created_at,
+            original.entry_meta(&key).unwrap().created_at
+        );
+        assert!(vault
+            .add("big".to_string(), Resource::TextMessage("x".repeat(1024)))
+            .is_err());
+
+        vault.add_tag(&key, "greeting").unwrap();
+        vault.add_tag(&"t".to_string(), "greeting").unwrap();
+        assert!(vault.add_tag(&"missing".to_string(), "x").is_err());
+        assert_eq!(vault.tags(&key).unwrap(), vec!["greeting"]);
+        let hits = vault
+            .recall_tagged("hello", &["greeting"], &RecallOptions::default())
+            .unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].key, key);
+        vault.remove(&"t".to_string()).unwrap();
+        assert_eq!(vault.find_by_tag("greeting").unwrap(), vec![key.clone()]);
+        assert!(vault.remove_tag(&key, "greeting").unwrap());
+        // Metadata is stored with each write, without waiting for `flush`
+        vault
+            .set_ttl(&key, Some(std::time::Duration::from_secs(3600)))
+            .unwrap();
+        let expires_at = vault.entry_meta(&key).unwrap().expires_at;
+        drop(vault);
+        let vault = Vault::<String, SqliteBackend<String>>::load_sqlite(db).unwrap();
+        assert_eq!(vault.entry_meta(&key).unwrap().expires_at, expires_at);
+        drop(vault);
+
+        assert_eq!(migrate_sqlite_to_json::<String>(db, json).unwrap(), 1);
+        let back = Vault::<String>::load_from_file(json).unwrap();
+        assert_eq!(back.get(&key), original.get(&key));
+
+        let _ = std::fs::remove_file(json);
+        let _ = std::fs::remove_file(db);
+    }
+
+    #[test]
+    fn test_packed_vault() {
+        let path = "/tmp/test_vault_packed.pack";
+        let mut vault = Vault::<String>::new("Packed".to_string(), MemorySize::KB(1));
+        vault
+            .add(
+                "agent/goal".to_string(),
+                Resource::TextMessage("Find the exit".to_string()),
+            )
+            .unwrap();
+        vault
+            .add("agent/temp".to_string(), Resource::SensorData(21.5))
+            .unwrap();
+        vault
+            .add(
+                "boot".to_string(),
+                Resource::SystemLogs(vec!["exit code 0".to_string()]),
+            )
+            .unwrap();
+        vault.pack(path).unwrap();
+
+        let packed = PackedVault::open(path).unwrap();
+        assert_eq!(packed.location, "Packed");
+        assert_eq!(packed.len(), 3);
+        assert_eq!(packed.get("agent/temp"), Some(Resource::SensorData(21.5)));
+        assert_eq!(packed.get("agent"), None);
+        let scanned: Vec<&str> = packed.scan_prefix("agent/").map(|(k, _)| k).collect();
+        assert_eq!(scanned, vec!["agent/goal", "agent/temp"]);
+        let found: Vec<&str> = packed.search("EXIT").into_iter().map(|(k, _)| k).collect();
+        assert_eq!(found, vec!["agent/goal", "boot"]);
+        packed.verify().unwrap();
+        drop(packed);
+
+        // A flipped byte is caught by that entry's checksum instead of yielding
+        // garbage; the other entries still read, and `verify` reports it
+        let mut bytes = std::fs::read(path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        std::fs::write(path, &bytes).unwrap();
+        let packed = PackedVault::open(path).unwrap();
+        assert_eq!(packed.get("boot"), None);
+        assert_eq!(packed.get("agent/temp"), Some(Resource::SensorData(21.5)));
+        assert!(packed.verify().is_err());
+        drop(packed);
+        std::fs::write(path, &bytes[..10]).unwrap();
+        assert!(PackedVault::open(path).is_err());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_retention_scoring() {
+        let hour = std::time::Duration::from_secs(3600);
+        let exp = DecayFunction::Exponential { half_life: hour };
+        assert_eq!(exp.recency(std::time::Duration::ZERO), 1.0);
+        assert!((exp.recency(hour) - 0.5).abs() < 1e-9);
+        let power = DecayFunction::PowerLaw {
+            scale: hour,
+            exponent: 1.0,
+        };
+        assert!((power.recency(hour) - 0.5).abs() < 1e-9);
+        assert!(power.recency(hour * 10) > exp.recency(hour * 10));
+
+        let mut vault = Vault::<String>::new("Recall".to_string(), MemorySize::KB(1));
+        for key in ["a", "b", "c"] {
+            vault
+                .add(key.to_string(), Resource::SensorData(1.0))
+                .unwrap();
+        }
+        vault.set_importance(&"b".to_string(), 0.9).unwrap();
+        vault.reinforce(&"c".to_string()).unwrap();
+        vault.reinforce(&"c".to_string()).unwrap();
+        assert!(vault.set_importance(&"a".to_string(), 1.5).is_err());
+        assert!(vault.reinforce(&"missing".to_string()).is_err());
+        assert_eq!(vault.entry_meta(&"c".to_string()).unwrap().access_count, 2);
+
+        let top: Vec<&String> = vault
+            .top_memories(2)
+            .into_iter()
+            .map(|(k, _, _)| k)
+            .collect();
+        assert_eq!(top, vec!["b", "c"]);
+        assert!(
+            vault.retention_score(&"b".to_string()).unwrap()
+                > vault.retention_score(&"a".to_string()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_eviction_by_retention() {
+        let mut vault = Vault::<String>::new("Evict".to_string(), MemorySize::KB(1));
+        let rx = vault.watch(EventFilter::all());
+        let note = |n: usize| Resource::TextMessage("x".repeat(n));
+        vault.add("old".to_string(), note(400)).unwrap();
+        vault.add("kept".to_string(), note(400)).unwrap();
+        vault.set_importance(&"kept".to_string(), 1.0).unwrap();
+
+        // Without eviction a full vault rejects the write
+        assert!(vault.add("new".to_string(), note(400)).is_err());
+
+        vault.set_eviction(true);
+        vault.add("new".to_string(), note(400)).unwrap();
+        assert!(!vault.contains_key(&"old".to_string()));
+        assert!(vault.contains_key(&"kept".to_string()));
+        assert_eq!(vault.current_usage(), 800);
+        assert!(rx.try_iter().any(|event| matches!(
+            event,
+            VaultEvent::Evicted { ref key, .. } if key == "old"
+        )));
+
+        // Nothing is evicted for a resource that could never fit
+        assert!(vault.add("huge".to_string(), note(2000)).is_err());
+        assert_eq!(vault.len(), 2);
+        let results = vault.add_many(vec![("x".to_string(), note(300))]);
+        assert!(results[0].is_ok());
+        assert_eq!(vault.len(), 2);
+    }
+
+    #[test]
+    fn test_memory_tiers_demotion_and_promotion() {
+        let mut memory = MemorySystem::<String>::new(
+            "Agent",
+            TierCapacities {
+                working: MemorySize::KB(1),
+                ..Default::default()
+            },
+        )
+        .with_rules(PromotionRules {
+            promote_after_recalls: 2,
+            ..Default::default()
+        });
+        let moves = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let log = moves.clone();
+        memory.on_consolidate(move |key: &String, _, from, to| {
+            log.lock()
+                .unwrap()
+                .push(format!("{} {}->{}", key, from, to));
+            None
+        });
+
+        let note = |text: &str| Resource::TextMessage(format!("{:<500}", text));
+        memory
+            .remember(Tier::Working, "first".to_string(), note("kettle on"))
+            .unwrap();
+        memory
+            .remember(Tier::Working, "second".to_string(), note("door open"))
+            .unwrap();
+        assert!(memory
+            .remember(Tier::Semantic, "first".to_string(), note("dup"))
+            .is_err());
+
+        memory
+            .tier_mut(Tier::Working)
+            .set_importance(&"second".to_string(), 0.9)
+            .unwrap();
+
+        // Working memory is full, so the weakest entry drops to episodic
+        memory
+            .remember(Tier::Working, "third".to_string(), note("lights off"))
+            .unwrap();
+        assert_eq!(memory.locate(&"first".to_string()), Some(Tier::Episodic));
+        assert_eq!(
+            memory
+                .tier(Tier::Episodic)
+                .entry_meta(&"first".to_string())
+                .unwrap()
+                .importance,
+            0.5
+        );
+
+        // Recalling an episode often enough turns it into a semantic fact
+        let hits = memory.recall("kettle", 10).unwrap();
+        assert_eq!(hits[0].tier, Tier::Episodic);
+        let hits = memory.recall("kettle", 10).unwrap();
+        assert_eq!(hits[0].tier, Tier::Semantic);
+        assert_eq!(hits[0].key, "first");
+        assert_eq!(
+            *moves.lock().unwrap(),
+            vec!["first working->episodic", "first episodic->semantic"]
+        );
+
+        let all = memory.recall("", 10).unwrap();
+        assert_eq!(all.len(), 3);
+        assert!(all.windows(2).all(|pair| pair[0].score >= pair[1].score));
+        assert_eq!(
+            memory.forget(&"third".to_string()).unwrap(),
+            note("lights off")
+        );
+        assert_eq!(memory.get(&"third".to_string()), None);
+    }
+
+    #[test]
+    fn test_memory_tiers_consolidation_callback() {
+        let mut memory = MemorySystem::<String>::new("Agent", TierCapacities::default())
+            .with_rules(PromotionRules {
+                working_idle: std::time::Duration::ZERO,
+                ..Default::default()
+            });
+        memory.on_consolidate(|_, resource, _, to| match (resource, to) {
+            (Resource::TextMessage(text), Tier::Episodic) => {
+                Some(Resource::TextMessage(format!("episode: {}", text)))
+            }
+            _ => None,
+        });
+        memory
+            .remember(
+                Tier::Working,
+                "scratch".to_string(),
+                Resource::TextMessage("draft".to_string()),
+            )
+            .unwrap();
+
+        // An entry pinned by a Restrict edge stays where it is, in one tier only
+        let pin = "pin".to_string();
+        let scratch = "scratch".to_string();
+        let working = memory.tier_mut(Tier::Working);
+        working.add(pin.clone(), Resource::SensorData(1.0)).unwrap();
+        working
+            .link_with(&pin, "needs", &scratch, OnDelete::Restrict)
+            .unwrap();
+        assert!(memory.move_to(&scratch, Tier::Episodic).is_err());
+        assert_eq!(memory.locate(&scratch), Some(Tier::Working));
+        assert!(!memory.tier(Tier::Episodic).contains_key(&scratch));
+        memory.forget(&pin).unwrap();
+
+        let moved = memory.consolidate().unwrap();
+        assert_eq!(moved, vec![("scratch".to_string(), Tier::Episodic)]);
+        assert_eq!(
+            memory.get(&"scratch".to_string()),
+            Some((
+                Tier::Episodic,
+                &Resource::TextMessage("episode: draft".to_string())
+            ))
+        );
+    }
+
+    // Deterministic stand-in for a model-backed summarizer
+    struct CountingSummarizer;
+
+    impl Summarizer for CountingSummarizer {
+        fn summarize(&mut self, previous: Option<&str>, turns: &[Turn]) -> String {
+            let before: usize = previous
+                .and_then(|text| text.split_whitespace().nth(1)?.parse().ok())
+                .unwrap_or(0);
+            format!("summarized {} turns", before + turns.len())
+        }
+    }
+
+    #[test]
+    fn test_session_turns_and_window() {
+        let vault = Vault::<String>::new("Chat".to_string(), MemorySize::KB(16));
+        let mut store = SessionStore::new(vault, Budget::Bytes(1024));
+        store.push("s1", Role::User, "hello there").unwrap();
+        store
+            .push("s1", Role::Assistant, "hi, how can I help?")
+            .unwrap();
+        store.push("s1", Role::Tool, "weather: sunny").unwrap();
+        store.push("s2", Role::User, "other chat").unwrap();
+        assert!(store.push("a/b", Role::User, "bad id").is_err());
+
+        let turns = store.turns("s1");
+        assert_eq!(turns.len(), 3);
+        assert_eq!(turns[1].role, Role::Assistant);
+        assert_eq!(turns[2].seq, 2);
+        assert_eq!(store.sessions(), vec!["s1", "s2"]);
+
+        let window = store.window("s1", 10, Budget::Tokens(6));
+        let contents: Vec<&str> = window.iter().map(|t| t.content.as_str()).collect();
+        assert_eq!(contents, vec!["weather: sunny"]);
+        assert_eq!(store.window("s1", 2, Budget::Bytes(1024)).len(), 2);
+
+        assert_eq!(store.clear("s1").unwrap(), 3);
+        assert!(store.turns("s1").is_empty());
+        assert_eq!(store.into_vault().len(), 1);
+    }
+
+    #[test]
+    fn test_session_overflow_summarizes() {
+        let vault = Vault::<String>::new("Chat".to_string(), MemorySize::KB(16));
+        let mut store =
+            SessionStore::new(vault, Budget::Tokens(100)).with_summarizer(CountingSummarizer);
+        store.set_budget("short", Budget::Tokens(8));
+        for i in 0..4 {
+            store
+                .push("short", Role::User, &format!("turn {} says hi", i))
+                .unwrap();
+        }
+        // 16 words against a budget of 8: the oldest turns are folded away
+        let seqs: Vec<u64> = store.turns("short").iter().map(|t| t.seq).collect();
+        assert_eq!(seqs, vec![3]);
+        assert_eq!(store.summary("short"), Some("summarized 3 turns"));
+
+        for i in 4..6 {
+            store
+                .push("short", Role::Assistant, &format!("reply {} ok", i))
+                .unwrap();
+        }
+        assert_eq!(store.summary("short"), Some("summarized 5 turns"));
+        assert_eq!(store.turns("short").last().unwrap().seq, 5);
+
+        // Without a summarizer the overflow is dropped
+        let vault = Vault::<String>::new("Chat".to_string(), MemorySize::KB(16));
+        let mut plain = SessionStore::new(vault, Budget::Bytes(10));
+        plain.push("s", Role::User, "0123456789").unwrap();
+        plain.push("s", Role::User, "abc").unwrap();
+        assert_eq!(plain.turns("s").len(), 1);
+        assert_eq!(plain.summary("s"), None);
+    }
+
+    // Fails the test if asked to summarize nothing
+    struct StrictSummarizer;
+
+    impl Summarizer for StrictSummarizer {
+        fn summarize(&mut self, _: Option<&str>, turns: &[Turn]) -> String {
+            assert!(!turns.is_empty(), "summarizer called without turns");
+            "a summary five words long".to_string()
+        }
+    }
+
+    #[test]
+    fn test_session_push_is_all_or_nothing() {
+        // A turn that a Restrict edge pins cannot be folded away
+        let first = "session/s/0000000000/user".to_string();
+        let mut vault = Vault::<String>::new("Chat".to_string(), MemorySize::KB(16));
+        vault
+            .add(first.clone(), Resource::TextMessage("one two three".into()))
+            .unwrap();
+        vault
+            .add("pin".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        vault
+            .link_with(&"pin".to_string(), "keeps", &first, OnDelete::Restrict)
+            .unwrap();
+        let mut store =
+            SessionStore::new(vault, Budget::Tokens(4)).with_summarizer(StrictSummarizer);
+
+        // Folding fails, so the new turn is not stored either
+        assert!(store.push("s", Role::User, "four five").is_err());
+        assert_eq!(store.turns("s").len(), 1);
+        assert_eq!(store.summary("s"), None);
+
+        // When the turns fit and only the summary is over, there is nothing
+        // to fold; the summary is cut down without asking for a new one
+        let vault = Vault::<String>::new("Chat".to_string(), MemorySize::KB(16));
+        let mut store =
+            SessionStore::new(vault, Budget::Tokens(6)).with_summarizer(StrictSummarizer);
+        store.push("s", Role::User, "a b c d e f").unwrap();
+        store.push("s", Role::User, "g").unwrap();
+        assert_eq!(store.summary("s"), Some("a summary five words long"));
+        store.push("s", Role::User, "h").unwrap();
+        assert_eq!(store.turns("s").len(), 2);
+        assert_eq!(store.summary("s"), Some("a summary five words"));
+    }
+
+    #[test]
+    fn test_tokenizers() {
+        assert_eq!(WhitespaceTokenizer.count("the quick  brown fox"), 4);
+        assert_eq!(ApproxTokenizer.count("a tokenization!"), 1 + 3 + 1);
+        assert_eq!(ApproxTokenizer.count(""), 0);
+    }
+
+    #[test]
+    fn test_context_builder() {
+        let mut vault = Vault::<String>::new("Prompt".to_string(), MemorySize::KB(4));
+        let text = |s: &str| Resource::TextMessage(s.to_string());
+        vault
+            .add("fact/alice".to_string(), text("Alice works at Acme"))
+            .unwrap();
+        vault
+            .add("fact/acme".to_string(), text("Acme builds rockets"))
+            .unwrap();
+        vault
+            .add("copy/acme".to_string(), text("acme builds rockets "))
+            .unwrap();
+        vault
+            .add("fact/bob".to_string(), text("Bob likes tea"))
+            .unwrap();
+        vault
+            .add("pinned/rule".to_string(), text("Answer briefly"))
+            .unwrap();
+
+        let context = ContextBuilder::new("where does alice work", 100)
+            .tokenizer(WhitespaceTokenizer)
+            .pin("pinned/rule".to_string())
+            .recent(0)
+            .build(&vault);
+        assert_eq!(context.keys, vec!["pinned/rule", "fact/alice"]);
+        assert_eq!(
+            context.text,
+            "[pinned/rule] Answer briefly\n[fact/alice] Alice works at Acme"
+        );
+        assert_eq!(context.tokens, 3 + 5);
+
+        // Duplicated content is included once, and the budget is respected
+        let context = ContextBuilder::new("acme rockets", 8)
+            .tokenizer(WhitespaceTokenizer)
+            .recent(0)
+            .build(&vault);
+        assert_eq!(context.keys.len(), 1);
+        assert!(context.keys[0].ends_with("/acme"));
+        assert!(context.tokens <= 8);
+
+        // Recent memories are offered even without a keyword match
+        let context = ContextBuilder::new("unrelated", 100).build(&vault);
+        assert_eq!(context.keys.len(), 4);
+    }
+
+    fn graph_vault() -> Vault<String> {
+        let mut vault = Vault::<String>::new("Graph".to_string(), MemorySize::KB(4));
+        for key in ["alice", "bob", "acme", "berlin", "germany"] {
+            vault
+                .add(key.to_string(), Resource::TextMessage(key.to_string()))
+                .unwrap();
+        }
+        vault
+    }
+
+    #[test]
+    fn test_graph_queries_and_persistence() {
+        let key = |k: &str| k.to_string();
+        let mut vault = graph_vault();
+        vault.link(&key("alice"), "works_at", &key("acme")).unwrap();
+        vault.link(&key("bob"), "knows", &key("alice")).unwrap();
+        vault
+            .link(&key("acme"), "based_in", &key("berlin"))
+            .unwrap();
+        vault.link(&key("berlin"), "in", &key("germany")).unwrap();
+        assert!(matches!(
+            vault.link(&key("alice"), "knows", &key("nobody")),
+            Err(VaultError::ResourceNotFound(_))
+        ));
+
+        assert_eq!(vault.neighbors(&key("alice")).len(), 1);
+        assert_eq!(vault.referrers(&key("alice"))[0].from, "bob");
+
+        let reached: Vec<(&String, usize)> = vault.traverse(&key("bob"), 2);
+        assert_eq!(reached, vec![(&key("alice"), 1), (&key("acme"), 2)]);
+
+        let path = vault.find_path(&key("bob"), &key("germany")).unwrap();
+        let hops: Vec<&str> = path.iter().map(|edge| edge.relation.as_str()).collect();
+        assert_eq!(hops, vec!["knows", "works_at", "based_in", "in"]);
+        assert!(vault.find_path(&key("germany"), &key("bob")).is_none());
+
+        let file = "/tmp/test_vault_graph.json";
+        vault.save_to_file(file).unwrap();
+        let mut loaded = Vault::<String>::load_from_file(file).unwrap();
+        assert_eq!(loaded.edges().count(), 4);
+        assert!(loaded.unlink(&key("bob"), "knows", &key("alice")).unwrap());
+        assert!(!loaded.unlink(&key("bob"), "knows", &key("alice")).unwrap());
+        assert!(loaded.find_path(&key("bob"), &key("germany")).is_none());
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[test]
+    fn test_graph_removal_policies() {
+        let key = |k: &str| k.to_string();
+        let path = "/tmp/test_vault_graph.wal";
+        let _ = std::fs::remove_file(path);
+        let mut vault = graph_vault();
+        vault.open_journal(path).unwrap();
+
+        // Restrict: the target stays until the referring entry is gone
+        vault
+            .link_with(&key("alice"), "works_at", &key("acme"), OnDelete::Restrict)
+            .unwrap();
+        assert!(matches!(
+            vault.remove(&key("acme")),
+            Err(VaultError::InvalidInput(_))
+        ));
+        let results = vault.remove_many(&[key("acme"), key("alice")]);
+        assert!(results.iter().all(|result| result.is_ok()));
+
+        // Cascade: removing the target takes the referring entry with it
+        vault
+            .link_with(&key("berlin"), "in", &key("germany"), OnDelete::Cascade)
+            .unwrap();
+        // Nullify: only the edge goes away
+        vault.link(&key("bob"), "lives_in", &key("berlin")).unwrap();
+        vault.remove(&key("germany")).unwrap();
+        assert!(!vault.contains_key(&key("berlin")));
+        assert!(vault.contains_key(&key("bob")));
+        assert!(vault.neighbors(&key("bob")).is_empty());
+        assert_eq!(vault.len(), 1);
+
+        // Replaying the journal reproduces links and cascades
+        let mut restored = graph_vault();
+        restored.open_journal(path).unwrap();
+        assert_eq!(restored.keys().collect::<Vec<_>>(), vec!["bob"]);
+        assert_eq!(restored.edges().count(), 0);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_restrict_on_cascade_dependent() {
+        let key = |k: &str| k.to_string();
+        let mut vault = graph_vault();
+        // Removing germany would take berlin along, which alice restricts
+        vault
+            .link_with(&key("berlin"), "in", &key("germany"), OnDelete::Cascade)
+            .unwrap();
+        vault
+            .link_with(&key("alice"), "visits", &key("berlin"), OnDelete::Restrict)
+            .unwrap();
+
+        assert!(matches!(
+            vault.remove(&key("germany")),
+            Err(VaultError::InvalidInput(e)) if e.contains("'berlin'")
+        ));
+        assert!(vault.remove_many(&[key("germany")])[0].is_err());
+        assert!(vault.transaction(|tx| tx.remove(&key("germany"))).is_err());
+        assert_eq!(vault.len(), 5);
+        // Unless the restricting entry goes too
+        let results = vault.remove_many(&[key("germany"), key("alice")]);
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert!(!vault.contains_key(&key("berlin")));
+
+        // Eviction passes over an entry whose dependents are pinned
+        let note = |n: usize| Resource::TextMessage("x".repeat(n));
+        let mut vault = Vault::<String>::new("Evict".to_string(), MemorySize::KB(1));
+        vault.add(key("old"), note(400)).unwrap();
+        vault.add(key("dep"), note(10)).unwrap();
+        vault.add(key("pin"), note(10)).unwrap();
+        vault
+            .link_with(&key("dep"), "of", &key("old"), OnDelete::Cascade)
+            .unwrap();
+        vault
+            .link_with(&key("pin"), "holds", &key("dep"), OnDelete::Restrict)
+            .unwrap();
+        vault.set_eviction(true);
+        assert!(vault.add(key("new"), note(700)).is_err());
+        assert!(vault.contains_key(&key("old")) && vault.contains_key(&key("dep")));
+    }
+
+    #[test]
+    fn test_dedup_policies_on_add() {
+        let text = |t: &str| Resource::TextMessage(t.to_string());
+        let mut vault = Vault::<String>::new("Dedup".to_string(), MemorySize::KB(4));
+        vault
+            .add("fact".to_string(), text("Alice works at Acme in Berlin"))
+            .unwrap();
+        vault
+            .add("reading".to_string(), Resource::SensorData(21.5))
+            .unwrap();
+        vault.set_dedup(Some(DedupConfig::default()));
+
+        // Exact copies of any kind, and reworded text, are caught
+        assert!(matches!(
+            vault.add("again".to_string(), Resource::SensorData(21.5)),
+            Err(VaultError::Duplicate { .. })
+        ));
+        assert!(matches!(
+            vault.add("reworded".to_string(), text("alice works at ACME, in Berlin.")),
+            Err(VaultError::Duplicate { ref existing, .. }) if existing == "fact"
+        ));
+        vault
+            .add("other".to_string(), text("Bob prefers tea over coffee"))
+            .unwrap();
+
+        vault.set_dedup(Some(DedupConfig {
+            policy: DedupPolicy::Merge,
+            near_threshold: None,
+        }));
+        vault
+            .add("copy".to_string(), text("Alice works at Acme in Berlin"))
+            .unwrap();
+        assert!(!vault.contains_key(&"copy".to_string()));
+        assert_eq!(vault.entry_meta(&"fact".to_string()).unwrap().duplicates, 1);
+        // Exact-only matching lets rewordings through
+        vault
+            .add("loose".to_string(), text("alice works at acme in berlin"))
+            .unwrap();
+
+        vault.set_dedup(Some(DedupConfig {
+            policy: DedupPolicy::Link,
+            ..Default::default()
+        }));
+        vault.remove(&"loose".to_string()).unwrap();
+        vault
+            .add("linked".to_string(), text("Bob prefers tea over coffee!"))
+            .unwrap();
+        let edges = vault.neighbors(&"linked".to_string());
+        assert_eq!(edges[0].relation, DUPLICATE_OF);
+        assert_eq!(edges[0].to, "other");
+    }
+
+    #[test]
+    fn test_dedupe_existing_entries() {
+        let text = |t: &str| Resource::TextMessage(t.to_string());
+        let mut vault = Vault::<String>::new("Dedup".to_string(), MemorySize::KB(4));
+        vault
+            .add("a".to_string(), text("The kettle is in the kitchen"))
+            .unwrap();
+        vault
+            .add("b".to_string(), text("the kettle is in the kitchen."))
+            .unwrap();
+        vault
+            .add("c".to_string(), text("The kettle is in the kitchen"))
+            .unwrap();
+        vault
+            .add("d".to_string(), text("Meeting moved to Friday"))
+            .unwrap();
+
+        let exact = vault.duplicates(None);
+        assert_eq!(exact.len(), 1);
+        assert_eq!((exact[0].key.as_str(), exact[0].similarity), ("c", 1.0));
+
+        let near = vault.duplicates(Some(0.8));
+        let keys: Vec<&str> = near.iter().map(|d| d.key.as_str()).collect();
+        assert_eq!(keys, vec!["b", "c"]);
+        assert!(near.iter().all(|d| d.original == "a"));
+
+        vault.dedupe(Some(0.8)).unwrap();
+        assert_eq!(vault.len(), 2);
+        assert_eq!(vault.entry_meta(&"a".to_string()).unwrap().duplicates, 2);
+    }
+
+    // Two-dimensional stand-in for a real model: counts of "cat" and "dog"
+    struct PetEmbedder;
+
+    impl Embedder for PetEmbedder {
+        fn dimensions(&self) -> usize {
+            2
+        }
+
+        fn embed(&self, text: &str) -> Vec<f32> {
+            vec![
+                text.matches("cat").count() as f32,
+                text.matches("dog").count() as f32,
+            ]
+        }
+    }
+
+    #[test]
+    fn test_embeddings_follow_entries() {
+        let text = |t: &str| Resource::TextMessage(t.to_string());
+        let key = |k: &str| k.to_string();
+        let mut vault = Vault::<String>::new("Vectors".to_string(), MemorySize::KB(4));
+        vault
+            .add(key("kettle"), text("The kettle is on the kitchen counter"))
+            .unwrap();
+        vault.add(key("temp"), Resource::SensorData(21.0)).unwrap();
+        assert!(vault.nearest("kettle", 1).is_empty());
+
+        vault.set_embedder(HashedEmbedder::default());
+        vault
+            .add(key("train"), text("The train to Berlin leaves at noon"))
+            .unwrap();
+        assert_eq!(vault.embedding(&key("kettle")).unwrap().len(), 256);
+        assert!(vault.embedding(&key("temp")).is_none());
+        let hits = vault.nearest("where is the kettle in the kitchen", 2);
+        assert_eq!(hits[0].0, "kettle");
+        assert!(hits[0].1 > hits[1].1);
+
+        // Updates re-embed, removals forget
+        vault
+            .update(&key("kettle"), text("Trains to Berlin run hourly"))
+            .unwrap();
+        assert_eq!(vault.nearest("berlin trains", 1)[0].0, "kettle");
+        vault.remove(&key("train")).unwrap();
+        assert_eq!(vault.nearest("berlin", 5).len(), 1);
+
+        vault.set_embedder(PetEmbedder);
+        vault.add(key("pets"), text("cat cat dog")).unwrap();
+        assert_eq!(vault.embedding(&key("pets")), Some(&[2.0, 1.0][..]));
+        assert_eq!(vault.nearest("a cat", 1)[0].0, "pets");
+        assert!((cosine_similarity(&[1.0, 0.0], &[0.0, 1.0])).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_hybrid_recall() {
+        let text = |t: &str| Resource::TextMessage(t.to_string());
+        let key = |k: &str| k.to_string();
+        let mut vault = Vault::<String>::new("Recall".to_string(), MemorySize::KB(4));
+        vault.set_embedder(HashedEmbedder::default());
+        vault
+            .add(key("kettle"), text("The kettle is on the kitchen counter"))
+            .unwrap();
+        vault
+            .add(
+                key("kettle2"),
+                text("The kettle is on the kitchen counter again"),
+            )
+            .unwrap();
+        vault
+            .add(key("mug"), text("Mugs are in the kitchen cupboard"))
+            .unwrap();
+        vault
+            .add(key("train"), text("The train to Berlin leaves at noon"))
+            .unwrap();
+
+        let options = RecallOptions {
+            diversity: None,
+            ..Default::default()
+        };
+        let hits = vault.recall("kettle kitchen", &options);
+        assert_eq!(hits[0].key, "kettle");
+        assert!(!hits.iter().any(|hit| hit.key == "train"));
+        let explanation = &hits[0].explanation;
+        assert_eq!(explanation.keyword.unwrap().rank, 1);
+        assert!(explanation.vector.is_some() && explanation.recency.is_some());
+        assert!(explanation.to_string().contains("keyword"));
+
+        // Diversification moves the near-copy below a different hit
+        let options = RecallOptions {
+            diversity: Some(0.3),
+            ..Default::default()
+        };
+        let keys: Vec<String> = vault
+            .recall("kettle kitchen", &options)
+            .into_iter()
+            .map(|hit| hit.key)
+            .collect();
+        assert_eq!(keys, vec!["kettle", "mug", "kettle2"]);
+
+        let options = RecallOptions {
+            fusion: Fusion::Weighted,
+            within: Some(HashSet::from([key("mug"), key("train")])),
+            limit: 1,
+            ..Default::default()
+        };
+        let hits = vault.recall("kitchen", &options);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].key, "mug");
+    }
+
+    #[test]
+    fn test_resp_commands() {
+        use resp::{execute, Session};
+
+        let mut vault: Vault<String> = Vault::empty("resp".to_string(), MemorySize::KB(1));
+        let mut session = Session::default();
+        let run = |vault: &mut Vault<String>, session: &mut Session, line: &str| {
+            let args: Vec<Vec<u8>> = line.split(' ').map(|w| w.as_bytes().to_vec()).collect();
+            let mut out = Vec::new();
+            execute(vault, session, &args).encode(session.protocol, &mut out);
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(run(&mut vault, &mut session, "SET a hello"), "+OK\r\n");
+        assert_eq!(run(&mut vault, &mut session, "SET a hi XX"), "+OK\r\n");
+        assert_eq!(run(&mut vault, &mut session, "SET b x NX"), "+OK\r\n");
+        assert_eq!(run(&mut vault, &mut session, "SET b y NX"), "$-1\r\n");
+        assert_eq!(run(&mut vault, &mut session, "get a"), "$2\r\nhi\r\n");
+        assert_eq!(run(&mut vault, &mut session, "EXISTS a b c"), ":2\r\n");
+        assert_eq!(run(&mut vault, &mut session, "TTL a"), ":-1\r\n");
+        assert_eq!(run(&mut vault, &mut session, "EXPIRE a 100"), ":1\r\n");
+        assert_eq!(run(&mut vault, &mut session, "TTL a"), ":100\r\n");
+        assert_eq!(run(&mut vault, &mut session, "TTL c"), ":-2\r\n");
+        assert_eq!(
+            run(&mut vault, &mut session, "KEYS [ab]"),
+            "*2\r\n$1\r\na\r\n$1\r\nb\r\n"
+        );
+        assert_eq!(
+            run(&mut vault, &mut session, "SCAN 0 COUNT 1"),
+            "*2\r\n$1\r\n1\r\n*1\r\n$1\r\na\r\n"
+        );
+
+        // Capacity is enforced like Redis's maxmemory
+        let reply = run(
+            &mut vault,
+            &mut session,
+            &format!("SET big {}", "x".repeat(1100)),
+        );
+        assert!(reply.starts_with("-OOM "));
+        assert_eq!(run(&mut vault, &mut session, "DEL a b c"), ":2\r\n");
+
+        // RESP3 after HELLO 3: maps and a distinct null
+        assert!(run(&mut vault, &mut session, "HELLO 3").starts_with("%7\r\n"));
+        assert_eq!(run(&mut vault, &mut session, "GET a"), "_\r\n");
+        vault
+            .add("n".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        assert!(run(&mut vault, &mut session, "GET n").starts_with("-WRONGTYPE"));
+        assert!(run(&mut vault, &mut session, "NOPE").starts_with("-ERR unknown command"));
+        assert_eq!(
+            run(&mut vault, &mut session, "SET a x EX 9223372036854775807"),
+            "-ERR invalid expire time in 'set' command\r\n"
+        );
+    }
+
+    #[test]
+    fn test_resp_request_limits() {
+        use resp::read_command;
+
+        let read = |bytes: &[u8]| read_command(&mut std::io::Cursor::new(bytes.to_vec()));
+        assert_eq!(
+            read(b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n").unwrap(),
+            Some(vec![b"GET".to_vec(), b"a".to_vec()])
+        );
+        // Sizes a client claims are checked before anything is allocated for them
+        for request in [
+            &b"*9999999999\r\n"[..],
+            b"*1\r\n$18446744073709551615\r\n",
+            b"*1\r\n$536870913\r\n",
+        ] {
+            let error = read(request).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+        assert!(read(&vec![b'x'; 70 * 1024]).is_err());
+        // A bulk string cut short is the end of the stream, not an empty argument
+        assert!(read(b"*1\r\n$10\r\nabc").is_err());
+    }
+
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn test_grpc_service_over_localhost() {
+        use grpc::proto::{self, change_event::Kind, vault_client::VaultClient};
+        use tokio_stream::StreamExt;
+
+        let path = "/tmp/test_vault_grpc.json";
+        let _ = std::fs::remove_file(path);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let server = GrpcServer::open(path, "gRPC".to_string(), MemorySize::KB(1)).unwrap();
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = format!("http://{}", listener.local_addr().unwrap());
+            let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+            let serving = tokio::spawn(server.serve(listener, async {
+                let _ = stopped.await;
+            }));
+
+            let mut client = VaultClient::connect(address).await.unwrap();
+            let mut events = client
+                .watch(proto::WatchRequest {
+                    key_prefix: "note".to_string(),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+
+            let text =
+                |text: &str| Some(proto::Resource::from(&Resource::TextMessage(text.into())));
+            let add = |key: &str, resource| proto::AddRequest {
+                key: key.to_string(),
+                resource,
+            };
+            client.add(add("note", text("hello"))).await.unwrap();
+            client
+                .add(add("other", text("skipped by the watch")))
+                .await
+                .unwrap();
+            let old = client
+                .update(proto::UpdateRequest {
+                    key: "note".to_string(),
+                    resource: text("hello again"),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(old.resource, text("hello"));
+
+            let listed = client
+                .list(proto::ListRequest::default())
+                .await
+                .unwrap()
+                .into_inner();
+            let keys: Vec<&str> = listed.entries.iter().map(|e| e.key.as_str()).collect();
+            assert_eq!(keys, vec!["note", "other"]);
+            let found = client
+                .search(proto::SearchRequest {
+                    query: "AGAIN".to_string(),
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(found.entries.len(), 1);
+            let summary = client
+                .summary(proto::SummaryRequest {})
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!((summary.entries, summary.text_messages), (2, 2));
+
+            // Vault errors map to gRPC status codes
+            let missing = proto::KeyRequest {
+                key: "missing".to_string(),
+            };
+            let status = client.get(missing).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::NotFound);
+            let status = client.add(add("note", text("again"))).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+            let status = client
+                .add(add("big", text(&"x".repeat(2000))))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+
+            let removed = proto::KeyRequest {
+                key: "note".to_string(),
+            };
+            client.remove(removed).await.unwrap();
+            let mut kinds = Vec::new();
+            for _ in 0..3 {
+                kinds.push(events.next().await.unwrap().unwrap().kind());
+            }
+            assert_eq!(kinds, vec![Kind::Added, Kind::Updated, Kind::Removed]);
+
+            // Shutting down saves the vault
+            stop.send(()).unwrap();
+            serving.await.unwrap().unwrap();
+        });
+        let vault = Vault::<String>::load_from_file(path).unwrap();
+        assert_eq!(vault.keys().collect::<Vec<_>>(), vec!["other"]);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async_vault_and_background_flush() {
+        use std::time::Duration;
+
+        let path = "/tmp/test_vault_async.json";
+        let _ = std::fs::remove_file(path);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let vault = AsyncVault::new(Vault::empty("Async".to_string(), MemorySize::KB(1)));
+            let key = |k: &str| k.to_string();
+            vault
+                .add(key("a"), Resource::TextMessage("first".into()))
+                .await
+                .unwrap();
+            assert_eq!(
+                vault.get(&key("a")).await,
+                Some(Resource::TextMessage("first".into()))
+            );
+            assert!(matches!(
+                vault.remove(&key("missing")).await,
+                Err(VaultError::ResourceNotFound(_))
+            ));
+
+            // Changes reach the file on the next tick, without an explicit save
+            let flusher = vault.flush_every(path, Duration::from_millis(20));
+            let other = vault.clone();
+            tokio::spawn(async move {
+                other
+                    .add(key("b"), Resource::SensorData(1.5))
+                    .await
+                    .unwrap();
+            })
+            .await
+            .unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(Vault::<String>::load_from_file(path).unwrap().len(), 2);
+
+            // Stopping saves whatever is still pending
+            vault.remove(&key("a")).await.unwrap();
+            flusher.stop().await.unwrap();
+            let loaded = AsyncVault::<String>::load(path).await.unwrap();
+            assert_eq!(loaded.len().await, 1);
+            assert!(loaded.contains_key(&key("b")).await);
+        });
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_access_policy_and_authorized_vault() {
+        use resp::{execute, Session};
+
+        let key = |k: &str| k.to_string();
+        let policy: AccessPolicy = serde_json::from_value(serde_json::json!({
+            "roles": { "reader": [{ "prefix": "shared/", "permissions": ["read"] }] },
+            "principals": [
+                { "name": "ops", "api_key": "k-ops",
+                  "grants": [{ "prefix": "", "permissions": ["admin"] }] },
+                { "name": "planner", "api_key": "k-planner", "roles": ["reader"],
+                  "grants": [{ "prefix": "planner/", "permissions": ["read", "write", "delete"] }] },
+            ],
+        }))
+        .unwrap();
+        policy.validate().unwrap();
+        let planner = policy.authenticate("k-planner").unwrap().clone();
+        assert!(policy.authenticate("k-guess").is_none());
+
+        let mut vault: Vault<String> = Vault::empty("acl".to_string(), MemorySize::KB(4));
+        vault
+            .add(key("shared/goal"), Resource::TextMessage("ship".into()))
+            .unwrap();
+        {
+            let mut scoped = vault.authorized(&policy, &planner);
+            scoped
+                .add(key("planner/step"), Resource::TextMessage("plan".into()))
+                .unwrap();
+            assert!(scoped.get(&key("shared/goal")).unwrap().is_some());
+            assert!(matches!(
+                scoped.add(key("shared/new"), Resource::TextMessage("no".into())),
+                Err(VaultError::PermissionDenied { .. })
+            ));
+            assert!(scoped.vault().is_err());
+            scoped
+                .link(&key("planner/step"), "serves", &key("shared/goal"))
+                .unwrap();
+        }
+
+        // Removing an entry needs delete on everything a cascade would take
+        vault
+            .link_with(
+                &key("shared/goal"),
+                "part_of",
+                &key("planner/step"),
+                OnDelete::Cascade,
+            )
+            .unwrap();
+        let mut scoped = vault.authorized(&policy, &planner);
+        let error = scoped.remove(&key("planner/step")).unwrap_err();
+        assert_eq!(error.kind(), "permission_denied");
+        assert_eq!(
+            error.message(),
+            "'planner' lacks delete permission on 'shared/goal'"
+        );
+        assert_eq!(vault.len(), 2);
+
+        // Server modes refuse everything but connection commands until AUTH
+        let mut session = Session {
+            access: acl::Access::new(Some(std::sync::Arc::new(policy))),
+            ..Session::default()
+        };
+        let mut run = |line: &str| {
+            let args: Vec<Vec<u8>> = line.split(' ').map(|w| w.as_bytes().to_vec()).collect();
+            let mut out = Vec::new();
+            execute(&mut vault, &mut session, &args).encode(2, &mut out);
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(run("PING"), "+PONG\r\n");
+        assert!(run("GET shared/goal").starts_with("-NOAUTH "));
+        assert!(run("AUTH ops k-planner").starts_with("-WRONGPASS "));
+        assert_eq!(run("AUTH planner k-planner"), "+OK\r\n");
+        assert_eq!(run("GET shared/goal"), "$4\r\nship\r\n");
+        assert!(run("SET shared/goal other").starts_with("-NOPERM "));
+        assert!(run("DBSIZE").starts_with("-NOPERM "));
+        assert_eq!(
+            run("KEYS *"),
+            "*2\r\n$12\r\nplanner/step\r\n$11\r\nshared/goal\r\n"
+        );
+        assert_eq!(run("AUTH k-ops"), "+OK\r\n");
+        assert_eq!(run("DEL planner/step"), ":1\r\n");
+        assert_eq!(run("DBSIZE"), ":0\r\n");
+    }
+
+    #[test]
+    fn test_audit_log_chain() {
+        let key = |k: &str| k.to_string();
+        let path = "/tmp/test_vault_audit.log";
+        let _ = std::fs::remove_file(path);
+        let mut vault: Vault<String> = Vault::empty("audit".to_string(), MemorySize::KB(4));
+        vault.open_audit_log(path).unwrap();
+
+        vault
+            .add(key("a"), Resource::TextMessage("one".into()))
+            .unwrap();
+        vault.set_actor("planner");
+        vault
+            .update(&key("a"), Resource::TextMessage("two".into()))
+            .unwrap();
+        vault.add(key("b"), Resource::SensorData(1.0)).unwrap();
+        vault
+            .link_with(&key("b"), "about", &key("a"), OnDelete::Cascade)
+            .unwrap();
+        vault.remove(&key("a")).unwrap();
+
+        // Reopening continues the chain
+        let mut vault: Vault<String> = Vault::empty("audit".to_string(), MemorySize::KB(4));
+        vault.open_audit_log(path).unwrap();
+        vault.add(key("c"), Resource::SensorData(2.0)).unwrap();
+
+        let records = AuditLog::read(path).unwrap();
+        let ops: Vec<(AuditOp, &str, &str)> = records
+            .iter()
+            .map(|r| (r.op, r.key.as_str(), r.actor.as_str()))
+            .collect();
+        assert_eq!(
+            ops,
+            vec![
+                (AuditOp::Add, "a", "local"),
+                (AuditOp::Update, "a", "planner"),
+                (AuditOp::Add, "b", "planner"),
+                (AuditOp::Remove, "a", "planner"),
+                (AuditOp::Remove, "b", "planner"),
+                (AuditOp::Add, "c", "local"),
+            ]
+        );
+        let two = resource_digest(&Resource::TextMessage("two".into()));
+        assert_eq!(records[1].after.as_ref(), Some(&two));
+        assert_eq!(records[3].before.as_ref(), Some(&two));
+        assert_eq!(AuditLog::verify(path).unwrap(), 6);
+        let query = AuditQuery {
+            key: Some(key("a")),
+            since: Some(records[0].timestamp),
+            until: Some(records[3].timestamp),
+        };
+        assert_eq!(AuditLog::query(path, &query).unwrap().len(), 3);
+        let query = AuditQuery {
+            until: Some(records[0].timestamp - 1),
+            ..Default::default()
+        };
+        assert!(AuditLog::query(path, &query).unwrap().is_empty());
+
+        // Rewriting history breaks the chain at the edited record
+        let log = std::fs::read_to_string(path).unwrap();
+        std::fs::write(path, log.replacen("\"planner\"", "\"someone\"", 1)).unwrap();
+        let error = AuditLog::verify(path).unwrap_err();
+        assert!(error.message().contains("record 1"));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_audit_record_precedes_change() {
+        use std::sync::{Arc, Mutex};
+
+        let path = "/tmp/test_vault_audit_first.log";
+        let _ = std::fs::remove_file(path);
+        let mut vault: Vault<String> = Vault::empty("audit".to_string(), MemorySize::KB(4));
+        vault.open_audit_log(path).unwrap();
+
+        // By the time a subscriber hears of a change, the log already has it
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&seen);
+        vault.subscribe(EventFilter::all(), move |_| {
+            log.lock()
+                .unwrap()
+                .push(AuditLog::read(path).unwrap().len());
+        });
+        vault
+            .add("a".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        vault
+            .update(&"a".to_string(), Resource::SensorData(2.0))
+            .unwrap();
+        vault.remove(&"a".to_string()).unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec![1, 2, 3]);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_quotas_on_add_and_update() {
+        let key = |k: &str| k.to_string();
+        let text = |t: &str| Resource::TextMessage(t.to_string());
+        let mut vault: Vault<String> = Vault::empty("quotas".to_string(), MemorySize::KB(1));
+        vault.add(key("logs/0"), text("0123456789")).unwrap();
+        vault.set_quotas(Some(Quotas {
+            limits: vec![
+                Quota::prefix("logs/").max_bytes(25),
+                Quota::kind(ResourceKind::SensorData).max_entries(2),
+            ],
+            max_entry_bytes: Some(100),
+        }));
+
+        vault.add(key("logs/1"), text("0123456789")).unwrap();
+        let error = vault.add(key("logs/2"), text("0123456789")).unwrap_err();
+        assert_eq!(error.kind(), "quota_exceeded");
+        assert!(matches!(
+            &error,
+            VaultError::QuotaExceeded { quota, limit: 25, used: 20, requested: 10 }
+                if quota == "max_bytes for prefix 'logs/'"
+        ));
+        // Other namespaces are unaffected, and updates count only the difference
+        vault.add(key("notes/a"), text("0123456789")).unwrap();
+        vault
+            .update(&key("logs/1"), text("012345678901234"))
+            .unwrap();
+        assert!(vault.update(&key("logs/1"), text(&"x".repeat(16))).is_err());
+        assert!(matches!(
+            vault.add(key("big"), text(&"x".repeat(101))),
+            Err(VaultError::QuotaExceeded { quota, .. }) if quota == "max_entry_bytes"
+        ));
+
+        // Batches and transactions are held to the same limits
+        let results =
+            vault.add_many((0..3).map(|i| (format!("s{}", i), Resource::SensorData(1.0))));
+        assert!(results[0].is_ok() && results[1].is_ok() && results[2].is_err());
+        assert!(vault
+            .transaction(|tx| tx.add(key("logs/3"), text("x")))
+            .is_err());
+        vault
+            .transaction(|tx| {
+                tx.remove(&key("logs/0"))?;
+                tx.add(key("logs/3"), text("x"))
+            })
+            .unwrap();
+
+        let usage: Vec<String> = vault.quota_usage().iter().map(|u| u.to_string()).collect();
+        assert_eq!(
+            usage,
+            vec![
+                "Quota for prefix 'logs/': 2 entries, 16/25 bytes",
+                "Quota for kind sensor: 2/2 entries, 16 bytes",
+            ]
+        );
+        vault.remove(&key("s0")).unwrap();
+        assert_eq!(vault.quota_usage()[1].entries, 1);
+    }
+
+    #[test]
+    fn test_stats_and_metadata() {
+        let mut vault: Vault<String> = Vault::empty("stats".to_string(), MemorySize::KB(1));
+        let stats = vault.stats();
+        assert_eq!(stats.entries, 0);
+        assert!(stats.key_lengths.is_none() && stats.ages.is_empty() && stats.largest.is_empty());
+
+        vault
+            .add(
+                "a".to_string(),
+                Resource::TextMessage("0123456789".to_string()),
+            )
+            .unwrap();
+        vault
+            .add("bbb".to_string(), Resource::SensorData(1.0))
+            .unwrap();
+        vault
+            .add(
+                "cc".to_string(),
+                Resource::TextMessage("01234567890123456789".to_string()),
+            )
+            .unwrap();
+
+        let stats = vault.stats();
+        assert_eq!(stats.entries, 3);
+        assert_eq!(stats.used_bytes, vault.current_usage());
+        assert_eq!(stats.utilization, vault.current_usage() as f64 / 1024.0);
+        let texts = &stats.kinds[0];
+        assert_eq!(
+            (texts.kind, texts.entries, texts.bytes),
+            (ResourceKind::TextMessage, 2, 30)
+        );
+        assert_eq!(stats.kinds[2].entries, 0);
+        let largest: Vec<&str> = stats.largest.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(largest, vec!["cc", "a", "bbb"]);
+        let lengths = stats.key_lengths.as_ref().unwrap();
+        assert_eq!((lengths.min, lengths.median, lengths.max), (1, 2, 3));
+        assert_eq!(lengths.mean, 2.0);
+        // Everything was just added
+        assert_eq!(stats.ages[0].entries, 3);
+        assert_eq!(stats.ages.iter().map(|b| b.entries).sum::<usize>(), 3);
+
+        assert_eq!(VaultMetadata::from(&stats), vault.metadata());
+        assert_eq!(vault.metadata().resource_count, 3);
+        let json = serde_json::to_string(&stats).unwrap();
+        assert!(!json.contains("quotas"));
+        assert_eq!(serde_json::from_str::<VaultStats>(&json).unwrap(), stats);
+        assert!(stats.to_string().contains("Key lengths"));
+
+        // An expired entry not yet purged is left out of both
+        vault
+            .set_ttl(&"bbb".to_string(), Some(std::time::Duration::ZERO))
+            .unwrap();
+        assert_eq!(vault.metadata().resource_count, 2);
+        assert_eq!(VaultMetadata::from(&vault.stats()), vault.metadata());
+    }
+
+    #[test]
+    fn test_dedup_on_every_write() {
+        let text = |t: &str| Resource::TextMessage(t.to_string());
+        let key = |k: &str| k.to_string();
+        let mut vault = Vault::<String>::new("Dedup".to_string(), MemorySize::KB(512));
+        vault.set_dedup(Some(DedupConfig::default()));
+
+        // A bulk load goes through the banded index, and copies within the
+        // batch are caught as well as copies of stored entries
+        let results = vault.add_many((0..2000u64).map(|i| {
+            let a = i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            let b = a.rotate_left(29).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            (format!("note-{}", i), text(&format!("{:x} {:x}", a, b)))
+        }));
+        assert!(results.iter().all(Result::is_ok));
+        let results = vault.add_many(vec![
+            (key("fact"), text("Alice works at Acme in Berlin")),
+            (key("again"), text("alice works at ACME, in Berlin.")),
+            (key("copy"), text("0 0")),
+        ]);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(VaultError::Duplicate { ref existing, .. }) if existing == "fact"
+        ));
+        assert!(matches!(results[2], Err(VaultError::Duplicate { .. })));
+
+        // Updating an entry into a copy of another is refused, but an entry
+        // may be rewritten to what it already says
+        vault.add(key("other"), text("Bob prefers tea")).unwrap();
+        assert!(matches!(
+            vault.update(&key("other"), text("Alice works at Acme in Berlin")),
+            Err(VaultError::Duplicate { .. })
+        ));
+        vault
+            .update(&key("fact"), text("Alice works at Acme in Berlin!"))
+            .unwrap();
+
+        // A transaction with a copy in it applies nothing
+        let copied = vault.transaction(|tx| {
+            tx.add(key("new"), text("Carol runs the Lisbon office"))?;
+            tx.add(key("echo"), text("carol runs the lisbon office"))
+        });
+        assert!(matches!(copied, Err(VaultError::Duplicate { .. })));
+        assert!(!vault.contains_key(&key("new")));
+        // Freeing a text in the same batch lets another take its place
+        vault
+            .transaction(|tx| {
+                tx.remove(&key("other"))?;
+                tx.add(key("tea"), text("Bob prefers tea"))
+            })
+            .unwrap();
+
+        // Merge counts copies on their originals, in the vault or in the batch
+        vault.set_dedup(Some(DedupConfig {
+            policy: DedupPolicy::Merge,
+            ..Default::default()
+        }));
+        vault
+            .transaction(|tx| {
+                tx.add(key("m1"), text("Alice works at Acme in Berlin"))?;
+                tx.add(key("dave"), text("Dave fixed the build on Monday"))?;
+                tx.add(key("m2"), text("dave fixed the build on monday"))
+            })
+            .unwrap();
+        assert!(!vault.contains_key(&key("m1")) && !vault.contains_key(&key("m2")));
+        assert_eq!(vault.entry_meta(&key("fact")).unwrap().duplicates, 1);
+        assert_eq!(vault.entry_meta(&key("dave")).unwrap().duplicates, 1);
+        // An update that makes a copy folds the entry into the original
+        let old = vault
+            .update(&key("tea"), text("Dave fixed the build on Monday."))
+            .unwrap();
+        assert_eq!(old, text("Bob prefers tea"));
+        assert!(!vault.contains_key(&key("tea")));
+        assert_eq!(vault.entry_meta(&key("dave")).unwrap().duplicates, 2);
+
+        // Link keeps copies and points them at their originals
+        vault.set_dedup(Some(DedupConfig {
+            policy: DedupPolicy::Link,
+            ..Default::default()
+        }));
+        vault
+            .add_many(vec![(key("erin"), text("Erin owns the release checklist"))])
+            .remove(0)
+            .unwrap();
+        vault
+            .transaction(|tx| tx.add(key("erin2"), text("erin owns the release checklist now")))
+            .unwrap();
+        vault.add(key("frank"), text("Frank is on call")).unwrap();
+        vault
+            .update(&key("frank"), text("Erin owns the release checklist"))
+            .unwrap();
+        for copy in ["erin2", "frank"] {
+            let edges = vault.neighbors(&key(copy));
+            assert_eq!(
+                (edges[0].relation.as_str(), edges[0].to.as_str()),
+                (DUPLICATE_OF, "erin")
+            );
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn test_grpc_types_match_proto() {
+        use grpc::proto::{self, change_event::Kind, resource::Value};
+        use prost::Message;
+        use std::collections::{BTreeMap, BTreeSet};
+
+        // Field numbers, wire types and names as declared in the file
+        let camel = |name: &str| -> String {
+            name.split('_')
+                .map(|part| part[..1].to_uppercase() + &part[1..].to_lowercase())
+                .collect()
+        };
+        let mut scopes: Vec<(&str, &str)> = Vec::new();
+        let mut declared: BTreeMap<&str, BTreeSet<(u64, u64)>> = BTreeMap::new();
+        let mut names: Vec<(&str, String)> = Vec::new();
+        let mut enums: Vec<(&str, u64)> = Vec::new();
+        for line in include_str!("../proto/vault.proto").lines() {
+            let line = line.split("//").next().unwrap().trim();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let message = scopes.iter().rev().find(|(kind, _)| *kind == "message");
+            match words[..] {
+                ["message", name, ..] => {
+                    declared.entry(name).or_default();
+                    if !line.ends_with("{}") {
+                        scopes.push(("message", name));
+                    }
+                }
+                [kind @ ("enum" | "oneof" | "service"), name, "{"] => scopes.push((kind, name)),
+                ["}"] => {
+                    scopes.pop();
+                }
+                [.., value, "=", number]
+                    if scopes.last().is_some_and(|(kind, _)| *kind == "enum") =>
+                {
+                    let number = number.trim_end_matches(';').parse().unwrap();
+                    enums.push((value, number));
+                }
+                [.., ty, field, "=", number] if message.is_some() => {
+                    let (_, message) = message.unwrap();
+                    let number = number.trim_end_matches(';').parse().unwrap();
+                    let wire_type = match ty {
+                        "double" => 1,
+                        "uint64" | "Kind" => 0,
+                        _ => 2,
+                    };
+                    declared
+                        .get_mut(message)
+                        .unwrap()
+                        .insert((number, wire_type));
+                    names.push(match scopes.last().unwrap() {
+                        ("oneof", _) => (message, format!("({}(", camel(field))),
+                        _ => (message, format!(" {}: ", field)),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        // One value per Rust message with every field set, and one per oneof
+        // case; a field added on the Rust side will not compile here
+        let text = || proto::Resource {
+            value: Some(Value::TextMessage("t".to_string())),
+        };
+        let entry = || proto::Entry {
+            key: "k".to_string(),
+            resource: Some(text()),
+        };
+        let sample =
+            |message: &dyn std::fmt::Debug, bytes: Vec<u8>| (format!("{:?}", message), bytes);
+        macro_rules! samples {
+            ($($name:literal => $value:expr),* $(,)?) => {
+                vec![$(($name, { let value = $value; sample(&value, value.encode_to_vec()) })),*]
+            };
+        }
+        let samples = samples![
+            "Resource" => text(),
+            "Resource" => proto::Resource { value: Some(Value::SensorData(1.5)) },
+            "Resource" => proto::Resource {
+                value: Some(Value::SystemLogs(proto::Logs { lines: vec!["l".to_string()] })),
+            },
+            "Logs" => proto::Logs { lines: vec!["l".to_string()] },
+            "Entry" => entry(),
+            "AddRequest" => proto::AddRequest { key: "k".to_string(), resource: Some(text()) },
+            "AddResponse" => proto::AddResponse {},
+            "KeyRequest" => proto::KeyRequest { key: "k".to_string() },
+            "UpdateRequest" => proto::UpdateRequest { key: "k".to_string(), resource: Some(text()) },
+            "ListRequest" => proto::ListRequest { prefix: "p".to_string() },
+            "SearchRequest" => proto::SearchRequest { query: "q".to_string() },
+            "EntryList" => proto::EntryList { entries: vec![entry()] },
+            "SummaryRequest" => proto::SummaryRequest {},
+            "SummaryResponse" => proto::SummaryResponse {
+                location: "l".to_string(),
+                capacity_bytes: 1,
+                used_bytes: 1,
+                entries: 1,
+                text_messages: 1,
+                sensor_readings: 1,
+                system_logs: 1,
+            },
+            "WatchRequest" => proto::WatchRequest { key_prefix: "p".to_string() },
+            "ChangeEvent" => proto::ChangeEvent {
+                kind: Kind::Updated as i32,
+                key: "k".to_string(),
+                old: Some(text()),
+                new: Some(text()),
+            },
+        ];
+
+        // The top-level fields each encoding carries, as (number, wire type)
+        let mut encoded: BTreeMap<&str, BTreeSet<(u64, u64)>> = BTreeMap::new();
+        for (message, (_, bytes)) in &samples {
+            let fields = encoded.entry(message).or_default();
+            let mut buf = &bytes[..];
+            while !buf.is_empty() {
+                let key = prost::encoding::decode_varint(&mut buf).unwrap();
+                let skip = match key & 7 {
+                    0 => {
+                        prost::encoding::decode_varint(&mut buf).unwrap();
+                        0
+                    }
+                    1 => 8,
+                    2 => prost::encoding::decode_varint(&mut buf).unwrap() as usize,
+                    wire_type => panic!("unexpected wire type {}", wire_type),
+                };
+                buf = &buf[skip..];
+                fields.insert((key >> 3, key & 7));
+            }
+        }
+        assert_eq!(encoded, declared);
+        for (message, name) in &names {
+            assert!(
+                samples
+                    .iter()
+                    .any(|(sample, (debug, _))| sample == message && debug.contains(name.as_str())),
+                "{} has no field {}",
+                message,
+                name
+            );
+        }
+        for (value, number) in enums {
+            let kind = Kind::try_from(number as i32).unwrap();
+            assert_eq!(
+                format!("{:?}", kind),
+                camel(value.trim_start_matches("KIND_"))
+            );
+        }
+        assert_eq!(Kind::try_from(6), Err(prost::UnknownEnumValue(6)));
+    }
+
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn test_grpc_watch_drops_lagging_client() {
+        use grpc::proto::{self, vault_client::VaultClient};
+        use tokio_stream::StreamExt;
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let vault = Vault::<String>::new("gRPC".to_string(), MemorySize::MB(32));
+            let server = GrpcServer::new(vault, None).with_watch_buffer(4);
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = format!("http://{}", listener.local_addr().unwrap());
+            let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+            let serving = tokio::spawn(server.serve(listener, async {
+                let _ = stopped.await;
+            }));
+
+            let mut watcher = VaultClient::connect(address.clone()).await.unwrap();
+            let mut events = watcher
+                .watch(proto::WatchRequest::default())
+                .await
+                .unwrap()
+                .into_inner();
+            // The watcher reads nothing until the transport's windows and then
+            // the buffer behind them are full
+            let mut writer = VaultClient::connect(address).await.unwrap();
+            let written = 100;
+            for i in 0..written {
+                let resource = Resource::TextMessage(format!("{}{}", i, "x".repeat(64 * 1024)));
+                let request = proto::AddRequest {
+                    key: format!("k{}", i),
+                    resource: Some(proto::Resource::from(&resource)),
+                };
+                writer.add(request).await.unwrap();
+            }
+
+            let mut received = 0;
+            let status = loop {
+                match events.next().await.unwrap() {
+                    Ok(_) => received += 1,
+                    Err(status) => break status,
+                }
+            };
+            assert_eq!(status.code(), tonic::Code::Aborted);
+            assert!(received > 0 && received < written);
+            assert!(events.next().await.is_none());
+
+            stop.send(()).unwrap();
+            serving.await.unwrap().unwrap();
+        });
+    }
 }
@@ -1,8 +1,14 @@
 use colored::Colorize;
 use dotenv::dotenv;
-use memoria::{ui, MemorySize, Resource, Vault, VaultError};
+use memoria::{
+    merge, ui, AccessPolicy, AuditLog, AuditQuery, Change, ConflictResolution, McpServer,
+    MemorySize, PackedVault, Resource, RespServer, Vault, VaultDiff, VaultError,
+};
+use std::collections::HashMap;
 use std::env;
 use std::error::Error;
+#[cfg(unix)]
+use std::sync::{atomic::AtomicBool, Arc};
 
 fn main() -> Result<(), Box<dyn Error>> {
     // Load environment variables from .env file
@@ -10,15 +16,43 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // Check for command line arguments
     let args: Vec<String> = env::args().collect();
+
+    // One-shot subcommands run and exit without entering the interactive loop
+    if let Some(command) = args.get(1) {
+        let result = match command.as_str() {
+            "diff" => Some(run_diff(&args[2..])),
+            "merge" => Some(run_merge(&args[2..])),
+            "apply" => Some(run_apply(&args[2..])),
+            "migrate" => Some(run_migrate(&args[2..])),
+            "pack" => Some(run_pack(&args[2..])),
+            "get" => Some(run_get(&args[2..])),
+            "scan" => Some(run_scan(&args[2..])),
+            "links" => Some(run_links(&args[2..])),
+            "dedupe" => Some(run_dedupe(&args[2..])),
+            "audit" => Some(run_audit(&args[2..])),
+            "summary" => Some(run_summary(&args[2..])),
+            "mcp" => Some(run_mcp(&args[2..])),
+            "resp" => Some(run_resp(&args[2..])),
+            #[cfg(feature = "grpc")]
+            "grpc" => Some(run_grpc(&args[2..])),
+            #[cfg(unix)]
+            "daemon" => Some(run_daemon(&args[2..])),
+            _ => None,
+        };
+        if let Some(result) = result {
+            if let Err(e) = result {
+                eprintln!("{} {}", "Error:".red().bold(), e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+    }
+
     let should_persist = args.contains(&"--save".to_string());
     let vault_file = ".memoria/vault.json";
+    let journal_file = ".memoria/vault.wal";
 
-    // Read environment variables with defaults
-    let vault_name = env::var("VAULT_NAME").unwrap_or_else(|_| "Global Vault".to_string());
-    let capacity_gb: u64 = env::var("VAULT_CAPACITY_GB")
-        .unwrap_or_else(|_| "50".to_string())
-        .parse()
-        .unwrap_or(50);
+    let (vault_name, capacity) = vault_settings_from_env();
 
     // Try to load existing vault if persisting is enabled
     let mut my_vault = if should_persist && std::path::Path::new(vault_file).exists() {
@@ -30,20 +64,28 @@ fn main() -> Result<(), Box<dyn Error>> {
             }
             Err(_) => {
                 println!("Creating new vault...");
-                Vault::<String>::new(vault_name.clone(), MemorySize::GB(capacity_gb))
+                Vault::<String>::new(vault_name.clone(), capacity.clone())
             }
         }
     } else {
         // Create the vault with configurable name and capacity
-        Vault::<String>::new(vault_name.clone(), MemorySize::GB(capacity_gb))
+        Vault::<String>::new(vault_name.clone(), capacity.clone())
     };
 
+    // Record every change as it happens so a crash loses nothing since the last save
+    if should_persist {
+        std::fs::create_dir_all(".memoria").ok();
+        if let Err(e) = my_vault.open_journal(journal_file) {
+            eprintln!("⚠ Failed to open journal: {}", e);
+        }
+    }
+
     println!("Welcome to Memoria - {}!", vault_name);
 
     loop {
         let mut available_commands = String::from("\nAvailable Commands: [add]");
 
-        if !my_vault.resources.is_empty() {
+        if !my_vault.is_empty() {
             available_commands.push_str(" [summary] [get] [delete]");
         }
 
@@ -86,7 +128,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Save vault on exit if persistence is enabled
     if should_persist {
         std::fs::create_dir_all(".memoria").ok();
-        match my_vault.save_to_file(vault_file) {
+        match my_vault.checkpoint(vault_file) {
             Ok(_) => println!("✓ Vault saved to {}", vault_file),
             Err(e) => eprintln!("⚠ Failed to save vault: {}", e),
         }
@@ -148,3 +190,615 @@ fn handle_delete(vault: &mut Vault<String>) -> Result<(), Box<dyn Error>> {
     println!("Deleted: {:?}", res);
     Ok(())
 }
+
+// Splits subcommand arguments into positional values and `--option value` pairs
+fn parse_args<'a>(
+    args: &'a [String],
+    options: &[&str],
+) -> Result<(Vec<&'a str>, HashMap<&'a str, &'a str>), VaultError> {
+    let mut positional = Vec::new();
+    let mut values = HashMap::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg.starts_with("--") {
+            if !options.contains(&arg.as_str()) {
+                return Err(VaultError::InvalidInput(format!("Unknown option {}", arg)));
+            }
+            let value = iter
+                .next()
+                .ok_or_else(|| VaultError::InvalidInput(format!("Missing value for {}", arg)))?;
+            values.insert(arg.as_str(), value.as_str());
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+    Ok((positional, values))
+}
+
+// Name and capacity for a new vault, from `VAULT_NAME` and `VAULT_CAPACITY_GB`
+fn vault_settings_from_env() -> (String, MemorySize) {
+    let name = env::var("VAULT_NAME").unwrap_or_else(|_| "Global Vault".to_string());
+    let capacity_gb = env::var("VAULT_CAPACITY_GB")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(50);
+    (name, MemorySize::GB(capacity_gb))
+}
+
+fn usage(text: &str) -> Box<dyn Error> {
+    VaultError::InvalidInput(format!("Usage: memoria {}", text)).into()
+}
+
+// The access policy named by `--policy`, if any
+fn load_policy(options: &HashMap<&str, &str>) -> Result<Option<AccessPolicy>, VaultError> {
+    options
+        .get("--policy")
+        .map(|path| AccessPolicy::load(path))
+        .transpose()
+}
+
+fn print_diff(diff: &VaultDiff<String>) {
+    for change in &diff.changes {
+        match change {
+            Change::Added { key, resource } => {
+                println!("{}", format!("+ {}: {:?}", key, resource).green())
+            }
+            Change::Removed { key, resource } => {
+                println!("{}", format!("- {}: {:?}", key, resource).red())
+            }
+            Change::Changed { key, old, new } => {
+                println!("{}", format!("~ {}: {:?} -> {:?}", key, old, new).yellow())
+            }
+        }
+    }
+    let (added, removed, changed) = diff.counts();
+    println!(
+        "{} added, {} removed, {} changed",
+        added.to_string().green().bold(),
+        removed.to_string().red().bold(),
+        changed.to_string().yellow().bold()
+    );
+}
+
+fn run_diff(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, options) = parse_args(args, &["--patch"])?;
+    let [from, to] = paths[..] else {
+        return Err(usage("diff <a.json> <b.json> [--patch out.json]"));
+    };
+
+    let a = Vault::<String>::load_from_file(from)?;
+    let b = Vault::<String>::load_from_file(to)?;
+    let diff = a.diff(&b);
+
+    if diff.is_empty() {
+        println!("{}", "✓ Vaults are identical".green().bold());
+    } else {
+        print_diff(&diff);
+    }
+
+    if let Some(path) = options.get("--patch") {
+        let json = serde_json::to_string_pretty(&diff)?;
+        std::fs::write(path, json)?;
+        println!("✓ Patch written to {}", path);
+    }
+    Ok(())
+}
+
+fn run_apply(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, _) = parse_args(args, &[])?;
+    let [vault_path, patch_path] = paths[..] else {
+        return Err(usage("apply <vault.json> <patch.json>"));
+    };
+
+    let mut vault = Vault::<String>::load_from_file(vault_path)?;
+    let patch: VaultDiff<String> = serde_json::from_str(&std::fs::read_to_string(patch_path)?)?;
+    vault.apply_patch(&patch)?;
+    vault.save_to_file(vault_path)?;
+
+    print_diff(&patch);
+    println!(
+        "{}",
+        format!("✓ Patch applied to {}", vault_path).green().bold()
+    );
+    Ok(())
+}
+
+fn run_merge(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, options) = parse_args(args, &["--strategy", "--output"])?;
+    let [base_path, ours_path, theirs_path] = paths[..] else {
+        return Err(usage(
+            "merge <base.json> <ours.json> <theirs.json> --output out.json [--strategy ours|theirs|newest]",
+        ));
+    };
+
+    let resolution = match options.get("--strategy").copied().unwrap_or("ours") {
+        "ours" => ConflictResolution::Ours,
+        "theirs" => ConflictResolution::Theirs,
+        "newest" => ConflictResolution::NewestWins,
+        other => {
+            return Err(VaultError::InvalidInput(format!("Unknown strategy '{}'", other)).into())
+        }
+    };
+
+    // Naming ours.json here is how a merge overwrites it in place
+    let Some(output) = options.get("--output").copied() else {
+        return Err(usage(
+            "merge <base.json> <ours.json> <theirs.json> --output out.json [--strategy ours|theirs|newest]",
+        ));
+    };
+
+    let base = Vault::<String>::load_from_file(base_path)?;
+    let ours = Vault::<String>::load_from_file(ours_path)?;
+    let theirs = Vault::<String>::load_from_file(theirs_path)?;
+    let outcome = merge(&base, &ours, &theirs, &resolution)?;
+
+    for conflict in &outcome.conflicts {
+        let kept = match outcome.vault.get(&conflict.key) {
+            Some(resource) => format!("{:?}", resource),
+            None => "deleted".to_string(),
+        };
+        println!(
+            "{} {}: ours {:?}, theirs {:?} -> {}",
+            "! conflict".magenta().bold(),
+            conflict.key,
+            conflict.ours,
+            conflict.theirs,
+            kept
+        );
+    }
+
+    outcome.vault.save_to_file(output)?;
+    println!(
+        "{}",
+        format!(
+            "✓ Merged {} entries into {} ({} conflicts)",
+            outcome.vault.len(),
+            output,
+            outcome.conflicts.len()
+        )
+        .green()
+        .bold()
+    );
+    Ok(())
+}
+
+fn is_sqlite_path(path: &str) -> bool {
+    [".db", ".sqlite", ".sqlite3"]
+        .iter()
+        .any(|ext| path.ends_with(ext))
+}
+
+// Moves a vault between a JSON snapshot and a SQLite database, in whichever
+// direction the file extensions point
+fn run_migrate(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, _) = parse_args(args, &[])?;
+    let [from, to] = paths[..] else {
+        return Err(usage(
+            "migrate <from> <to>  (one side .json, the other .db)",
+        ));
+    };
+    if is_sqlite_path(from) == is_sqlite_path(to) {
+        return Err(usage(
+            "migrate <from> <to>  (one side .json, the other .db)",
+        ));
+    }
+    let moved = migrate(from, to)?;
+    println!(
+        "{}",
+        format!("✓ Migrated {} entries from {} to {}", moved, from, to)
+            .green()
+            .bold()
+    );
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn migrate(from: &str, to: &str) -> Result<usize, VaultError> {
+    if is_sqlite_path(from) {
+        memoria::migrate_sqlite_to_json::<String>(from, to)
+    } else {
+        memoria::migrate_json_to_sqlite::<String>(from, to)
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn migrate(_from: &str, _to: &str) -> Result<usize, VaultError> {
+    Err(VaultError::InvalidInput(
+        "memoria was built without SQLite support; rebuild with --features sqlite".to_string(),
+    ))
+}
+
+fn run_pack(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, options) = parse_args(args, &["--output"])?;
+    let [vault_path] = paths[..] else {
+        return Err(usage("pack <vault.json> [--output vault.pack]"));
+    };
+    let default_output = std::path::Path::new(vault_path).with_extension("pack");
+    let output = match options.get("--output") {
+        Some(path) => path.to_string(),
+        None => default_output.to_string_lossy().into_owned(),
+    };
+
+    let vault = Vault::<String>::load_from_file(vault_path)?;
+    vault.pack(&output)?;
+    println!(
+        "{}",
+        format!("✓ Packed {} entries into {}", vault.len(), output)
+            .green()
+            .bold()
+    );
+    Ok(())
+}
+
+// An unreadable packed file is rebuilt from the JSON snapshot next to it, if any
+fn open_packed(path: &str) -> Result<PackedVault, VaultError> {
+    PackedVault::open(path).or_else(|e| {
+        let json = std::path::Path::new(path).with_extension("json");
+        if !json.exists() {
+            return Err(e);
+        }
+        eprintln!("⚠ {}; repacking from {}", e, json.display());
+        PackedVault::open_or_repack(path, &json.to_string_lossy())
+    })
+}
+
+fn run_get(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, _) = parse_args(args, &[])?;
+    let [pack_path, key] = paths[..] else {
+        return Err(usage("get <vault.pack> <key>"));
+    };
+    let packed = open_packed(pack_path)?;
+    let resource = packed
+        .get(key)
+        .ok_or_else(|| VaultError::ResourceNotFound(key.to_string()))?;
+    println!("{}: {:?}", key, resource);
+    Ok(())
+}
+
+fn run_scan(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, options) = parse_args(args, &["--search"])?;
+    let (pack_path, prefix) = match paths[..] {
+        [pack_path] => (pack_path, ""),
+        [pack_path, prefix] => (pack_path, prefix),
+        _ => return Err(usage("scan <vault.pack> [prefix] [--search text]")),
+    };
+    let packed = open_packed(pack_path)?;
+    let hits: Vec<(&str, Resource)> = match options.get("--search") {
+        Some(query) => packed
+            .search(query)
+            .into_iter()
+            .filter(|(key, _)| key.starts_with(prefix))
+            .collect(),
+        None => packed.scan_prefix(prefix).collect(),
+    };
+    for (key, resource) in &hits {
+        println!("{}: {:?}", key, resource);
+    }
+    println!("{} of {} entries", hits.len(), packed.len());
+    Ok(())
+}
+
+fn run_links(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, options) = parse_args(args, &["--depth"])?;
+    let [path, key] = paths[..] else {
+        return Err(usage("links <vault.json> <key> [--depth n]"));
+    };
+    let vault = Vault::<String>::load_from_file(path)?;
+    let key = key.to_string();
+    if !vault.contains_key(&key) {
+        return Err(VaultError::ResourceNotFound(key).into());
+    }
+
+    let outgoing = vault.neighbors(&key);
+    let incoming = vault.referrers(&key);
+    if outgoing.is_empty() && incoming.is_empty() {
+        println!("No links for '{}'", key);
+        return Ok(());
+    }
+    for edge in outgoing {
+        println!("{} -[{}]-> {}", edge.from, edge.relation.cyan(), edge.to);
+    }
+    for edge in incoming {
+        println!("{} <-[{}]- {}", edge.to, edge.relation.cyan(), edge.from);
+    }
+
+    if let Some(depth) = options.get("--depth") {
+        let depth: usize = depth
+            .parse()
+            .map_err(|_| VaultError::InvalidInput(format!("Invalid depth '{}'", depth)))?;
+        println!("Reachable within {} hops:", depth);
+        for (reached, hops) in vault.traverse(&key, depth) {
+            println!("  {} ({})", reached, hops);
+        }
+    }
+    Ok(())
+}
+
+fn run_dedupe(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let args: Vec<String> = args
+        .iter()
+        .filter(|arg| *arg != "--dry-run")
+        .cloned()
+        .collect();
+    let (paths, options) = parse_args(&args, &["--threshold"])?;
+    let [path] = paths[..] else {
+        return Err(usage(
+            "dedupe <vault.json> [--threshold 0.8|exact] [--dry-run]",
+        ));
+    };
+    let near_threshold = match options.get("--threshold").copied().unwrap_or("0.8") {
+        "exact" => None,
+        value => match value.parse::<f64>() {
+            Ok(threshold) if (0.0..=1.0).contains(&threshold) => Some(threshold),
+            _ => {
+                return Err(
+                    VaultError::InvalidInput(format!("Invalid threshold '{}'", value)).into(),
+                )
+            }
+        },
+    };
+
+    let mut vault = Vault::<String>::load_from_file(path)?;
+    let duplicates = if dry_run {
+        vault.duplicates(near_threshold)
+    } else {
+        vault.dedupe(near_threshold)?
+    };
+    for duplicate in &duplicates {
+        println!(
+            "{} {} ≈ {} ({:.2})",
+            "~ duplicate".yellow().bold(),
+            duplicate.key,
+            duplicate.original,
+            duplicate.similarity
+        );
+    }
+
+    if dry_run {
+        println!(
+            "Found {} duplicates (dry run, nothing changed)",
+            duplicates.len()
+        );
+    } else {
+        vault.save_to_file(path)?;
+        println!(
+            "{}",
+            format!("✓ Removed {} duplicates from {}", duplicates.len(), path)
+                .green()
+                .bold()
+        );
+    }
+    Ok(())
+}
+
+fn run_audit(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, options) = parse_args(args, &["--key", "--since", "--until"])?;
+    match paths[..] {
+        ["verify", log] => {
+            let count = AuditLog::verify(log)?;
+            println!(
+                "{}",
+                format!("✓ {} records, chain intact", count).green().bold()
+            );
+        }
+        // One JSON record per line, as stored, so exports can be verified too
+        ["export", log] => {
+            let time = |option: &str| {
+                options
+                    .get(option)
+                    .map(|value| value.parse::<u64>())
+                    .transpose()
+                    .map_err(|_| {
+                        VaultError::InvalidInput(format!(
+                            "{} must be milliseconds since the Unix epoch",
+                            option
+                        ))
+                    })
+            };
+            let query = AuditQuery {
+                key: options.get("--key").map(|key| key.to_string()),
+                since: time("--since")?,
+                until: time("--until")?,
+            };
+            for record in AuditLog::query(log, &query)? {
+                println!("{}", serde_json::to_string(&record)?);
+            }
+        }
+        _ => {
+            return Err(usage(
+                "audit verify <audit.log> | audit export <audit.log> [--key k] [--since ms] [--until ms]",
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn run_summary(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, options) = parse_args(args, &["--format"])?;
+    let [path] = paths[..] else {
+        return Err(usage("summary <vault.json> [--format table|json]"));
+    };
+    let stats = Vault::<String>::load_from_file(path)?.stats();
+    match options.get("--format").copied().unwrap_or("table") {
+        "json" => println!("{}", serde_json::to_string_pretty(&stats)?),
+        "table" => print!("{}", stats),
+        format => {
+            return Err(VaultError::InvalidInput(format!("Unknown format '{}'", format)).into())
+        }
+    }
+    Ok(())
+}
+
+fn run_mcp(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, options) = parse_args(args, &["--policy", "--audit"])?;
+    let path = match paths[..] {
+        [] => {
+            std::fs::create_dir_all(".memoria")?;
+            ".memoria/vault.json"
+        }
+        [path] => path,
+        _ => {
+            return Err(usage(
+                "mcp [--policy acl.json] [--audit audit.log] [vault.json]",
+            ))
+        }
+    };
+    let (vault_name, capacity) = vault_settings_from_env();
+
+    let mut server = McpServer::open(path, vault_name, capacity)?;
+    // The one client of a stdio server is whoever started it, so the API key
+    // comes from the environment
+    if let Some(policy) = load_policy(&options)? {
+        let api_key = env::var("MEMORIA_API_KEY").map_err(|_| {
+            VaultError::InvalidInput("MEMORIA_API_KEY must be set with --policy".to_string())
+        })?;
+        server = server.with_policy(policy, &api_key)?;
+    }
+    if let Some(log) = options.get("--audit") {
+        server = server.with_audit_log(log)?;
+    }
+    eprintln!("memoria MCP server on stdio, vault {}", path);
+    server.serve(std::io::stdin().lock(), std::io::stdout().lock())?;
+    Ok(())
+}
+
+fn run_resp(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, options) = parse_args(args, &["--port", "--policy", "--audit"])?;
+    let path = match paths[..] {
+        [] => {
+            std::fs::create_dir_all(".memoria")?;
+            ".memoria/vault.json"
+        }
+        [path] => path,
+        _ => {
+            return Err(usage(
+                "resp [--port 6379] [--policy acl.json] [--audit audit.log] [vault.json]",
+            ))
+        }
+    };
+    let port: u16 = options
+        .get("--port")
+        .map(|port| port.parse())
+        .transpose()
+        .map_err(|_| VaultError::InvalidInput("--port must be a port number".to_string()))?
+        .unwrap_or(6379);
+    let (vault_name, capacity) = vault_settings_from_env();
+
+    let mut server = RespServer::open(path, vault_name, capacity)?;
+    if let Some(policy) = load_policy(&options)? {
+        server = server.with_policy(policy);
+    }
+    if let Some(log) = options.get("--audit") {
+        server = server.with_audit_log(log)?;
+    }
+    #[cfg(unix)]
+    stop_on_signals(&server.stop_flag())?;
+    let listener = std::net::TcpListener::bind(("127.0.0.1", port))?;
+    // Printed after binding so `--port 0` reveals the port picked
+    println!(
+        "memoria RESP server on {}, vault {}",
+        listener.local_addr()?,
+        path
+    );
+    server.serve(listener)?;
+    println!("{}", format!("✓ Vault saved to {}", path).green());
+    Ok(())
+}
+
+#[cfg(feature = "grpc")]
+fn run_grpc(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, options) = parse_args(args, &["--port", "--policy", "--audit"])?;
+    let path = match paths[..] {
+        [] => {
+            std::fs::create_dir_all(".memoria")?;
+            ".memoria/vault.json"
+        }
+        [path] => path,
+        _ => {
+            return Err(usage(
+                "grpc [--port 50051] [--policy acl.json] [--audit audit.log] [vault.json]",
+            ))
+        }
+    };
+    let port: u16 = options
+        .get("--port")
+        .map(|port| port.parse())
+        .transpose()
+        .map_err(|_| VaultError::InvalidInput("--port must be a port number".to_string()))?
+        .unwrap_or(50051);
+    let (vault_name, capacity) = vault_settings_from_env();
+
+    let mut server = memoria::GrpcServer::open(path, vault_name, capacity)?;
+    if let Some(policy) = load_policy(&options)? {
+        server = server.with_policy(policy);
+    }
+    if let Some(log) = options.get("--audit") {
+        server = server.with_audit_log(log)?;
+    }
+    tokio::runtime::Runtime::new()?.block_on(async {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await?;
+        println!(
+            "memoria gRPC server on {}, vault {}",
+            listener.local_addr()?,
+            path
+        );
+        // Ctrl-C or `kill` stops the server, which then saves the vault
+        #[cfg(unix)]
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        let shutdown = async move {
+            #[cfg(unix)]
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            #[cfg(not(unix))]
+            let _ = tokio::signal::ctrl_c().await;
+        };
+        server.serve(listener, shutdown).await?;
+        println!("{}", format!("✓ Vault saved to {}", path).green());
+        Ok(())
+    })
+}
+
+// Ctrl-C and `kill` set `stop`, so the server shuts down and saves the vault
+// as it would on a shutdown request
+#[cfg(unix)]
+fn stop_on_signals(stop: &Arc<AtomicBool>) -> Result<(), VaultError> {
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register(signal, stop.clone())?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn run_daemon(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (paths, options) = parse_args(args, &["--socket", "--policy", "--audit"])?;
+    let (Some(socket), [] | [_]) = (options.get("--socket"), &paths[..]) else {
+        return Err(usage(
+            "daemon --socket <path> [--policy acl.json] [--audit audit.log] [vault.json]",
+        ));
+    };
+    let path = match paths.first() {
+        Some(path) => path,
+        None => {
+            std::fs::create_dir_all(".memoria")?;
+            ".memoria/vault.json"
+        }
+    };
+    let (vault_name, capacity) = vault_settings_from_env();
+
+    let mut daemon = memoria::Daemon::open(path, vault_name, capacity)?;
+    if let Some(policy) = load_policy(&options)? {
+        daemon = daemon.with_policy(policy);
+    }
+    if let Some(log) = options.get("--audit") {
+        daemon = daemon.with_audit_log(log)?;
+    }
+    stop_on_signals(&daemon.stop_flag())?;
+    println!("memoria daemon on {}, vault {}", socket, path);
+    daemon.serve(socket)?;
+    println!("{}", format!("✓ Vault saved to {}", path).green());
+    Ok(())
+}
@@ -0,0 +1,338 @@
+use crate::acl::{Access, AccessPolicy, Permission};
+use crate::error::VaultError;
+use crate::memory::MemorySize;
+use crate::recall::RecallOptions;
+use crate::resource::Resource;
+use crate::vault::Vault;
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+// Protocol revisions this server can speak, newest last
+const PROTOCOL_VERSIONS: [&str; 3] = ["2024-11-05", "2025-03-26", "2025-06-18"];
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// Plain JSON for a resource: text as a string, sensor data as a number and
+// logs as an array of strings
+pub fn resource_to_json(resource: &Resource) -> Value {
+    match resource {
+        Resource::TextMessage(text) => json!(text),
+        Resource::SensorData(value) => json!(value),
+        Resource::SystemLogs(logs) => json!(logs),
+    }
+}
+
+pub fn resource_from_json(value: &Value) -> Result<Resource, VaultError> {
+    match value {
+        Value::String(text) => Ok(Resource::TextMessage(text.clone())),
+        Value::Number(number) => number
+            .as_f64()
+            .map(Resource::SensorData)
+            .ok_or_else(|| VaultError::InvalidInput(format!("Unsupported number {}", number))),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .map(Resource::SystemLogs)
+            .ok_or_else(|| VaultError::InvalidInput("Logs must be strings".to_string())),
+        other => Err(VaultError::InvalidInput(format!(
+            "Unsupported value {}",
+            other
+        ))),
+    }
+}
+
+fn tool(name: &str, description: &str, properties: Value, required: &[&str]) -> Value {
+    json!({
+        "name": name,
+        "description": description,
+        "inputSchema": {
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        },
+    })
+}
+
+fn tools() -> Value {
+    let value_schema = json!({
+        "description": "Text, a number, or a list of log lines",
+        "anyOf": [
+            { "type": "string" },
+            { "type": "number" },
+            { "type": "array", "items": { "type": "string" } },
+        ],
+    });
+    json!([
+        tool(
+            "memory_add",
+            "Store a new memory under a unique key",
+            json!({
+                "key": { "type": "string", "description": "Unique key, e.g. user/name" },
+                "value": value_schema,
+            }),
+            &["key", "value"],
+        ),
+        tool(
+            "memory_get",
+            "Read the memory stored under a key",
+            json!({ "key": { "type": "string" } }),
+            &["key"],
+        ),
+        tool(
+            "memory_search",
+            "Find the memories most relevant to a query",
+            json!({
+                "query": { "type": "string" },
+                "limit": { "type": "integer", "minimum": 1, "default": 10 },
+            }),
+            &["query"],
+        ),
+        tool(
+            "memory_delete",
+            "Delete the memory stored under a key",
+            json!({ "key": { "type": "string" } }),
+            &["key"],
+        ),
+        tool(
+            "memory_list",
+            "List stored keys, optionally only those starting with a prefix",
+            json!({ "prefix": { "type": "string" } }),
+            &[],
+        ),
+    ])
+}
+
+fn string_arg<'a>(arguments: &'a Value, name: &str) -> Result<&'a str, VaultError> {
+    arguments
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| VaultError::InvalidInput(format!("Missing string argument '{}'", name)))
+}
+
+// A tool failure, reported inside a successful JSON-RPC response so the model
+// can see and react to it
+fn tool_error(error: &VaultError) -> Value {
+    let structured = json!({ "error": { "kind": error.kind(), "message": error.message() } });
+    json!({
+        "content": [{ "type": "text", "text": error.message() }],
+        "structuredContent": structured,
+        "isError": true,
+    })
+}
+
+fn tool_result(structured: Value) -> Value {
+    json!({
+        "content": [{ "type": "text", "text": structured.to_string() }],
+        "structuredContent": structured,
+        "isError": false,
+    })
+}
+
+fn response(id: Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+// Serves a vault to LLM clients over the Model Context Protocol: JSON-RPC 2.0
+// messages, one per line. When a save path is set, the vault is written there
+// after every change.
+pub struct McpServer {
+    vault: Vault<String>,
+    save_path: Option<String>,
+    access: Access,
+}
+
+impl McpServer {
+    pub fn new(vault: Vault<String>, save_path: Option<String>) -> Self {
+        Self {
+            vault,
+            save_path,
+            access: Access::default(),
+        }
+    }
+
+    // Runs every tool as the principal holding `api_key`. A stdio server has a
+    // single client, so it authenticates once, up front.
+    pub fn with_policy(mut self, policy: AccessPolicy, api_key: &str) -> Result<Self, VaultError> {
+        let mut access = Access::new(Some(Arc::new(policy)));
+        access.authenticate(api_key)?;
+        self.access = access;
+        Ok(self)
+    }
+
+    // Records every change in the audit log at `path`
+    pub fn with_audit_log(mut self, path: &str) -> Result<Self, VaultError> {
+        self.vault.open_audit_log(path)?;
+        Ok(self)
+    }
+
+    // Saves back to `path` after every change
+    pub fn open(path: &str, location: String, capacity: MemorySize) -> Result<Self, VaultError> {
+        let vault = Vault::open_or_create(path, location, capacity)?;
+        Ok(Self::new(vault, Some(path.to_string())))
+    }
+
+    pub fn vault(&self) -> &Vault<String> {
+        &self.vault
+    }
+
+    // Answers requests from `input` until it is closed
+    pub fn serve<R: BufRead, W: Write>(
+        &mut self,
+        input: R,
+        mut output: W,
+    ) -> Result<(), VaultError> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match serde_json::from_str::<Value>(&line) {
+                Ok(message) => self.handle(&message),
+                Err(e) => Some(error_response(Value::Null, PARSE_ERROR, &e.to_string())),
+            };
+            if let Some(reply) = reply {
+                writeln!(output, "{}", reply)?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Handles one JSON-RPC message; notifications get no reply
+    pub fn handle(&mut self, message: &Value) -> Option<Value> {
+        let method = message.get("method").and_then(Value::as_str);
+        let id = message.get("id").cloned()?;
+        let Some(method) = method else {
+            return Some(error_response(id, INVALID_REQUEST, "Missing method"));
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let reply = match method {
+            "initialize" => {
+                let requested = params.get("protocolVersion").and_then(Value::as_str);
+                let version = requested
+                    .filter(|v| PROTOCOL_VERSIONS.contains(v))
+                    .unwrap_or(PROTOCOL_VERSIONS[PROTOCOL_VERSIONS.len() - 1]);
+                response(
+                    id,
+                    json!({
+                        "protocolVersion": version,
+                        "capabilities": { "tools": { "listChanged": false } },
+                        "serverInfo": { "name": "memoria", "version": env!("CARGO_PKG_VERSION") },
+                    }),
+                )
+            }
+            "ping" => response(id, json!({})),
+            "tools/list" => response(id, json!({ "tools": tools() })),
+            "tools/call" => {
+                let name = params.get("name").and_then(Value::as_str).unwrap_or("");
+                let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+                match self.call(name, &arguments) {
+                    Some(Ok(result)) => response(id, tool_result(result)),
+                    Some(Err(e)) => response(id, tool_error(&e)),
+                    None => error_response(id, INVALID_PARAMS, &format!("Unknown tool '{}'", name)),
+                }
+            }
+            other => error_response(id, METHOD_NOT_FOUND, &format!("Unknown method '{}'", other)),
+        };
+        Some(reply)
+    }
+
+    // Runs a tool; `None` when there is no tool by that name
+    fn call(&mut self, name: &str, arguments: &Value) -> Option<Result<Value, VaultError>> {
+        self.vault.set_actor(self.access.actor());
+        let access = &self.access;
+        let result = match name {
+            "memory_add" => self.add(arguments),
+            "memory_get" => string_arg(arguments, "key").and_then(|key| {
+                access.check(Permission::Read, key)?;
+                let resource = self
+                    .vault
+                    .get(&key.to_string())
+                    .ok_or_else(|| VaultError::ResourceNotFound(key.to_string()))?;
+                Ok(json!({ "key": key, "value": resource_to_json(resource) }))
+            }),
+            "memory_search" => string_arg(arguments, "query").map(|query| {
+                let limit = arguments.get("limit").and_then(Value::as_u64).unwrap_or(10);
+                // Only readable memories are candidates
+                let within = access.enforced().then(|| {
+                    self.vault
+                        .keys()
+                        .filter(|key| access.allows(Permission::Read, key))
+                        .cloned()
+                        .collect()
+                });
+                let options = RecallOptions {
+                    limit: limit as usize,
+                    within,
+                    ..Default::default()
+                };
+                let hits: Vec<Value> = self
+                    .vault
+                    .recall(query, &options)
+                    .into_iter()
+                    .map(|hit| {
+                        json!({
+                            "key": hit.key,
+                            "value": resource_to_json(&hit.resource),
+                            "score": hit.score,
+                        })
+                    })
+                    .collect();
+                json!({ "results": hits })
+            }),
+            "memory_delete" => string_arg(arguments, "key").and_then(|key| {
+                let key = key.to_string();
+                self.access.check_removal(&self.vault, &key)?;
+                self.vault.remove(&key)?;
+                self.persist()?;
+                Ok(json!({ "deleted": key }))
+            }),
+            "memory_list" => {
+                let prefix = arguments
+                    .get("prefix")
+                    .and_then(Value::as_str)
+                    .unwrap_or("");
+                let mut keys: Vec<&String> = self
+                    .vault
+                    .keys()
+                    .filter(|key| key.starts_with(prefix))
+                    .filter(|key| access.allows(Permission::Read, key))
+                    .collect();
+                keys.sort();
+                Ok(json!({ "keys": keys }))
+            }
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    fn add(&mut self, arguments: &Value) -> Result<Value, VaultError> {
+        let key = string_arg(arguments, "key")?;
+        self.access.check(Permission::Write, key)?;
+        let value = arguments
+            .get("value")
+            .ok_or_else(|| VaultError::InvalidInput("Missing argument 'value'".to_string()))?;
+        self.vault
+            .add(key.to_string(), resource_from_json(value)?)?;
+        self.persist()?;
+        Ok(json!({ "added": key }))
+    }
+
+    fn persist(&self) -> Result<(), VaultError> {
+        match &self.save_path {
+            Some(path) => self.vault.save_to_file(path),
+            None => Ok(()),
+        }
+    }
+}
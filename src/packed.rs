@@ -0,0 +1,285 @@
+use crate::backend::StorageBackend;
+use crate::error::VaultError;
+use crate::memory::MemorySize;
+use crate::resource::Resource;
+use crate::vault::{invalid_data, Vault};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::Write;
+use std::path::Path;
+
+// File layout, all integers little-endian:
+//
+//   magic "MEMPACK2" | entry count u64 | header length u32 | header JSON
+//   key table: one row per entry, sorted by key
+//       key offset u64 | key length u32 | value offset u64 | value length u32 |
+//       checksum u64 (FNV-1a of the key followed by the value)
+//   data: UTF-8 keys and JSON-encoded resources
+const MAGIC: &[u8; 8] = b"MEMPACK2";
+const ROW: usize = 32;
+const FIXED: usize = MAGIC.len() + 8 + 4;
+
+#[derive(Serialize, Deserialize)]
+struct PackHeader {
+    location: String,
+    storage_capacity: MemorySize,
+}
+
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn corrupt(reason: &str) -> VaultError {
+    VaultError::InvalidInput(format!("Corrupt packed vault: {}", reason))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> usize {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> usize {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap()) as usize
+}
+
+// Read-only vault served straight from a memory-mapped file written by
+// `Vault::pack`. Opening it reads only the header, so it takes the same time
+// whatever the file's size. Keys are compared in place, and a resource is
+// checked against its checksum and decoded only when it is asked for; a
+// damaged entry reads as missing. `verify` checks the whole file.
+pub struct PackedVault {
+    pub location: String,
+    pub storage_capacity: MemorySize,
+    map: Mmap,
+    count: usize,
+    table: usize,
+}
+
+impl PackedVault {
+    pub fn open(path: &str) -> Result<Self, VaultError> {
+        let file = File::open(path)?;
+        // Safety: the map is only read, and packed files are written once to a
+        // temporary name and renamed into place, never modified afterwards
+        let map = unsafe { Mmap::map(&file)? };
+
+        if map.len() < FIXED || &map[..MAGIC.len()] != MAGIC {
+            return Err(corrupt("not a packed vault"));
+        }
+        let count = u64_at(&map, MAGIC.len());
+        let header_len = u32_at(&map, MAGIC.len() + 8);
+        let table = FIXED + header_len;
+        let table_end = count
+            .checked_mul(ROW)
+            .and_then(|rows| rows.checked_add(table));
+        if table_end.is_none_or(|end| end > map.len()) {
+            return Err(corrupt("truncated key table"));
+        }
+        let header: PackHeader =
+            serde_json::from_slice(&map[FIXED..table]).map_err(|_| corrupt("bad header"))?;
+
+        Ok(Self {
+            location: header.location,
+            storage_capacity: header.storage_capacity,
+            map,
+            count,
+            table,
+        })
+    }
+
+    // Checks every entry's bounds, key, checksum and order, reading the whole file
+    pub fn verify(&self) -> Result<(), VaultError> {
+        for index in 0..self.count {
+            let (key, value) = self.ranges(index);
+            if key.end > self.map.len() || value.end > self.map.len() {
+                return Err(corrupt("entry out of bounds"));
+            }
+            if std::str::from_utf8(&self.map[key]).is_err() {
+                return Err(corrupt("key is not UTF-8"));
+            }
+            if self.entry(index).is_none() {
+                return Err(corrupt("checksum mismatch"));
+            }
+            if index > 0 && self.key_at(index - 1) >= self.key_at(index) {
+                return Err(corrupt("keys out of order"));
+            }
+        }
+        Ok(())
+    }
+
+    // Opens `pack_path`, rebuilding it from the JSON snapshot at `json_path`
+    // if it is missing or unreadable
+    pub fn open_or_repack(pack_path: &str, json_path: &str) -> Result<Self, VaultError> {
+        match Self::open(pack_path) {
+            Ok(packed) => Ok(packed),
+            Err(_) => {
+                Vault::<String>::load_from_file(json_path)?.pack(pack_path)?;
+                Self::open(pack_path)
+            }
+        }
+    }
+
+    fn ranges(&self, index: usize) -> (std::ops::Range<usize>, std::ops::Range<usize>) {
+        let row = self.table + index * ROW;
+        let key = u64_at(&self.map, row);
+        let key_len = u32_at(&self.map, row + 8);
+        let value = u64_at(&self.map, row + 12);
+        let value_len = u32_at(&self.map, row + 20);
+        (
+            key..key.saturating_add(key_len),
+            value..value.saturating_add(value_len),
+        )
+    }
+
+    fn checksum_at(&self, index: usize) -> u64 {
+        u64_at(&self.map, self.table + index * ROW + 24) as u64
+    }
+
+    // A damaged key reads as empty, which at worst makes lookups miss
+    fn key_at(&self, index: usize) -> &str {
+        self.map
+            .get(self.ranges(index).0)
+            .and_then(|key| std::str::from_utf8(key).ok())
+            .unwrap_or_default()
+    }
+
+    // The key and value bytes of an entry, if they match its checksum
+    fn entry(&self, index: usize) -> Option<&[u8]> {
+        let (key, value) = self.ranges(index);
+        if key.end != value.start {
+            return None;
+        }
+        let bytes = self.map.get(key.start..value.end)?;
+        (fnv1a(bytes) == self.checksum_at(index)).then_some(&bytes[key.len()..])
+    }
+
+    fn value_at(&self, index: usize) -> Option<Resource> {
+        serde_json::from_slice(self.entry(index)?).ok()
+    }
+
+    // Index of the first key not less than `key`
+    fn lower_bound(&self, key: &str) -> usize {
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.key_at(mid) < key {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn get(&self, key: &str) -> Option<Resource> {
+        let index = self.lower_bound(key);
+        if index < self.count && self.key_at(index) == key {
+            self.value_at(index)
+        } else {
+            None
+        }
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        let index = self.lower_bound(key);
+        index < self.count && self.key_at(index) == key
+    }
+
+    // Keys in sorted order
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        (0..self.count).map(|index| self.key_at(index))
+    }
+
+    // Entries whose key starts with `prefix`, in key order
+    pub fn scan_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, Resource)> {
+        (self.lower_bound(prefix)..self.count)
+            .map(|index| (index, self.key_at(index)))
+            .take_while(move |(_, key)| key.starts_with(prefix))
+            .filter_map(|(index, key)| Some((key, self.value_at(index)?)))
+    }
+
+    // Entries whose key or content contains `query`, ignoring case, sorted by key
+    pub fn search(&self, query: &str) -> Vec<(&str, Resource)> {
+        let needle = query.to_lowercase();
+        (0..self.count)
+            .filter_map(|index| {
+                let key = self.key_at(index);
+                let resource = self.value_at(index)?;
+                (key.to_lowercase().contains(&needle) || resource.contains_text(&needle))
+                    .then_some((key, resource))
+            })
+            .collect()
+    }
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    // Writes the live entries out in the packed layout read by `PackedVault`.
+    // Keys are stored in their display form.
+    pub fn pack(&self, path: &str) -> Result<(), VaultError> {
+        let mut entries: Vec<(String, Vec<u8>)> = self
+            .iter()
+            .map(|(key, resource)| Ok((key.to_string(), serde_json::to_vec(resource)?)))
+            .collect::<Result<_, serde_json::Error>>()
+            .map_err(invalid_data)?;
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        if let Some(pair) = entries.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            return Err(VaultError::InvalidInput(format!(
+                "Key '{}' appears twice once written as text",
+                pair[0].0
+            )));
+        }
+
+        let header = serde_json::to_vec(&PackHeader {
+            location: self.location.clone(),
+            storage_capacity: self.storage_capacity.clone(),
+        })
+        .map_err(invalid_data)?;
+
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        out.extend_from_slice(&header);
+
+        let mut offset = out.len() + entries.len() * ROW;
+        for (key, value) in &entries {
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            offset += key.len();
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            offset += value.len();
+            let mut entry = key.as_bytes().to_vec();
+            entry.extend_from_slice(value);
+            out.extend_from_slice(&fnv1a(&entry).to_le_bytes());
+        }
+        for (key, value) in &entries {
+            out.extend_from_slice(key.as_bytes());
+            out.extend_from_slice(value);
+        }
+
+        // Written aside and renamed so a reader never maps a half-written file
+        let tmp = Path::new(path).with_extension("pack.tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&out)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+use crate::backend::StorageBackend;
+use crate::error::VaultError;
+use crate::resource::{Resource, ResourceKind};
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::hash::Hash;
+
+// The entries a quota counts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QuotaScope {
+    // Keys starting with the prefix, e.g. a namespace like "logs/"
+    Prefix(String),
+    Kind(ResourceKind),
+}
+
+impl Display for QuotaScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QuotaScope::Prefix(prefix) => write!(f, "prefix '{}'", prefix),
+            QuotaScope::Kind(kind) => write!(f, "kind {}", kind),
+        }
+    }
+}
+
+// Caps on the entries in one scope; `None` leaves that measure unlimited
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quota {
+    pub scope: QuotaScope,
+    pub max_bytes: Option<u64>,
+    pub max_entries: Option<u64>,
+}
+
+impl Quota {
+    pub fn prefix(prefix: &str) -> Self {
+        Self::scoped(QuotaScope::Prefix(prefix.to_string()))
+    }
+
+    pub fn kind(kind: ResourceKind) -> Self {
+        Self::scoped(QuotaScope::Kind(kind))
+    }
+
+    fn scoped(scope: QuotaScope) -> Self {
+        Self {
+            scope,
+            max_bytes: None,
+            max_entries: None,
+        }
+    }
+
+    pub fn max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    pub fn max_entries(mut self, entries: u64) -> Self {
+        self.max_entries = Some(entries);
+        self
+    }
+
+    fn covers(&self, key: &str, kind: ResourceKind) -> bool {
+        match &self.scope {
+            QuotaScope::Prefix(prefix) => key.starts_with(prefix.as_str()),
+            QuotaScope::Kind(scoped) => *scoped == kind,
+        }
+    }
+}
+
+// Limits checked on top of the vault's storage capacity. Every quota an entry
+// falls under applies.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quotas {
+    #[serde(default)]
+    pub limits: Vec<Quota>,
+    // Largest single resource accepted
+    #[serde(default)]
+    pub max_entry_bytes: Option<u64>,
+}
+
+// What one quota currently counts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    pub quota: Quota,
+    pub bytes: u64,
+    pub entries: u64,
+}
+
+// One line of `Vault::summary`, e.g. "Quota for prefix 'logs/': 3/10 entries, 120 bytes"
+impl Display for QuotaUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let limit = |limit: Option<u64>| limit.map_or(String::new(), |limit| format!("/{}", limit));
+        write!(
+            f,
+            "Quota for {}: {}{} entries, {}{} bytes",
+            self.quota.scope,
+            self.entries,
+            limit(self.quota.max_entries),
+            self.bytes,
+            limit(self.quota.max_bytes)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Used {
+    bytes: u64,
+    entries: u64,
+}
+
+// Quotas plus running totals for each, kept in step by the vault's entry helpers
+pub(crate) struct QuotaIndex {
+    pub(crate) quotas: Quotas,
+    pub(crate) used: Vec<Used>,
+}
+
+impl QuotaIndex {
+    fn new(quotas: Quotas) -> Self {
+        let used = vec![Used::default(); quotas.limits.len()];
+        Self { quotas, used }
+    }
+
+    pub(crate) fn insert(&mut self, key: &str, resource: &Resource) {
+        track(&self.quotas, &mut self.used, key, resource, true);
+    }
+
+    pub(crate) fn remove(&mut self, key: &str, resource: &Resource) {
+        track(&self.quotas, &mut self.used, key, resource, false);
+    }
+
+    // Fails if storing `resource` under `key`, in place of `replacing`, would
+    // take `used` past any limit
+    pub(crate) fn check(
+        &self,
+        used: &[Used],
+        key: &str,
+        resource: &Resource,
+        replacing: Option<&Resource>,
+    ) -> Result<(), VaultError> {
+        let size = resource.size_bytes();
+        if let Some(limit) = self.quotas.max_entry_bytes.filter(|limit| size > *limit) {
+            return Err(VaultError::QuotaExceeded {
+                quota: "max_entry_bytes".to_string(),
+                limit,
+                used: 0,
+                requested: size,
+            });
+        }
+        for (quota, used) in self.quotas.limits.iter().zip(used) {
+            if !quota.covers(key, resource.kind()) {
+                continue;
+            }
+            // What the replaced resource counted here is freed by the change
+            let (freed_bytes, freed_entries) = match replacing {
+                Some(old) if quota.covers(key, old.kind()) => (old.size_bytes(), 1),
+                _ => (0, 0),
+            };
+            let checks = [
+                ("max_bytes", quota.max_bytes, used.bytes - freed_bytes, size),
+                (
+                    "max_entries",
+                    quota.max_entries,
+                    used.entries - freed_entries,
+                    1,
+                ),
+            ];
+            for (measure, limit, used, requested) in checks {
+                if let Some(limit) = limit.filter(|limit| used + requested > *limit) {
+                    return Err(VaultError::QuotaExceeded {
+                        quota: format!("{} for {}", measure, quota.scope),
+                        limit,
+                        used,
+                        requested,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn track(quotas: &Quotas, used: &mut [Used], key: &str, resource: &Resource, add: bool) {
+    let (kind, size) = (resource.kind(), resource.size_bytes());
+    for (quota, used) in quotas.limits.iter().zip(used) {
+        if quota.covers(key, kind) {
+            if add {
+                used.bytes += size;
+                used.entries += 1;
+            } else {
+                used.bytes -= size;
+                used.entries -= 1;
+            }
+        }
+    }
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    // Turns quota checks on `add` and `update` on or off. Entries already
+    // over a new quota stay; only later changes are refused.
+    pub fn set_quotas(&mut self, quotas: Option<Quotas>) {
+        self.quotas = quotas.map(|quotas| {
+            let mut index = QuotaIndex::new(quotas);
+            for (key, resource) in self.resources.iter() {
+                index.insert(&key.to_string(), resource);
+            }
+            index
+        });
+    }
+
+    pub fn quotas(&self) -> Option<&Quotas> {
+        self.quotas.as_ref().map(|index| &index.quotas)
+    }
+
+    // Current usage of each quota, in the order they were given
+    pub fn quota_usage(&self) -> Vec<QuotaUsage> {
+        let Some(index) = &self.quotas else {
+            return Vec::new();
+        };
+        index
+            .quotas
+            .limits
+            .iter()
+            .zip(&index.used)
+            .map(|(quota, used)| QuotaUsage {
+                quota: quota.clone(),
+                bytes: used.bytes,
+                entries: used.entries,
+            })
+            .collect()
+    }
+
+    pub(crate) fn check_quotas(
+        &self,
+        key: &K,
+        resource: &Resource,
+        replacing: Option<&Resource>,
+    ) -> Result<(), VaultError> {
+        match &self.quotas {
+            Some(index) => index.check(&index.used, &key.to_string(), resource, replacing),
+            None => Ok(()),
+        }
+    }
+
+    // Checks a batch of puts (`Some`) and removals (`None`), at most one per key
+    pub(crate) fn check_quotas_batch(
+        &self,
+        changes: &[(K, Option<Resource>)],
+    ) -> Result<(), VaultError> {
+        let Some(index) = &self.quotas else {
+            return Ok(());
+        };
+        let mut used = index.used.clone();
+        for (key, value) in changes {
+            let name = key.to_string();
+            if let Some(old) = self.resources.get(key) {
+                track(&index.quotas, &mut used, &name, old, false);
+            }
+            if let Some(new) = value {
+                index.check(&used, &name, new, None)?;
+                track(&index.quotas, &mut used, &name, new, true);
+            }
+        }
+        Ok(())
+    }
+}
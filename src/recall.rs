@@ -0,0 +1,341 @@
+use crate::backend::StorageBackend;
+use crate::embedding::cosine_similarity;
+use crate::entry::now_millis;
+use crate::resource::Resource;
+use crate::retention::DecayFunction;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::hash::Hash;
+use std::time::Duration;
+
+// BM25 parameters
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+// How the per-signal rankings are combined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fusion {
+    // Reciprocal rank fusion: each signal adds weight / (k + rank)
+    Rrf { k: f64 },
+    // Each signal adds weight * its score scaled to [0, 1]
+    Weighted,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecallOptions<K: Eq + Hash> {
+    pub limit: usize,
+    pub fusion: Fusion,
+    pub keyword_weight: f64,
+    pub vector_weight: f64,
+    pub recency_weight: f64,
+    pub recency_decay: DecayFunction,
+    // Maximal marginal relevance trade-off: 1.0 ranks on relevance alone,
+    // lower values push near-identical hits down. `None` skips diversification.
+    pub diversity: Option<f64>,
+    // Only these keys are considered, e.g. the result of a tag lookup
+    pub within: Option<HashSet<K>>,
+}
+
+impl<K: Eq + Hash> Default for RecallOptions<K> {
+    fn default() -> Self {
+        Self {
+            limit: 10,
+            fusion: Fusion::Rrf { k: 60.0 },
+            keyword_weight: 1.0,
+            vector_weight: 1.0,
+            recency_weight: 0.5,
+            recency_decay: DecayFunction::Exponential {
+                half_life: Duration::from_secs(24 * 60 * 60),
+            },
+            diversity: Some(0.7),
+            within: None,
+        }
+    }
+}
+
+// One signal's part in a hit's score
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Signal {
+    // BM25 score, cosine similarity or recency
+    pub raw: f64,
+    // Position among the candidates by this signal alone, from 1
+    pub rank: usize,
+    // What it added to the fused score
+    pub contribution: f64,
+}
+
+// How a recall hit's score was computed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Explanation {
+    pub keyword: Option<Signal>,
+    pub vector: Option<Signal>,
+    pub recency: Option<Signal>,
+    pub fused: f64,
+    // Highest similarity to a hit ranked above this one (MMR only)
+    pub redundancy: f64,
+    pub score: f64,
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let signals = [
+            ("keyword", &self.keyword),
+            ("vector", &self.vector),
+            ("recency", &self.recency),
+        ];
+        for (name, signal) in signals {
+            if let Some(signal) = signal {
+                write!(
+                    f,
+                    "{} {:.3} (#{}) +{:.4}; ",
+                    name, signal.raw, signal.rank, signal.contribution
+                )?;
+            }
+        }
+        write!(
+            f,
+            "fused {:.4}, redundancy {:.2}, score {:.4}",
+            self.fused, self.redundancy, self.score
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecallHit<K> {
+    pub key: K,
+    pub resource: Resource,
+    pub score: f64,
+    pub explanation: Explanation,
+}
+
+fn terms(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// Ranks `scores` from highest to lowest, assigning ranks from 1
+fn ranks<K: Eq + Hash + Clone + Display>(scores: &HashMap<K, f64>) -> HashMap<K, usize> {
+    let mut ordered: Vec<(&K, &f64)> = scores.iter().collect();
+    ordered.sort_by(|a, b| {
+        b.1.total_cmp(a.1)
+            .then_with(|| a.0.to_string().cmp(&b.0.to_string()))
+    });
+    ordered
+        .into_iter()
+        .enumerate()
+        .map(|(i, (key, _))| (key.clone(), i + 1))
+        .collect()
+}
+
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        0.0
+    } else {
+        a.intersection(b).count() as f64 / union as f64
+    }
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    // Finds the entries most relevant to `query` by fusing keyword relevance
+    // (BM25 over key and content), vector similarity (when an embedder is
+    // set) and recency. Candidates are entries matching the query by keyword
+    // or vector, or every entry when the query has no terms.
+    pub fn recall(&self, query: &str, options: &RecallOptions<K>) -> Vec<RecallHit<K>> {
+        let documents: HashMap<&K, Vec<String>> = self
+            .iter()
+            .filter(|(key, _)| {
+                options
+                    .within
+                    .as_ref()
+                    .is_none_or(|keys| keys.contains(key))
+            })
+            .map(|(key, resource)| (key, terms(&format!("{} {}", key, resource.to_text()))))
+            .collect();
+        let query_terms: HashSet<String> = terms(query).into_iter().collect();
+
+        // BM25
+        let mut keyword: HashMap<K, f64> = HashMap::new();
+        if !query_terms.is_empty() && !documents.is_empty() {
+            let count = documents.len() as f64;
+            let average = documents.values().map(Vec::len).sum::<usize>() as f64 / count;
+            for term in &query_terms {
+                let frequency = documents.values().filter(|doc| doc.contains(term)).count() as f64;
+                if frequency == 0.0 {
+                    continue;
+                }
+                let idf = (1.0 + (count - frequency + 0.5) / (frequency + 0.5)).ln();
+                for (key, doc) in &documents {
+                    let tf = doc.iter().filter(|word| *word == term).count() as f64;
+                    if tf > 0.0 {
+                        let norm = 1.0 - B + B * doc.len() as f64 / average.max(1.0);
+                        *keyword.entry((*key).clone()).or_default() +=
+                            idf * tf * (K1 + 1.0) / (tf + K1 * norm);
+                    }
+                }
+            }
+        }
+
+        let probe = self.embed(query).filter(|_| !query_terms.is_empty());
+        let mut vector: HashMap<K, f64> = HashMap::new();
+        if let Some(probe) = &probe {
+            for key in documents.keys() {
+                if let Some(embedding) = self.embedding(key) {
+                    let similarity = cosine_similarity(probe, embedding) as f64;
+                    if similarity > 0.0 {
+                        vector.insert((*key).clone(), similarity);
+                    }
+                }
+            }
+        }
+
+        let candidates: HashSet<K> = if query_terms.is_empty() {
+            documents.keys().map(|key| (*key).clone()).collect()
+        } else {
+            keyword.keys().chain(vector.keys()).cloned().collect()
+        };
+        let now = now_millis();
+        let recency: HashMap<K, f64> = candidates
+            .iter()
+            .filter_map(|key| {
+                let age = now.saturating_sub(self.entry_meta(key)?.last_used());
+                let recency = options.recency_decay.recency(Duration::from_millis(age));
+                Some((key.clone(), recency))
+            })
+            .collect();
+
+        let mut scored: Vec<(K, Explanation)> = {
+            let signals = [
+                (&keyword, options.keyword_weight),
+                (&vector, options.vector_weight),
+                (&recency, options.recency_weight),
+            ];
+            let ranked: Vec<HashMap<K, usize>> =
+                signals.iter().map(|(scores, _)| ranks(scores)).collect();
+            let maxima: Vec<f64> = signals
+                .iter()
+                .map(|(scores, _)| scores.values().copied().fold(0.0, f64::max))
+                .collect();
+            candidates
+                .into_iter()
+                .map(|key| {
+                    let mut parts = [None; 3];
+                    for (i, (scores, weight)) in signals.iter().enumerate() {
+                        let (Some(raw), Some(rank)) = (scores.get(&key), ranked[i].get(&key))
+                        else {
+                            continue;
+                        };
+                        let contribution = match options.fusion {
+                            Fusion::Rrf { k } => weight / (k + *rank as f64),
+                            Fusion::Weighted if maxima[i] > 0.0 => weight * raw / maxima[i],
+                            Fusion::Weighted => 0.0,
+                        };
+                        parts[i] = Some(Signal {
+                            raw: *raw,
+                            rank: *rank,
+                            contribution,
+                        });
+                    }
+                    let fused = parts.iter().flatten().map(|s| s.contribution).sum();
+                    let [keyword, vector, recency] = parts;
+                    let explanation = Explanation {
+                        keyword,
+                        vector,
+                        recency,
+                        fused,
+                        redundancy: 0.0,
+                        score: fused,
+                    };
+                    (key, explanation)
+                })
+                .collect()
+        };
+        scored.sort_by(|a, b| {
+            b.1.fused
+                .total_cmp(&a.1.fused)
+                .then_with(|| a.0.to_string().cmp(&b.0.to_string()))
+        });
+
+        let selected = match options.diversity {
+            Some(lambda) => self.diversify(scored, &documents, lambda, options.limit),
+            None => {
+                scored.truncate(options.limit);
+                scored
+            }
+        };
+        selected
+            .into_iter()
+            .filter_map(|(key, explanation)| {
+                Some(RecallHit {
+                    resource: self.get(&key)?.clone(),
+                    score: explanation.score,
+                    explanation,
+                    key,
+                })
+            })
+            .collect()
+    }
+
+    // Maximal marginal relevance: repeatedly picks the candidate with the best
+    // lambda * relevance - (1 - lambda) * similarity to anything already picked,
+    // with relevance scaled to [0, 1]. Similarity is the cosine of embeddings
+    // when both entries have one, otherwise the overlap of their words.
+    fn diversify(
+        &self,
+        mut remaining: Vec<(K, Explanation)>,
+        documents: &HashMap<&K, Vec<String>>,
+        lambda: f64,
+        limit: usize,
+    ) -> Vec<(K, Explanation)> {
+        let top = remaining.first().map_or(0.0, |(_, e)| e.fused);
+        let words: HashMap<&K, HashSet<String>> = documents
+            .iter()
+            .map(|(key, doc)| (*key, doc.iter().cloned().collect()))
+            .collect();
+        let similarity = |a: &K, b: &K| match (self.embedding(a), self.embedding(b)) {
+            (Some(x), Some(y)) => cosine_similarity(x, y).max(0.0) as f64,
+            _ => match (words.get(a), words.get(b)) {
+                (Some(x), Some(y)) => jaccard(x, y),
+                _ => 0.0,
+            },
+        };
+
+        let mut selected: Vec<(K, Explanation)> = Vec::new();
+        while selected.len() < limit && !remaining.is_empty() {
+            let mut best = 0;
+            let mut best_score = f64::NEG_INFINITY;
+            let mut best_redundancy = 0.0;
+            for (i, (key, explanation)) in remaining.iter().enumerate() {
+                let relevance = if top > 0.0 {
+                    explanation.fused / top
+                } else {
+                    0.0
+                };
+                let redundancy = selected
+                    .iter()
+                    .map(|(chosen, _)| similarity(key, chosen))
+                    .fold(0.0, f64::max);
+                let score = lambda * relevance - (1.0 - lambda) * redundancy;
+                if score > best_score {
+                    best = i;
+                    best_score = score;
+                    best_redundancy = redundancy;
+                }
+            }
+            let (key, mut explanation) = remaining.remove(best);
+            explanation.redundancy = best_redundancy;
+            explanation.score = best_score;
+            selected.push((key, explanation));
+        }
+        selected
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Resource {
@@ -7,6 +8,14 @@ pub enum Resource {
     SystemLogs(Vec<String>),
 }
 
+// The variant of a resource, without its payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ResourceKind {
+    TextMessage,
+    SensorData,
+    SystemLogs,
+}
+
 impl Resource {
     // Returns the estimated size of the resource in bytes
     pub fn size_bytes(&self) -> u64 {
@@ -16,4 +25,44 @@ impl Resource {
             Resource::SystemLogs(logs) => logs.iter().map(|log| log.len() as u64).sum(),
         }
     }
+
+    // Case-insensitive substring match against the resource's content
+    pub fn contains_text(&self, needle: &str) -> bool {
+        let needle = needle.to_lowercase();
+        match self {
+            Resource::TextMessage(s) => s.to_lowercase().contains(&needle),
+            Resource::SensorData(value) => value.to_string().contains(&needle),
+            Resource::SystemLogs(logs) => {
+                logs.iter().any(|log| log.to_lowercase().contains(&needle))
+            }
+        }
+    }
+
+    // Plain-text form of the content, as it would appear in a prompt
+    pub fn to_text(&self) -> String {
+        match self {
+            Resource::TextMessage(s) => s.clone(),
+            Resource::SensorData(value) => value.to_string(),
+            Resource::SystemLogs(logs) => logs.join("\n"),
+        }
+    }
+
+    pub fn kind(&self) -> ResourceKind {
+        match self {
+            Resource::TextMessage(_) => ResourceKind::TextMessage,
+            Resource::SensorData(_) => ResourceKind::SensorData,
+            Resource::SystemLogs(_) => ResourceKind::SystemLogs,
+        }
+    }
+}
+
+impl fmt::Display for ResourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ResourceKind::TextMessage => "text",
+            ResourceKind::SensorData => "sensor",
+            ResourceKind::SystemLogs => "log",
+        };
+        write!(f, "{}", name)
+    }
 }
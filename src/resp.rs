@@ -0,0 +1,685 @@
+use crate::acl::{Access, AccessPolicy, Permission};
+use crate::error::VaultError;
+use crate::memory::MemorySize;
+use crate::resource::Resource;
+use crate::vault::Vault;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How often the accept loop checks for a shutdown request
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// Keys returned per SCAN call when the client gives no COUNT
+const SCAN_COUNT: usize = 10;
+
+// Request size limits, as in Redis: an inline command or bulk header line,
+// the arguments of one command, and one argument (`proto-max-bulk-len`)
+const MAX_INLINE: usize = 64 * 1024;
+const MAX_ARGS: usize = 1024 * 1024;
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+// A reply before encoding; how nulls and maps go over the wire depends on the
+// protocol version the connection negotiated with HELLO
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Null,
+    Array(Vec<Reply>),
+    Map(Vec<(Reply, Reply)>),
+}
+
+impl Reply {
+    fn bulk(text: impl Into<String>) -> Self {
+        Reply::Bulk(text.into().into_bytes())
+    }
+
+    fn ok() -> Self {
+        Reply::Simple("OK".to_string())
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Reply::Error(format!("ERR {}", message.into()))
+    }
+
+    pub(crate) fn encode(&self, protocol: u8, out: &mut Vec<u8>) {
+        match self {
+            Reply::Simple(text) => out.extend_from_slice(format!("+{}\r\n", text).as_bytes()),
+            Reply::Error(text) => out.extend_from_slice(format!("-{}\r\n", text).as_bytes()),
+            Reply::Integer(n) => out.extend_from_slice(format!(":{}\r\n", n).as_bytes()),
+            Reply::Bulk(bytes) => {
+                out.extend_from_slice(format!("${}\r\n", bytes.len()).as_bytes());
+                out.extend_from_slice(bytes);
+                out.extend_from_slice(b"\r\n");
+            }
+            Reply::Null if protocol >= 3 => out.extend_from_slice(b"_\r\n"),
+            Reply::Null => out.extend_from_slice(b"$-1\r\n"),
+            Reply::Array(items) => {
+                out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+                for item in items {
+                    item.encode(protocol, out);
+                }
+            }
+            Reply::Map(pairs) => {
+                // RESP2 has no maps, so pairs are flattened into an array
+                let header = if protocol >= 3 {
+                    format!("%{}\r\n", pairs.len())
+                } else {
+                    format!("*{}\r\n", pairs.len() * 2)
+                };
+                out.extend_from_slice(header.as_bytes());
+                for (key, value) in pairs {
+                    key.encode(protocol, out);
+                    value.encode(protocol, out);
+                }
+            }
+        }
+    }
+}
+
+impl From<VaultError> for Reply {
+    fn from(error: VaultError) -> Self {
+        match error {
+            VaultError::VaultFull { capacity, .. } => Reply::Error(format!(
+                "OOM command not allowed when used memory > 'storage_capacity' ({} bytes)",
+                capacity
+            )),
+            denied @ VaultError::PermissionDenied { .. } => {
+                Reply::Error(format!("NOPERM {}", denied.message()))
+            }
+            other => Reply::error(other.message()),
+        }
+    }
+}
+
+// Per-connection state
+pub(crate) struct Session {
+    pub(crate) protocol: u8,
+    // Set by QUIT and SHUTDOWN; the connection closes after the reply
+    pub(crate) closing: bool,
+    pub(crate) shutdown: bool,
+    pub(crate) access: Access,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            protocol: 2,
+            closing: false,
+            shutdown: false,
+            access: Access::default(),
+        }
+    }
+}
+
+// Glob matching as in Redis KEYS: `*`, `?`, `[abc]`, `[a-z]`, `[^a]` and `\` escapes
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        Some((b'?', rest)) => !text.is_empty() && glob_match(rest, &text[1..]),
+        Some((b'[', rest)) => {
+            let Some(close) = rest.iter().position(|&c| c == b']') else {
+                return text.first() == Some(&b'[') && glob_match(rest, &text[1..]);
+            };
+            let Some(&c) = text.first() else {
+                return false;
+            };
+            let (negated, set) = match rest[..close].split_first() {
+                Some((b'^', set)) => (true, set),
+                _ => (false, &rest[..close]),
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < set.len() {
+                if i + 2 < set.len() && set[i + 1] == b'-' {
+                    found |= (set[i]..=set[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    found |= set[i] == c;
+                    i += 1;
+                }
+            }
+            found != negated && glob_match(&rest[close + 1..], &text[1..])
+        }
+        Some((b'\\', [escaped, rest @ ..])) => {
+            text.first() == Some(escaped) && glob_match(rest, &text[1..])
+        }
+        Some((c, rest)) => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
+
+fn text_arg(arg: &[u8]) -> Result<String, Reply> {
+    String::from_utf8(arg.to_vec()).map_err(|_| Reply::error("arguments must be valid UTF-8"))
+}
+
+fn int_arg(arg: &[u8]) -> Result<i64, Reply> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .ok_or_else(|| Reply::error("value is not an integer or out of range"))
+}
+
+fn wrong_arity(command: &str) -> Reply {
+    Reply::error(format!(
+        "wrong number of arguments for '{}' command",
+        command.to_lowercase()
+    ))
+}
+
+// Sorted live keys matching `pattern` that the client can read
+fn matching_keys(vault: &Vault<String>, access: &Access, pattern: &[u8]) -> Vec<String> {
+    let mut keys: Vec<String> = vault
+        .keys()
+        .filter(|key| glob_match(pattern, key.as_bytes()))
+        .filter(|key| access.allows(Permission::Read, key))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys
+}
+
+// Sets or clears a key's deadline; a deadline already passed deletes the key
+fn expire(
+    vault: &mut Vault<String>,
+    access: &Access,
+    key: &String,
+    millis: i64,
+) -> Result<(), VaultError> {
+    if millis <= 0 {
+        access.check_removal(vault, key)?;
+        vault.remove(key).map(|_| ())
+    } else {
+        vault.set_ttl(key, Some(Duration::from_millis(millis as u64)))
+    }
+}
+
+fn info(vault: &Vault<String>) -> String {
+    let expires = vault.keys().filter(|key| vault.ttl(key).is_some()).count();
+    format!(
+        "# Server\r\nredis_version:7.0.0\r\nmemoria_version:{}\r\nredis_mode:standalone\r\n\r\n\
+         # Memory\r\nused_memory:{}\r\nmaxmemory:{}\r\nmaxmemory_policy:noeviction\r\n\r\n\
+         # Keyspace\r\ndb0:keys={},expires={},avg_ttl=0\r\n",
+        env!("CARGO_PKG_VERSION"),
+        vault.current_usage(),
+        vault.storage_capacity.size_bytes(),
+        vault.len(),
+        expires
+    )
+}
+
+// Runs one command. Values are stored as text messages; other resource
+// kinds read back as WRONGTYPE.
+pub(crate) fn execute(vault: &mut Vault<String>, session: &mut Session, args: &[Vec<u8>]) -> Reply {
+    match run(vault, session, args) {
+        Ok(reply) | Err(reply) => reply,
+    }
+}
+
+fn run(vault: &mut Vault<String>, session: &mut Session, args: &[Vec<u8>]) -> Result<Reply, Reply> {
+    let Some((name, args)) = args.split_first() else {
+        return Err(Reply::error("empty command"));
+    };
+    let command = String::from_utf8_lossy(name).to_uppercase();
+    // Under an access policy only the connection-level commands work before AUTH
+    let open = matches!(
+        command.as_str(),
+        "PING" | "ECHO" | "HELLO" | "COMMAND" | "SELECT" | "AUTH" | "QUIT"
+    );
+    if !open && !session.access.authenticated() {
+        return Err(Reply::Error("NOAUTH Authentication required.".into()));
+    }
+    let access = session.access.clone();
+    // The argument at `i`, once the client is known to hold `permission` on it
+    let key = |i: usize, permission: Permission| {
+        let key = args
+            .get(i)
+            .ok_or_else(|| wrong_arity(&command))
+            .and_then(|k| text_arg(k))?;
+        access.check(permission, &key)?;
+        Ok::<_, Reply>(key)
+    };
+
+    let reply = match command.as_str() {
+        "PING" => match args {
+            [] => Reply::Simple("PONG".to_string()),
+            [message] => Reply::Bulk(message.clone()),
+            _ => return Err(wrong_arity(&command)),
+        },
+        "ECHO" => match args {
+            [message] => Reply::Bulk(message.clone()),
+            _ => return Err(wrong_arity(&command)),
+        },
+        "HELLO" => {
+            if let Some(version) = args.first() {
+                match int_arg(version)? {
+                    version @ 2..=3 => session.protocol = version as u8,
+                    _ => return Err(Reply::Error("NOPROTO unsupported protocol version".into())),
+                }
+            }
+            Reply::Map(vec![
+                (Reply::bulk("server"), Reply::bulk("redis")),
+                (Reply::bulk("version"), Reply::bulk("7.0.0")),
+                (
+                    Reply::bulk("proto"),
+                    Reply::Integer(session.protocol as i64),
+                ),
+                (Reply::bulk("id"), Reply::Integer(0)),
+                (Reply::bulk("mode"), Reply::bulk("standalone")),
+                (Reply::bulk("role"), Reply::bulk("master")),
+                (Reply::bulk("modules"), Reply::Array(Vec::new())),
+            ])
+        }
+        // Clients ask for command docs on connect; an empty list is acceptable
+        "COMMAND" => Reply::Array(Vec::new()),
+        "SELECT" => match args {
+            [db] if int_arg(db)? == 0 => Reply::ok(),
+            [_] => return Err(Reply::error("DB index is out of range")),
+            _ => return Err(wrong_arity(&command)),
+        },
+        // The username, when given, must name the principal holding the key
+        "AUTH" => {
+            let (user, password) = match args {
+                [password] => (None, password),
+                [user, password] => (Some(text_arg(user)?), password),
+                _ => return Err(wrong_arity(&command)),
+            };
+            let mut attempt = session.access.clone();
+            match attempt.authenticate(&text_arg(password)?) {
+                Ok(name) if user.as_ref().is_none_or(|user| *user == name) => {
+                    session.access = attempt;
+                    Reply::ok()
+                }
+                Err(VaultError::InvalidInput(message)) if !session.access.enforced() => {
+                    return Err(Reply::error(message))
+                }
+                _ => {
+                    return Err(Reply::Error(
+                        "WRONGPASS invalid username-password pair or user is disabled.".into(),
+                    ))
+                }
+            }
+        }
+        "GET" => match vault.get(&key(0, Permission::Read)?) {
+            None => Reply::Null,
+            Some(Resource::TextMessage(text)) => Reply::bulk(text.clone()),
+            Some(_) => {
+                return Err(Reply::Error(
+                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
+                ))
+            }
+        },
+        "SET" => {
+            let key = key(0, Permission::Write)?;
+            let value = text_arg(args.get(1).ok_or_else(|| wrong_arity(&command))?)?;
+            let (mut ttl, mut only_new, mut only_existing, mut keep_ttl) =
+                (None, false, false, false);
+            let mut options = args[2..].iter();
+            while let Some(option) = options.next() {
+                match String::from_utf8_lossy(option).to_uppercase().as_str() {
+                    "EX" => {
+                        let seconds =
+                            int_arg(options.next().ok_or_else(|| Reply::error("syntax error"))?)?;
+                        ttl =
+                            Some(seconds.checked_mul(1000).ok_or_else(|| {
+                                Reply::error("invalid expire time in 'set' command")
+                            })?)
+                    }
+                    "PX" => {
+                        ttl = Some(int_arg(
+                            options.next().ok_or_else(|| Reply::error("syntax error"))?,
+                        )?)
+                    }
+                    "NX" => only_new = true,
+                    "XX" => only_existing = true,
+                    "KEEPTTL" => keep_ttl = true,
+                    _ => return Err(Reply::error("syntax error")),
+                }
+            }
+            if ttl.is_some_and(|ttl| ttl <= 0) {
+                return Err(Reply::error("invalid expire time in 'set' command"));
+            }
+            let exists = vault.contains_key(&key);
+            if (only_new && exists) || (only_existing && !exists) {
+                return Ok(Reply::Null);
+            }
+            let resource = Resource::TextMessage(value);
+            if exists {
+                vault.update(&key, resource)?;
+                if !keep_ttl {
+                    vault.set_ttl(&key, None)?;
+                }
+            } else {
+                vault.add(key.clone(), resource)?;
+            }
+            if let Some(ttl) = ttl {
+                expire(vault, &access, &key, ttl)?;
+            }
+            Reply::ok()
+        }
+        "DEL" | "UNLINK" => {
+            if args.is_empty() {
+                return Err(wrong_arity(&command));
+            }
+            // Checked up front so a refusal leaves every key in place
+            let keys = args
+                .iter()
+                .map(|key| text_arg(key))
+                .collect::<Result<Vec<_>, _>>()?;
+            for key in &keys {
+                access.check_removal(vault, key)?;
+            }
+            let mut removed = 0;
+            for key in &keys {
+                if vault.remove(key).is_ok() {
+                    removed += 1;
+                }
+            }
+            Reply::Integer(removed)
+        }
+        "EXISTS" => {
+            if args.is_empty() {
+                return Err(wrong_arity(&command));
+            }
+            let mut found = 0;
+            for i in 0..args.len() {
+                found += vault.contains_key(&key(i, Permission::Read)?) as i64;
+            }
+            Reply::Integer(found)
+        }
+        "KEYS" => match args {
+            [pattern] => Reply::Array(
+                matching_keys(vault, &access, pattern)
+                    .into_iter()
+                    .map(Reply::bulk)
+                    .collect(),
+            ),
+            _ => return Err(wrong_arity(&command)),
+        },
+        // The cursor is an offset into the sorted key list
+        "SCAN" => {
+            let cursor =
+                int_arg(args.first().ok_or_else(|| wrong_arity(&command))?)?.max(0) as usize;
+            let (mut pattern, mut count) = (b"*".to_vec(), SCAN_COUNT);
+            let mut options = args[1..].iter();
+            while let Some(option) = options.next() {
+                let value = options.next().ok_or_else(|| Reply::error("syntax error"))?;
+                match String::from_utf8_lossy(option).to_uppercase().as_str() {
+                    "MATCH" => pattern = value.clone(),
+                    "COUNT" => count = int_arg(value)?.max(1) as usize,
+                    _ => return Err(Reply::error("syntax error")),
+                }
+            }
+            let keys = matching_keys(vault, &access, b"*");
+            let end = (cursor + count).min(keys.len());
+            let page = keys.get(cursor..end).unwrap_or_default();
+            let next = if end >= keys.len() { 0 } else { end };
+            let found = page
+                .iter()
+                .filter(|key| glob_match(&pattern, key.as_bytes()))
+                .map(|key| Reply::bulk(key.clone()))
+                .collect();
+            Reply::Array(vec![Reply::bulk(next.to_string()), Reply::Array(found)])
+        }
+        "EXPIRE" | "PEXPIRE" => {
+            let [_, amount] = args else {
+                return Err(wrong_arity(&command));
+            };
+            let key = key(0, Permission::Write)?;
+            let amount = int_arg(amount)?;
+            if !vault.contains_key(&key) {
+                return Ok(Reply::Integer(0));
+            }
+            let millis = if command == "EXPIRE" {
+                amount.saturating_mul(1000)
+            } else {
+                amount
+            };
+            expire(vault, &access, &key, millis)?;
+            Reply::Integer(1)
+        }
+        "PERSIST" => {
+            let key = key(0, Permission::Write)?;
+            if vault.ttl(&key).is_none() {
+                return Ok(Reply::Integer(0));
+            }
+            vault.set_ttl(&key, None)?;
+            Reply::Integer(1)
+        }
+        // -2 for a missing key, -1 for a key without a time-to-live
+        "TTL" | "PTTL" => {
+            let key = key(0, Permission::Read)?;
+            if !vault.contains_key(&key) {
+                return Ok(Reply::Integer(-2));
+            }
+            match vault.ttl(&key) {
+                None => Reply::Integer(-1),
+                Some(ttl) if command == "TTL" => {
+                    Reply::Integer(((ttl.as_millis() + 500) / 1000) as i64)
+                }
+                Some(ttl) => Reply::Integer(ttl.as_millis() as i64),
+            }
+        }
+        "DBSIZE" => {
+            access.check(Permission::Read, "")?;
+            Reply::Integer(vault.len() as i64)
+        }
+        "INFO" => {
+            access.check(Permission::Read, "")?;
+            Reply::bulk(info(vault))
+        }
+        "QUIT" => {
+            session.closing = true;
+            Reply::ok()
+        }
+        "SHUTDOWN" => {
+            access.check(Permission::Admin, "")?;
+            session.closing = true;
+            session.shutdown = true;
+            Reply::ok()
+        }
+        _ => {
+            return Err(Reply::error(format!(
+                "unknown command '{}'",
+                String::from_utf8_lossy(name)
+            )))
+        }
+    };
+    Ok(reply)
+}
+
+// Reads one command: a RESP array of bulk strings, or an inline command of
+// space-separated words. `None` at end of input.
+pub(crate) fn read_command<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<Vec<u8>>>> {
+    let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_string());
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            return Ok(None);
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        let Some(count) = line.strip_prefix('*') else {
+            let words: Vec<Vec<u8>> = line
+                .split_whitespace()
+                .map(|w| w.as_bytes().to_vec())
+                .collect();
+            if words.is_empty() {
+                continue;
+            }
+            return Ok(Some(words));
+        };
+        let count: usize = count
+            .parse()
+            .ok()
+            .filter(|count| *count <= MAX_ARGS)
+            .ok_or_else(|| invalid("invalid multibulk length"))?;
+        // Grown as arguments arrive rather than sized by what the client claims
+        let mut args = Vec::new();
+        for _ in 0..count {
+            let length: usize = read_line(reader)?
+                .trim_end()
+                .strip_prefix('$')
+                .and_then(|length| length.parse().ok())
+                .ok_or_else(|| invalid("expected a bulk string"))?;
+            if length > MAX_BULK_LEN {
+                return Err(invalid("invalid bulk length"));
+            }
+            let mut bulk = Vec::new();
+            reader
+                .by_ref()
+                .take(length as u64 + 2)
+                .read_to_end(&mut bulk)?;
+            if bulk.len() < length + 2 {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+            bulk.truncate(length);
+            args.push(bulk);
+        }
+        return Ok(Some(args));
+    }
+}
+
+// One line of at most `MAX_INLINE` bytes; empty at the end of the stream
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    let mut line = String::new();
+    reader
+        .by_ref()
+        .take(MAX_INLINE as u64 + 1)
+        .read_line(&mut line)?;
+    if line.len() > MAX_INLINE {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "too big inline request",
+        ));
+    }
+    Ok(line)
+}
+
+// A Redis-compatible front end for a `Vault<String>`, speaking RESP2 and,
+// after `HELLO 3`, RESP3. One thread per client; SHUTDOWN saves the vault
+// when a save path is set and stops the server.
+pub struct RespServer {
+    vault: Arc<Mutex<Vault<String>>>,
+    save_path: Option<String>,
+    stopping: Arc<AtomicBool>,
+    policy: Option<Arc<AccessPolicy>>,
+}
+
+impl RespServer {
+    pub fn new(vault: Vault<String>, save_path: Option<String>) -> Self {
+        Self {
+            vault: Arc::new(Mutex::new(vault)),
+            save_path,
+            stopping: Arc::new(AtomicBool::new(false)),
+            policy: None,
+        }
+    }
+
+    // Requires clients to AUTH with an API key as the password, and checks
+    // every command against the principal's permissions. SHUTDOWN needs admin.
+    pub fn with_policy(mut self, policy: AccessPolicy) -> Self {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
+    // Records every change clients make in the audit log at `path`, under the
+    // name of the principal making it
+    pub fn with_audit_log(self, path: &str) -> Result<Self, VaultError> {
+        self.vault
+            .lock()
+            .map_err(|_| VaultError::IoError(io::Error::other("vault lock poisoned")))?
+            .open_audit_log(path)?;
+        Ok(self)
+    }
+
+    // Saves back to `path` at shutdown
+    pub fn open(path: &str, location: String, capacity: MemorySize) -> Result<Self, VaultError> {
+        let vault = Vault::open_or_create(path, location, capacity)?;
+        Ok(Self::new(vault, Some(path.to_string())))
+    }
+
+    // Setting this to true stops the server as SHUTDOWN would
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stopping.clone()
+    }
+
+    // Accepts clients on `listener` until shut down, then saves the vault
+    pub fn serve(&self, listener: TcpListener) -> Result<(), VaultError> {
+        listener.set_nonblocking(true)?;
+        while !self.stopping.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    let vault = self.vault.clone();
+                    let stopping = self.stopping.clone();
+                    let access = Access::new(self.policy.clone());
+                    thread::spawn(move || serve_client(stream, &vault, &stopping, access));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let vault = self
+            .vault
+            .lock()
+            .map_err(|_| VaultError::IoError(io::Error::other("vault lock poisoned")))?;
+        match &self.save_path {
+            Some(path) => vault.save_to_file(path),
+            None => Ok(()),
+        }
+    }
+}
+
+fn serve_client(
+    stream: TcpStream,
+    vault: &Mutex<Vault<String>>,
+    stopping: &AtomicBool,
+    access: Access,
+) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(stream);
+    let mut session = Session {
+        access,
+        ..Session::default()
+    };
+    loop {
+        let args = match read_command(&mut reader) {
+            Ok(Some(args)) => args,
+            // Like Redis, answer a malformed or oversized request and hang up
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                let mut out = Vec::new();
+                Reply::error(format!("Protocol error: {}", e)).encode(session.protocol, &mut out);
+                let _ = writer.write_all(&out);
+                return;
+            }
+            _ => return,
+        };
+        let reply = match vault.lock() {
+            Ok(mut vault) => {
+                vault.set_actor(session.access.actor());
+                execute(&mut vault, &mut session, &args)
+            }
+            Err(_) => Reply::error("vault lock poisoned"),
+        };
+        let mut out = Vec::new();
+        reply.encode(session.protocol, &mut out);
+        if writer.write_all(&out).is_err() {
+            return;
+        }
+        if session.shutdown {
+            stopping.store(true, Ordering::SeqCst);
+        }
+        if session.closing {
+            return;
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use crate::backend::StorageBackend;
+use crate::entry::{now_millis, EntryMeta};
+use crate::error::VaultError;
+use crate::events::Removal;
+use crate::journal::JournalOp;
+use crate::resource::Resource;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::time::Duration;
+
+// How a memory's recency fades with time since it was last used
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecayFunction {
+    // Halves every `half_life`
+    Exponential { half_life: Duration },
+    // (1 + age / scale) ^ -exponent: drops fast at first, then lingers
+    PowerLaw { scale: Duration, exponent: f64 },
+}
+
+impl DecayFunction {
+    // Recency in (0, 1] for a memory last used `age` ago
+    pub fn recency(&self, age: Duration) -> f64 {
+        let age = age.as_secs_f64();
+        match *self {
+            DecayFunction::Exponential { half_life } => {
+                0.5f64.powf(age / half_life.as_secs_f64().max(f64::MIN_POSITIVE))
+            }
+            DecayFunction::PowerLaw { scale, exponent } => {
+                (1.0 + age / scale.as_secs_f64().max(f64::MIN_POSITIVE)).powf(-exponent)
+            }
+        }
+    }
+}
+
+// Combines recency, frequency and importance into a single retention score.
+// Each part lies in [0, 1], so the score ranges over the sum of the weights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetentionPolicy {
+    pub decay: DecayFunction,
+    pub recency_weight: f64,
+    pub frequency_weight: f64,
+    pub importance_weight: f64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            decay: DecayFunction::Exponential {
+                half_life: Duration::from_secs(24 * 60 * 60),
+            },
+            recency_weight: 0.4,
+            frequency_weight: 0.2,
+            importance_weight: 0.4,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    pub fn score(&self, meta: &EntryMeta, now: u64) -> f64 {
+        let age = Duration::from_millis(now.saturating_sub(meta.last_used()));
+        let recency = self.decay.recency(age);
+        // Saturates towards 1 as accesses pile up
+        let frequency = 1.0 - 1.0 / (1.0 + meta.access_count as f64);
+        self.recency_weight * recency
+            + self.frequency_weight * frequency
+            + self.importance_weight * meta.importance.clamp(0.0, 1.0)
+    }
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    pub fn set_retention_policy(&mut self, policy: RetentionPolicy) {
+        self.retention = policy;
+    }
+
+    pub fn retention_policy(&self) -> &RetentionPolicy {
+        &self.retention
+    }
+
+    // With eviction on, a full vault drops its lowest-retention memories to make
+    // room for new ones instead of rejecting them. Applies to `add`, `add_many`
+    // and `update`; transactions are still rejected when they do not fit.
+    pub fn set_eviction(&mut self, enabled: bool) {
+        self.evict_on_full = enabled;
+    }
+
+    pub fn retention_score(&self, key: &K) -> Option<f64> {
+        self.get(key)?;
+        Some(self.retention.score(self.meta.get(key)?, now_millis()))
+    }
+
+    // Records a recall of the entry, making it more likely to be kept
+    pub fn reinforce(&mut self, key: &K) -> Result<(), VaultError> {
+        self.edit_meta(key, EntryMeta::record_access)
+    }
+
+    pub fn set_importance(&mut self, key: &K, importance: f64) -> Result<(), VaultError> {
+        if !(0.0..=1.0).contains(&importance) {
+            return Err(VaultError::InvalidInput(format!(
+                "Importance must be between 0 and 1, got {}",
+                importance
+            )));
+        }
+        self.edit_meta(key, |meta| meta.importance = importance)
+    }
+
+    pub(crate) fn edit_meta(
+        &mut self,
+        key: &K,
+        edit: impl FnOnce(&mut EntryMeta),
+    ) -> Result<(), VaultError> {
+        let resource = self
+            .get(key)
+            .cloned()
+            .ok_or_else(|| VaultError::ResourceNotFound(key.to_string()))?;
+        let mut meta = self.meta.get(key).cloned().unwrap_or_default();
+        edit(&mut meta);
+        if self.is_journaling() {
+            self.log(&[JournalOp::Put {
+                key: key.clone(),
+                resource,
+                meta: meta.clone(),
+            }])?;
+        }
+        self.replace_meta(key, meta)
+    }
+
+    // Stores new metadata for an entry without journaling it
+    pub(crate) fn replace_meta(&mut self, key: &K, meta: EntryMeta) -> Result<(), VaultError> {
+        self.resources.put_meta(key, &meta)?;
+        self.meta.insert(key.clone(), meta);
+        Ok(())
+    }
+
+    // The `n` live entries with the highest retention score, best first
+    pub fn top_memories(&self, n: usize) -> Vec<(&K, &Resource, f64)> {
+        let now = now_millis();
+        let mut scored: Vec<_> = self
+            .iter()
+            .map(|(key, res)| {
+                let score = self
+                    .meta
+                    .get(key)
+                    .map_or(0.0, |meta| self.retention.score(meta, now));
+                (key, res, score)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.2.total_cmp(&a.2));
+        scored.truncate(n);
+        scored
+    }
+
+    // When eviction is on and `needed` more bytes would not fit on top of
+    // `current`, evicts the lowest-retention entries other than `keep` until
+    // they do. Returns the number of bytes freed.
+    pub(crate) fn make_room(
+        &mut self,
+        current: u64,
+        needed: u64,
+        keep: Option<&K>,
+    ) -> Result<u64, VaultError> {
+        let capacity = self.storage_capacity.size_bytes();
+        if !self.evict_on_full || current + needed <= capacity || needed > capacity {
+            return Ok(0);
+        }
+
+        let now = now_millis();
+        let mut candidates: Vec<(f64, K, u64)> = self
+            .meta
+            .iter()
+            .filter(|(key, _)| Some(*key) != keep)
+            .filter(|(key, _)| self.check_removal(key, &HashSet::new()).is_ok())
+            .filter_map(|(key, meta)| {
+                let size = self.resources.size_of(key)?;
+                Some((self.retention.score(meta, now), key.clone(), size))
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut freed = 0;
+        let mut victims = Vec::new();
+        for (_, key, size) in candidates {
+            if current - freed + needed <= capacity {
+                break;
+            }
+            freed += size;
+            victims.push(key);
+        }
+        if current - freed + needed > capacity {
+            return Ok(0);
+        }
+
+        let ops: Vec<JournalOp<K>> = victims
+            .iter()
+            .map(|key| JournalOp::Delete { key: key.clone() })
+            .collect();
+        self.log(&ops)?;
+        for key in &victims {
+            self.remove_entry(key, Removal::Evicted)?;
+        }
+        Ok(freed)
+    }
+}
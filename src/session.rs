@@ -0,0 +1,318 @@
+use crate::backend::{MemoryBackend, StorageBackend};
+use crate::error::VaultError;
+use crate::resource::Resource;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Role {
+    User,
+    Assistant,
+    Tool,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            Role::Tool => "tool",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = VaultError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "assistant" => Ok(Role::Assistant),
+            "tool" => Ok(Role::Tool),
+            other => Err(VaultError::InvalidInput(format!(
+                "Unknown role '{}'",
+                other
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    // Position in the session, counting from 0; never reused
+    pub seq: u64,
+    pub role: Role,
+    pub content: String,
+}
+
+// How much of a session is kept verbatim
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Bytes(u64),
+    // Approximated as whitespace-separated words
+    Tokens(u64),
+}
+
+impl Budget {
+    pub fn limit(&self) -> u64 {
+        match *self {
+            Budget::Bytes(n) | Budget::Tokens(n) => n,
+        }
+    }
+
+    pub fn cost(&self, text: &str) -> u64 {
+        match self {
+            Budget::Bytes(_) => text.len() as u64,
+            Budget::Tokens(_) => text.split_whitespace().count() as u64,
+        }
+    }
+
+    // Cuts `text` down to at most `limit` units
+    fn truncate(&self, text: &str, limit: u64) -> String {
+        match self {
+            Budget::Bytes(_) => {
+                let mut end = (limit as usize).min(text.len());
+                while !text.is_char_boundary(end) {
+                    end -= 1;
+                }
+                text[..end].to_string()
+            }
+            Budget::Tokens(_) => text
+                .split_whitespace()
+                .take(limit as usize)
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+// Condenses turns that no longer fit a session's budget. `previous` is the
+// summary those turns followed, if the session has been summarized before.
+pub trait Summarizer {
+    fn summarize(&mut self, previous: Option<&str>, turns: &[Turn]) -> String;
+}
+
+// Chat sessions kept in a vault. Each turn is a `TextMessage` stored under
+// `session/<id>/<seq>/<role>`, and a session's running summary under
+// `session/<id>/summary`.
+pub struct SessionStore<B = MemoryBackend<String>> {
+    vault: Vault<String, B>,
+    summarizer: Option<Box<dyn Summarizer + Send>>,
+    default_budget: Budget,
+    budgets: HashMap<String, Budget>,
+}
+
+fn prefix(session: &str) -> String {
+    format!("session/{}/", session)
+}
+
+fn summary_key(session: &str) -> String {
+    format!("session/{}/summary", session)
+}
+
+fn turn_key(session: &str, seq: u64, role: Role) -> String {
+    // Zero-padded so turns sort in order by key
+    format!("session/{}/{:010}/{}", session, seq, role)
+}
+
+fn parse_turn(key: &str, prefix: &str, resource: &Resource) -> Option<Turn> {
+    let (seq, role) = key.strip_prefix(prefix)?.split_once('/')?;
+    let Resource::TextMessage(content) = resource else {
+        return None;
+    };
+    Some(Turn {
+        seq: seq.parse().ok()?,
+        role: role.parse().ok()?,
+        content: content.clone(),
+    })
+}
+
+fn check_id(session: &str) -> Result<(), VaultError> {
+    if session.is_empty() || session.contains('/') {
+        return Err(VaultError::InvalidInput(format!(
+            "Invalid session id '{}'",
+            session
+        )));
+    }
+    Ok(())
+}
+
+impl<B: StorageBackend<String>> SessionStore<B> {
+    pub fn new(vault: Vault<String, B>, default_budget: Budget) -> Self {
+        Self {
+            vault,
+            summarizer: None,
+            default_budget,
+            budgets: HashMap::new(),
+        }
+    }
+
+    // Without a summarizer, turns that overflow a budget are simply dropped
+    pub fn with_summarizer<S: Summarizer + Send + 'static>(mut self, summarizer: S) -> Self {
+        self.summarizer = Some(Box::new(summarizer));
+        self
+    }
+
+    pub fn set_budget(&mut self, session: &str, budget: Budget) {
+        self.budgets.insert(session.to_string(), budget);
+    }
+
+    pub fn budget(&self, session: &str) -> Budget {
+        self.budgets
+            .get(session)
+            .copied()
+            .unwrap_or(self.default_budget)
+    }
+
+    pub fn vault(&self) -> &Vault<String, B> {
+        &self.vault
+    }
+
+    pub fn into_vault(self) -> Vault<String, B> {
+        self.vault
+    }
+
+    // Ids of every session with stored turns or a summary, sorted
+    pub fn sessions(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .vault
+            .keys()
+            .filter_map(|key| key.strip_prefix("session/")?.split_once('/'))
+            .map(|(id, _)| id.to_string())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    // Every stored turn of a session, oldest first
+    pub fn turns(&self, session: &str) -> Vec<Turn> {
+        let prefix = prefix(session);
+        let mut turns: Vec<Turn> = self
+            .vault
+            .iter()
+            .filter_map(|(key, resource)| parse_turn(key, &prefix, resource))
+            .collect();
+        turns.sort_by_key(|turn| turn.seq);
+        turns
+    }
+
+    pub fn summary(&self, session: &str) -> Option<&str> {
+        match self.vault.get(&summary_key(session))? {
+            Resource::TextMessage(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    // The most recent turns, at most `n` of them, that fit within `budget`
+    // together, oldest first
+    pub fn window(&self, session: &str, n: usize, budget: Budget) -> Vec<Turn> {
+        let mut used = 0;
+        let mut window: Vec<Turn> = self
+            .turns(session)
+            .into_iter()
+            .rev()
+            .take(n)
+            .take_while(|turn| {
+                used += budget.cost(&turn.content);
+                used <= budget.limit()
+            })
+            .collect();
+        window.reverse();
+        window
+    }
+
+    // Appends a turn. If the session then exceeds its budget, its oldest turns
+    // are folded into the summary (or dropped without a summarizer) until the
+    // remaining turns use at most half of it. The turn, the folding and the
+    // summary are written in one transaction, so on error the session is left
+    // as it was. Returns the turn's sequence number.
+    pub fn push(&mut self, session: &str, role: Role, content: &str) -> Result<u64, VaultError> {
+        check_id(session)?;
+        let turns = self.turns(session);
+        let seq = turns.last().map_or(0, |turn| turn.seq + 1);
+
+        let budget = self.budget(session);
+        let mut kept: Vec<Turn> = turns;
+        kept.push(Turn {
+            seq,
+            role,
+            content: content.to_string(),
+        });
+        let summary_cost = self.summary(session).map_or(0, |text| budget.cost(text));
+        let mut kept_cost: u64 = kept.iter().map(|turn| budget.cost(&turn.content)).sum();
+        let mut folded = Vec::new();
+        let mut summary = None;
+        if kept_cost + summary_cost > budget.limit() {
+            // Always keep the newest turn, even when it alone is over budget
+            while kept.len() > 1 && kept_cost > budget.limit() / 2 {
+                let turn = kept.remove(0);
+                kept_cost -= budget.cost(&turn.content);
+                folded.push(turn);
+            }
+            summary = self.fold(session, budget, &folded, kept_cost);
+        }
+
+        self.vault.transaction(|tx| {
+            tx.add(
+                turn_key(session, seq, role),
+                Resource::TextMessage(content.to_string()),
+            )?;
+            for turn in &folded {
+                tx.remove(&turn_key(session, turn.seq, turn.role))?;
+            }
+            if let Some(summary) = summary {
+                let key = summary_key(session);
+                let resource = Resource::TextMessage(summary);
+                if tx.contains_key(&key) {
+                    tx.update(&key, resource)?;
+                } else {
+                    tx.add(key, resource)?;
+                }
+            }
+            Ok(())
+        })?;
+        Ok(seq)
+    }
+
+    // The summary to store once `folded` turns are gone, cut to what the
+    // budget leaves after `kept_cost`. With no turns to fold, only the
+    // existing summary is too long, and it is cut without a new one being made.
+    fn fold(
+        &mut self,
+        session: &str,
+        budget: Budget,
+        folded: &[Turn],
+        kept_cost: u64,
+    ) -> Option<String> {
+        let room = budget.limit().saturating_sub(kept_cost);
+        let previous = match self.vault.get(&summary_key(session)) {
+            Some(Resource::TextMessage(text)) => Some(text.as_str()),
+            _ => None,
+        };
+        if folded.is_empty() {
+            return previous.map(|text| budget.truncate(text, room));
+        }
+        let text = self.summarizer.as_mut()?.summarize(previous, folded);
+        Some(budget.truncate(&text, room))
+    }
+
+    // Removes every turn and the summary of a session
+    pub fn clear(&mut self, session: &str) -> Result<usize, VaultError> {
+        let prefix = prefix(session);
+        let keys: Vec<String> = self
+            .vault
+            .keys()
+            .filter(|key| key.starts_with(&prefix))
+            .cloned()
+            .collect();
+        self.vault
+            .remove_many(&keys)
+            .into_iter()
+            .try_for_each(|r| r.map(|_| ()))?;
+        Ok(keys.len())
+    }
+}
@@ -0,0 +1,118 @@
+use crate::entry::{now_millis, EntryMeta};
+use crate::memory::MemorySize;
+use crate::resource::Resource;
+use crate::stats::{self, VaultStats};
+use crate::vault::{search_entries, Vault};
+use imbl::HashMap as ImHashMap;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::hash::Hash;
+
+// A frozen, read-only view of a vault. Taking one is O(1): it shares structure
+// with the live vault, and only the parts the vault later changes are copied.
+// Expiry is judged at the moment the snapshot was taken.
+#[derive(Clone)]
+pub struct VaultSnapshot<K>
+where
+    K: Eq + Hash + Clone,
+{
+    pub location: String,
+    pub storage_capacity: MemorySize,
+    resources: ImHashMap<K, Resource>,
+    meta: ImHashMap<K, EntryMeta>,
+    usage: u64,
+    taken_at: u64,
+}
+
+impl<K> VaultSnapshot<K>
+where
+    K: Eq + Hash + Display + Clone,
+{
+    pub fn taken_at(&self) -> u64 {
+        self.taken_at
+    }
+
+    pub fn current_usage(&self) -> u64 {
+        self.usage
+    }
+
+    // Entries live when the snapshot was taken, as `iter` lists them
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    pub fn get(&self, key: &K) -> Option<&Resource> {
+        match self.meta.get(key) {
+            Some(meta) if meta.is_expired(self.taken_at) => None,
+            _ => self.resources.get(key),
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn entry_meta(&self, key: &K) -> Option<&EntryMeta> {
+        self.meta.get(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Resource)> {
+        self.resources.iter().filter(move |(key, _)| {
+            !self
+                .meta
+                .get(*key)
+                .is_some_and(|m| m.is_expired(self.taken_at))
+        })
+    }
+
+    pub fn search(&self, query: &str) -> Vec<(&K, &Resource)> {
+        search_entries(self.iter(), query)
+    }
+
+    // The same stats a vault reports, as of when the snapshot was taken. A
+    // snapshot keeps no quotas, so none are listed.
+    pub fn stats(&self) -> VaultStats {
+        let entries = self.iter().map(|(key, resource)| {
+            let created = self.meta.get(key).map(|meta| meta.created_at);
+            (key, resource, created)
+        });
+        stats::collect(
+            &self.location,
+            &self.storage_capacity,
+            self.usage,
+            entries,
+            self.taken_at,
+        )
+    }
+
+    pub fn summary(&self) {
+        print!("{}", self.stats());
+    }
+}
+
+impl<K> Vault<K>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+{
+    // Captures the current contents; later mutations of the vault do not show
+    // up in the snapshot
+    pub fn snapshot(&self) -> VaultSnapshot<K> {
+        VaultSnapshot {
+            location: self.location.clone(),
+            storage_capacity: self.storage_capacity.clone(),
+            resources: self.resources.map().clone(),
+            meta: self.meta.clone(),
+            usage: self.current_usage(),
+            taken_at: now_millis(),
+        }
+    }
+}
@@ -0,0 +1,684 @@
+use crate::backend::{StorageBackend, CACHE_ENTRIES};
+use crate::entry::EntryMeta;
+use crate::error::VaultError;
+use crate::graph::{Edge, OnDelete};
+use crate::memory::MemorySize;
+use crate::recall::{RecallHit, RecallOptions};
+use crate::resource::Resource;
+use crate::vault::{invalid_data, Vault};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::hash::Hash;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+// Keys are stored as JSON so any serializable key type round-trips; `label`
+// holds the display form for people querying the file by hand
+const SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+    CREATE TABLE IF NOT EXISTS vault (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        location TEXT NOT NULL,
+        capacity TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS entries (
+        key TEXT PRIMARY KEY,
+        label TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        resource TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS meta (
+        key TEXT PRIMARY KEY REFERENCES entries(key) ON DELETE CASCADE,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL,
+        expires_at INTEGER,
+        importance REAL NOT NULL DEFAULT 0.5,
+        last_accessed INTEGER NOT NULL DEFAULT 0,
+        access_count INTEGER NOT NULL DEFAULT 0,
+        duplicates INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS tags (
+        key TEXT NOT NULL REFERENCES entries(key) ON DELETE CASCADE,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (key, tag)
+    );
+    CREATE INDEX IF NOT EXISTS tags_by_tag ON tags(tag);
+    CREATE TABLE IF NOT EXISTS edges (
+        source TEXT NOT NULL REFERENCES entries(key) ON DELETE CASCADE,
+        relation TEXT NOT NULL,
+        target TEXT NOT NULL REFERENCES entries(key) ON DELETE CASCADE,
+        on_delete TEXT NOT NULL,
+        PRIMARY KEY (source, relation, target)
+    );
+";
+
+fn sql_error(e: rusqlite::Error) -> VaultError {
+    VaultError::IoError(std::io::Error::other(e.to_string()))
+}
+
+// Adds the columns that files created by older versions lack
+fn upgrade(conn: &Connection) -> Result<(), VaultError> {
+    let columns: Vec<String> = conn
+        .prepare("SELECT name FROM pragma_table_info('meta')")
+        .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+        .map_err(sql_error)?;
+    for (column, definition) in [
+        ("importance", "REAL NOT NULL DEFAULT 0.5"),
+        ("last_accessed", "INTEGER NOT NULL DEFAULT 0"),
+        ("access_count", "INTEGER NOT NULL DEFAULT 0"),
+        ("duplicates", "INTEGER NOT NULL DEFAULT 0"),
+    ] {
+        if !columns.iter().any(|name| name == column) {
+            conn.execute_batch(&format!(
+                "ALTER TABLE meta ADD COLUMN {} {}",
+                column, definition
+            ))
+            .map_err(sql_error)?;
+        }
+    }
+    Ok(())
+}
+
+fn encode_key<K: Serialize>(key: &K) -> Result<String, VaultError> {
+    serde_json::to_string(key).map_err(invalid_data)
+}
+
+// Inserts or replaces the metadata row of the entry stored under `key`
+fn upsert_meta(conn: &Connection, key: &str, meta: &EntryMeta) -> Result<(), VaultError> {
+    conn.execute(
+        "INSERT INTO meta (key, created_at, updated_at, expires_at, importance,
+                           last_accessed, access_count, duplicates)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT(key) DO UPDATE SET
+             created_at = ?2, updated_at = ?3, expires_at = ?4, importance = ?5,
+             last_accessed = ?6, access_count = ?7, duplicates = ?8",
+        params![
+            key,
+            meta.created_at as i64,
+            meta.updated_at as i64,
+            meta.expires_at.map(|at| at as i64),
+            meta.importance,
+            meta.last_accessed as i64,
+            meta.access_count as i64,
+            meta.duplicates as i64
+        ],
+    )
+    .map_err(sql_error)?;
+    Ok(())
+}
+
+fn on_delete_name(on_delete: OnDelete) -> &'static str {
+    match on_delete {
+        OnDelete::Restrict => "restrict",
+        OnDelete::Cascade => "cascade",
+        OnDelete::Nullify => "nullify",
+    }
+}
+
+fn parse_on_delete(name: &str) -> OnDelete {
+    match name {
+        "restrict" => OnDelete::Restrict,
+        "cascade" => OnDelete::Cascade,
+        _ => OnDelete::Nullify,
+    }
+}
+
+// What the index remembers about a stored entry
+struct Slot {
+    size: u64,
+    // Filled the first time the resource is read; dropped again by `flush`,
+    // or by the next write once too many are held
+    cache: OnceLock<Resource>,
+}
+
+// Backend keeping entries, their metadata and tags in a single SQLite file.
+// Only keys and sizes stay in RAM; resources are read when first needed and
+// held while borrowed from the backend, like `DiskBackend` does.
+pub struct SqliteBackend<K> {
+    // Behind a mutex so reads through `&self` can use the connection
+    conn: Mutex<Connection>,
+    index: HashMap<K, Slot>,
+    cached: AtomicUsize,
+    // A failed read, reported by the next `flush`
+    deferred: Mutex<Option<VaultError>>,
+}
+
+impl<K> SqliteBackend<K>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+{
+    pub fn open(path: &str) -> Result<Self, VaultError> {
+        let conn = Connection::open(Path::new(path)).map_err(sql_error)?;
+        conn.execute_batch(SCHEMA).map_err(sql_error)?;
+        upgrade(&conn)?;
+
+        let mut index = HashMap::new();
+        {
+            let mut stmt = conn
+                .prepare("SELECT key, size FROM entries")
+                .map_err(sql_error)?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+                })
+                .map_err(sql_error)?;
+            for row in rows {
+                let (key, size) = row.map_err(sql_error)?;
+                let key: K = serde_json::from_str(&key).map_err(invalid_data)?;
+                index.insert(
+                    key,
+                    Slot {
+                        size: size as u64,
+                        cache: OnceLock::new(),
+                    },
+                );
+            }
+        }
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            index,
+            cached: AtomicUsize::new(0),
+            deferred: Mutex::new(None),
+        })
+    }
+
+    // Resources currently paged in
+    pub fn cached_entries(&self) -> usize {
+        self.cached.load(Ordering::Relaxed)
+    }
+
+    fn lock_conn(&self) -> Result<MutexGuard<'_, Connection>, VaultError> {
+        self.conn
+            .lock()
+            .map_err(|_| VaultError::IoError(std::io::Error::other("database lock poisoned")))
+    }
+
+    fn read(&self, key: &K) -> Result<Resource, VaultError> {
+        let json: String = self
+            .lock_conn()?
+            .query_row(
+                "SELECT resource FROM entries WHERE key = ?1",
+                params![encode_key(key)?],
+                |row| row.get(0),
+            )
+            .map_err(sql_error)?;
+        serde_json::from_str(&json).map_err(invalid_data)
+    }
+
+    // Pages a resource in. A read failure leaves the entry out and is
+    // reported by the next `flush`.
+    fn load<'a>(&'a self, key: &K, slot: &'a Slot) -> Option<&'a Resource> {
+        if let Some(resource) = slot.cache.get() {
+            return Some(resource);
+        }
+        match self.read(key) {
+            Ok(resource) => {
+                if slot.cache.set(resource).is_ok() {
+                    self.cached.fetch_add(1, Ordering::Relaxed);
+                }
+                slot.cache.get()
+            }
+            Err(e) => {
+                if let Ok(mut deferred) = self.deferred.lock() {
+                    deferred.get_or_insert(e);
+                }
+                None
+            }
+        }
+    }
+
+    // Lets go of every paged-in resource once there are too many, before a write
+    fn trim_cache(&mut self) {
+        if *self.cached.get_mut() > CACHE_ENTRIES {
+            self.release_cache();
+        }
+    }
+
+    fn release_cache(&mut self) {
+        for slot in self.index.values_mut() {
+            slot.cache = OnceLock::new();
+        }
+        *self.cached.get_mut() = 0;
+    }
+
+    fn current(&self, key: &K) -> Result<Option<Resource>, VaultError> {
+        match self.index.get(key) {
+            Some(slot) => match slot.cache.get() {
+                Some(cached) => Ok(Some(cached.clone())),
+                None => self.read(key).map(Some),
+            },
+            None => Ok(None),
+        }
+    }
+
+    // Stores a resource, and its metadata if given, in one SQLite transaction
+    fn write(
+        &mut self,
+        key: K,
+        resource: Resource,
+        meta: Option<&EntryMeta>,
+    ) -> Result<Option<Resource>, VaultError> {
+        self.trim_cache();
+        let old = self.current(&key)?;
+        let size = resource.size_bytes();
+        let json = serde_json::to_string(&resource).map_err(invalid_data)?;
+        let encoded = encode_key(&key)?;
+        {
+            let mut conn = self.lock_conn()?;
+            let tx = conn.transaction().map_err(sql_error)?;
+            // An upsert rather than REPLACE, which would cascade away the entry's tags
+            tx.execute(
+                "INSERT INTO entries (key, label, kind, size, resource) VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(key) DO UPDATE SET
+                     label = ?2, kind = ?3, size = ?4, resource = ?5",
+                params![
+                    encoded,
+                    key.to_string(),
+                    resource.kind().to_string(),
+                    size as i64,
+                    json
+                ],
+            )
+            .map_err(sql_error)?;
+            if let Some(meta) = meta {
+                upsert_meta(&tx, &encoded, meta)?;
+            }
+            tx.commit().map_err(sql_error)?;
+        }
+        let slot = Slot {
+            size,
+            cache: OnceLock::new(),
+        };
+        if let Some(previous) = self.index.insert(key, slot) {
+            if previous.cache.get().is_some() {
+                *self.cached.get_mut() -= 1;
+            }
+        }
+        Ok(old)
+    }
+
+    // Location and capacity recorded by `Vault::open_sqlite`
+    pub fn settings(&self) -> Result<Option<(String, MemorySize)>, VaultError> {
+        let row: Option<(String, String)> = self
+            .lock_conn()?
+            .query_row("SELECT location, capacity FROM vault", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()
+            .map_err(sql_error)?;
+        match row {
+            Some((location, capacity)) => Ok(Some((
+                location,
+                serde_json::from_str(&capacity).map_err(invalid_data)?,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    fn store_settings(&self, location: &str, capacity: &MemorySize) -> Result<(), VaultError> {
+        let capacity = serde_json::to_string(capacity).map_err(invalid_data)?;
+        self.lock_conn()?
+            .execute(
+                "INSERT INTO vault (id, location, capacity) VALUES (0, ?1, ?2)
+                 ON CONFLICT(id) DO UPDATE SET location = ?1, capacity = ?2",
+                params![location, capacity],
+            )
+            .map_err(sql_error)?;
+        Ok(())
+    }
+}
+
+impl<K> StorageBackend<K> for SqliteBackend<K>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+{
+    fn get(&self, key: &K) -> Option<&Resource> {
+        self.load(key, self.index.get(key)?)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.index.contains_key(key)
+    }
+
+    fn insert(&mut self, key: K, resource: Resource) -> Result<Option<Resource>, VaultError> {
+        self.write(key, resource, None)
+    }
+
+    fn insert_with_meta(
+        &mut self,
+        key: K,
+        resource: Resource,
+        meta: &EntryMeta,
+    ) -> Result<Option<Resource>, VaultError> {
+        self.write(key, resource, Some(meta))
+    }
+
+    fn put_meta(&mut self, key: &K, meta: &EntryMeta) -> Result<(), VaultError> {
+        if !self.index.contains_key(key) {
+            return Ok(());
+        }
+        upsert_meta(&*self.lock_conn()?, &encode_key(key)?, meta)
+    }
+
+    fn remove(&mut self, key: &K) -> Result<Option<Resource>, VaultError> {
+        self.trim_cache();
+        let Some(old) = self.current(key)? else {
+            return Ok(None);
+        };
+        self.lock_conn()?
+            .execute(
+                "DELETE FROM entries WHERE key = ?1",
+                params![encode_key(key)?],
+            )
+            .map_err(sql_error)?;
+        if let Some(slot) = self.index.remove(key) {
+            if slot.cache.get().is_some() {
+                *self.cached.get_mut() -= 1;
+            }
+        }
+        Ok(Some(old))
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    fn keys(&self) -> Box<dyn Iterator<Item = &K> + '_> {
+        Box::new(self.index.keys())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (&K, &Resource)> + '_> {
+        Box::new(
+            self.index
+                .iter()
+                .filter_map(|(key, slot)| self.load(key, slot).map(|res| (key, res))),
+        )
+    }
+
+    fn size_of(&self, key: &K) -> Option<u64> {
+        self.index.get(key).map(|slot| slot.size)
+    }
+
+    // Every statement is already committed, so this lets go of paged-in
+    // resources and reports a read that failed since the last flush
+    fn flush(&mut self) -> Result<(), VaultError> {
+        self.release_cache();
+        match self.deferred.get_mut() {
+            Ok(deferred) => deferred.take().map_or(Ok(()), Err),
+            Err(_) => Err(VaultError::IoError(std::io::Error::other(
+                "deferred error lock poisoned",
+            ))),
+        }
+    }
+
+    fn load_meta(&self) -> Result<Option<Vec<u8>>, VaultError> {
+        let conn = self.lock_conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT key, created_at, updated_at, expires_at, importance, last_accessed,
+                        access_count, duplicates
+                 FROM meta",
+            )
+            .map_err(sql_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    EntryMeta {
+                        created_at: row.get::<_, i64>(1)? as u64,
+                        updated_at: row.get::<_, i64>(2)? as u64,
+                        expires_at: row.get::<_, Option<i64>>(3)?.map(|at| at as u64),
+                        importance: row.get(4)?,
+                        last_accessed: row.get::<_, i64>(5)? as u64,
+                        access_count: row.get::<_, i64>(6)? as u64,
+                        duplicates: row.get::<_, i64>(7)? as u64,
+                    },
+                ))
+            })
+            .map_err(sql_error)?;
+        let mut meta = HashMap::new();
+        for row in rows {
+            let (key, entry) = row.map_err(sql_error)?;
+            let key: K = serde_json::from_str(&key).map_err(invalid_data)?;
+            meta.insert(key, entry);
+        }
+        serde_json::to_vec(&meta).map(Some).map_err(invalid_data)
+    }
+
+    fn store_meta(&mut self, meta: &[u8]) -> Result<(), VaultError> {
+        let meta: HashMap<K, EntryMeta> = serde_json::from_slice(meta).map_err(invalid_data)?;
+        let mut conn = self.lock_conn()?;
+        let tx = conn.transaction().map_err(sql_error)?;
+        tx.execute("DELETE FROM meta", []).map_err(sql_error)?;
+        for (key, meta) in &meta {
+            upsert_meta(&tx, &encode_key(key)?, meta)?;
+        }
+        tx.commit().map_err(sql_error)
+    }
+
+    fn load_edges(&self) -> Result<Option<Vec<u8>>, VaultError> {
+        let conn = self.lock_conn()?;
+        let mut stmt = conn
+            .prepare("SELECT source, relation, target, on_delete FROM edges")
+            .map_err(sql_error)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .map_err(sql_error)?;
+        let mut edges = Vec::new();
+        for row in rows {
+            let (from, relation, to, on_delete) = row.map_err(sql_error)?;
+            edges.push(Edge::<K> {
+                from: serde_json::from_str(&from).map_err(invalid_data)?,
+                relation,
+                to: serde_json::from_str(&to).map_err(invalid_data)?,
+                on_delete: parse_on_delete(&on_delete),
+            });
+        }
+        serde_json::to_vec(&edges).map(Some).map_err(invalid_data)
+    }
+
+    fn store_edges(&mut self, edges: &[u8]) -> Result<(), VaultError> {
+        let edges: Vec<Edge<K>> = serde_json::from_slice(edges).map_err(invalid_data)?;
+        let mut conn = self.lock_conn()?;
+        let tx = conn.transaction().map_err(sql_error)?;
+        tx.execute("DELETE FROM edges", []).map_err(sql_error)?;
+        for edge in &edges {
+            tx.execute(
+                "INSERT INTO edges (source, relation, target, on_delete) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    encode_key(&edge.from)?,
+                    edge.relation,
+                    encode_key(&edge.to)?,
+                    on_delete_name(edge.on_delete)
+                ],
+            )
+            .map_err(sql_error)?;
+        }
+        tx.commit().map_err(sql_error)
+    }
+}
+
+impl<K> Vault<K, SqliteBackend<K>>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+{
+    // Opens a SQLite vault with the location and capacity stored in it, or
+    // creates one and records `location` and `capacity` in the file. Entry
+    // metadata is written along with each change, except for access counts,
+    // which `flush` persists.
+    pub fn open_sqlite(
+        path: &str,
+        location: String,
+        capacity: MemorySize,
+    ) -> Result<Self, VaultError> {
+        let backend = SqliteBackend::open(path)?;
+        let (location, capacity) = match backend.settings()? {
+            Some(stored) => stored,
+            None => {
+                backend.store_settings(&location, &capacity)?;
+                (location, capacity)
+            }
+        };
+        Self::with_backend(location, capacity, backend)
+    }
+
+    // Reopens a SQLite vault with the location and capacity stored in it
+    pub fn load_sqlite(path: &str) -> Result<Self, VaultError> {
+        let backend = SqliteBackend::open(path)?;
+        let (location, capacity) = backend.settings()?.ok_or_else(|| {
+            VaultError::InvalidInput(format!("'{}' is not a memoria database", path))
+        })?;
+        Self::with_backend(location, capacity, backend)
+    }
+
+    fn require(&self, key: &K) -> Result<String, VaultError> {
+        if !self.contains_key(key) {
+            return Err(VaultError::ResourceNotFound(key.to_string()));
+        }
+        encode_key(key)
+    }
+
+    pub fn add_tag(&mut self, key: &K, tag: &str) -> Result<(), VaultError> {
+        let key = self.require(key)?;
+        self.resources
+            .lock_conn()?
+            .execute(
+                "INSERT OR IGNORE INTO tags (key, tag) VALUES (?1, ?2)",
+                params![key, tag],
+            )
+            .map_err(sql_error)?;
+        Ok(())
+    }
+
+    // Returns whether the entry had the tag
+    pub fn remove_tag(&mut self, key: &K, tag: &str) -> Result<bool, VaultError> {
+        let key = self.require(key)?;
+        let removed = self
+            .resources
+            .lock_conn()?
+            .execute(
+                "DELETE FROM tags WHERE key = ?1 AND tag = ?2",
+                params![key, tag],
+            )
+            .map_err(sql_error)?;
+        Ok(removed > 0)
+    }
+
+    pub fn tags(&self, key: &K) -> Result<Vec<String>, VaultError> {
+        let key = self.require(key)?;
+        let conn = self.resources.lock_conn()?;
+        let mut stmt = conn
+            .prepare("SELECT tag FROM tags WHERE key = ?1 ORDER BY tag")
+            .map_err(sql_error)?;
+        let tags = stmt
+            .query_map(params![key], |row| row.get(0))
+            .map_err(sql_error)?
+            .collect::<Result<_, _>>()
+            .map_err(sql_error);
+        tags
+    }
+
+    // Live entries carrying `tag`, sorted by key
+    pub fn find_by_tag(&self, tag: &str) -> Result<Vec<K>, VaultError> {
+        let conn = self.resources.lock_conn()?;
+        let mut stmt = conn
+            .prepare("SELECT key FROM tags WHERE tag = ?1")
+            .map_err(sql_error)?;
+        let encoded: Vec<String> = stmt
+            .query_map(params![tag], |row| row.get(0))
+            .map_err(sql_error)?
+            .collect::<Result<_, _>>()
+            .map_err(sql_error)?;
+        drop(stmt);
+        drop(conn);
+
+        let mut keys = Vec::with_capacity(encoded.len());
+        for key in encoded {
+            let key: K = serde_json::from_str(&key).map_err(invalid_data)?;
+            if self.contains_key(&key) {
+                keys.push(key);
+            }
+        }
+        keys.sort_by_key(|key| key.to_string());
+        Ok(keys)
+    }
+
+    // `recall` limited to entries carrying every one of `tags`
+    pub fn recall_tagged(
+        &self,
+        query: &str,
+        tags: &[&str],
+        options: &RecallOptions<K>,
+    ) -> Result<Vec<RecallHit<K>>, VaultError> {
+        let mut within = options.within.clone();
+        for tag in tags {
+            let tagged: HashSet<K> = self.find_by_tag(tag)?.into_iter().collect();
+            within = Some(match within {
+                Some(keys) => keys.intersection(&tagged).cloned().collect(),
+                None => tagged,
+            });
+        }
+        let options = RecallOptions {
+            within,
+            ..options.clone()
+        };
+        Ok(self.recall(query, &options))
+    }
+}
+
+// Copies a JSON snapshot into a new SQLite vault, keeping entry metadata.
+// Returns the number of entries moved.
+pub fn migrate_json_to_sqlite<K>(json_path: &str, db_path: &str) -> Result<usize, VaultError>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+{
+    let source = Vault::<K>::load_from_file(json_path)?;
+    // Checked before anything is written, so a refusal leaves the file as it was
+    let backend = SqliteBackend::<K>::open(db_path)?;
+    if !backend.is_empty() {
+        return Err(VaultError::InvalidInput(format!(
+            "'{}' already holds a vault",
+            db_path
+        )));
+    }
+    backend.store_settings(&source.location, &source.storage_capacity)?;
+    let mut target = Vault::with_backend(
+        source.location.clone(),
+        source.storage_capacity.clone(),
+        backend,
+    )?;
+    for (key, resource) in source.iter() {
+        let meta = source.entry_meta(key).cloned().unwrap_or_default();
+        target.insert_entry(key.clone(), resource.clone(), meta)?;
+    }
+    target.restore_edges(source.edges().cloned().collect());
+    target.flush()?;
+    Ok(target.len())
+}
+
+// Writes a SQLite vault out as a JSON snapshot. Returns the number of entries moved.
+pub fn migrate_sqlite_to_json<K>(db_path: &str, json_path: &str) -> Result<usize, VaultError>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+{
+    let source = Vault::<K, SqliteBackend<K>>::load_sqlite(db_path)?;
+    source.save_to_file(json_path)?;
+    Ok(source.len())
+}
@@ -0,0 +1,295 @@
+use crate::backend::StorageBackend;
+use crate::entry::now_millis;
+use crate::memory::MemorySize;
+use crate::quota::QuotaUsage;
+use crate::resource::{Resource, ResourceKind};
+use crate::vault::{Vault, VaultMetadata};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::hash::Hash;
+
+// Entries listed in `VaultStats::largest`
+const LARGEST_ENTRIES: usize = 5;
+
+const HOUR: u64 = 60 * 60 * 1000;
+const DAY: u64 = 24 * HOUR;
+
+// Upper bounds of the age histogram; the last bucket has none
+const AGE_BUCKETS: [(&str, Option<u64>); 5] = [
+    ("< 1 hour", Some(HOUR)),
+    ("< 1 day", Some(DAY)),
+    ("< 1 week", Some(7 * DAY)),
+    ("< 30 days", Some(30 * DAY)),
+    (">= 30 days", None),
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KindStats {
+    pub kind: ResourceKind,
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntrySize {
+    pub key: String,
+    pub kind: ResourceKind,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyLengths {
+    pub min: usize,
+    pub median: usize,
+    pub mean: f64,
+    pub max: usize,
+}
+
+// Entries created within `max_age_millis` of now but not in an earlier bucket
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgeBucket {
+    pub label: String,
+    pub max_age_millis: Option<u64>,
+    pub entries: usize,
+}
+
+// A point-in-time description of a vault's contents, for programs and for
+// the `memoria summary` command
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VaultStats {
+    pub location: String,
+    pub storage_capacity: MemorySize,
+    pub used_bytes: u64,
+    // `used_bytes` as a fraction of the capacity
+    pub utilization: f64,
+    pub entries: usize,
+    // Every kind, in declaration order, including those with no entries
+    pub kinds: Vec<KindStats>,
+    // Biggest first; ties go to the smaller key
+    pub largest: Vec<EntrySize>,
+    // `None` for an empty vault
+    pub key_lengths: Option<KeyLengths>,
+    // Empty when no entry has a creation time
+    pub ages: Vec<AgeBucket>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub quotas: Vec<QuotaUsage>,
+}
+
+impl From<&VaultStats> for VaultMetadata {
+    fn from(stats: &VaultStats) -> Self {
+        VaultMetadata {
+            location: stats.location.clone(),
+            storage_capacity: stats.storage_capacity.clone(),
+            current_usage: stats.used_bytes,
+            resource_count: stats.entries,
+        }
+    }
+}
+
+// The table printed by `Vault::summary`, `VaultSnapshot::summary` and
+// `memoria summary`
+impl Display for VaultStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.location.bold())?;
+        writeln!(
+            f,
+            "{:<12} {} / {} bytes ({:.2}%)",
+            "Used",
+            self.used_bytes,
+            self.storage_capacity.size_bytes(),
+            self.utilization * 100.0
+        )?;
+        writeln!(f, "{:<12} {}", "Entries", self.entries)?;
+
+        writeln!(
+            f,
+            "\n{:<12} {:>8} {:>12}",
+            "Kind".bold(),
+            "Entries",
+            "Bytes"
+        )?;
+        for kind in &self.kinds {
+            writeln!(
+                f,
+                "{:<12} {:>8} {:>12}",
+                kind.kind.to_string(),
+                kind.entries,
+                kind.bytes
+            )?;
+        }
+
+        if !self.largest.is_empty() {
+            writeln!(
+                f,
+                "\n{:<32} {:<8} {:>12}",
+                "Largest".bold(),
+                "Kind",
+                "Bytes"
+            )?;
+            for entry in &self.largest {
+                writeln!(
+                    f,
+                    "{:<32} {:<8} {:>12}",
+                    entry.key,
+                    entry.kind.to_string(),
+                    entry.bytes
+                )?;
+            }
+        }
+
+        if let Some(lengths) = &self.key_lengths {
+            writeln!(
+                f,
+                "\n{} min {}, median {}, mean {:.1}, max {}",
+                "Key lengths".bold(),
+                lengths.min,
+                lengths.median,
+                lengths.mean,
+                lengths.max
+            )?;
+        }
+
+        if !self.ages.is_empty() {
+            writeln!(f, "\n{:<12} {:>8}", "Age".bold(), "Entries")?;
+            for bucket in &self.ages {
+                writeln!(f, "{:<12} {:>8}", bucket.label, bucket.entries)?;
+            }
+        }
+
+        if !self.quotas.is_empty() {
+            writeln!(f)?;
+            for usage in &self.quotas {
+                writeln!(f, "{}", usage)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    pub fn stats(&self) -> VaultStats {
+        let entries = self.iter().map(|(key, resource)| {
+            let created = self.entry_meta(key).map(|meta| meta.created_at);
+            (key, resource, created)
+        });
+        let mut stats = collect(
+            &self.location,
+            &self.storage_capacity,
+            self.current_usage(),
+            entries,
+            now_millis(),
+        );
+        stats.quotas = self.quota_usage();
+        stats
+    }
+
+    // The metadata the vault's stats would report, without computing the rest
+    pub fn metadata(&self) -> VaultMetadata {
+        VaultMetadata {
+            location: self.location.clone(),
+            storage_capacity: self.storage_capacity.clone(),
+            current_usage: self.current_usage(),
+            resource_count: self.iter().count(),
+        }
+    }
+}
+
+// Stats over live entries, each with its creation time if it has metadata.
+// Shared by `Vault` and `VaultSnapshot`; ages are measured from `now`.
+pub(crate) fn collect<'a, K: Display + 'a>(
+    location: &str,
+    storage_capacity: &MemorySize,
+    used_bytes: u64,
+    entries: impl Iterator<Item = (&'a K, &'a Resource, Option<u64>)>,
+    now: u64,
+) -> VaultStats {
+    let mut kinds: Vec<KindStats> = [
+        ResourceKind::TextMessage,
+        ResourceKind::SensorData,
+        ResourceKind::SystemLogs,
+    ]
+    .into_iter()
+    .map(|kind| KindStats {
+        kind,
+        entries: 0,
+        bytes: 0,
+    })
+    .collect();
+    let mut sizes = Vec::new();
+    let mut key_lengths = Vec::new();
+    let mut created = Vec::new();
+    for (key, resource, created_at) in entries {
+        let (kind, bytes) = (resource.kind(), resource.size_bytes());
+        if let Some(stats) = kinds.iter_mut().find(|stats| stats.kind == kind) {
+            stats.entries += 1;
+            stats.bytes += bytes;
+        }
+        created.extend(created_at);
+        let key = key.to_string();
+        key_lengths.push(key.chars().count());
+        sizes.push(EntrySize { key, kind, bytes });
+    }
+
+    sizes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.key.cmp(&b.key)));
+    sizes.truncate(LARGEST_ENTRIES);
+    let capacity = storage_capacity.size_bytes();
+    VaultStats {
+        location: location.to_string(),
+        storage_capacity: storage_capacity.clone(),
+        used_bytes,
+        utilization: if capacity == 0 {
+            0.0
+        } else {
+            used_bytes as f64 / capacity as f64
+        },
+        entries: key_lengths.len(),
+        kinds,
+        largest: sizes,
+        key_lengths: lengths(key_lengths),
+        ages: ages(&created, now),
+        quotas: Vec::new(),
+    }
+}
+
+fn lengths(mut lengths: Vec<usize>) -> Option<KeyLengths> {
+    lengths.sort_unstable();
+    Some(KeyLengths {
+        min: *lengths.first()?,
+        median: lengths[lengths.len() / 2],
+        mean: lengths.iter().sum::<usize>() as f64 / lengths.len() as f64,
+        max: *lengths.last()?,
+    })
+}
+
+// Histogram of creation times; entries from before timestamps were kept carry
+// zero and are left out
+fn ages(created: &[u64], now: u64) -> Vec<AgeBucket> {
+    let created: Vec<u64> = created.iter().copied().filter(|at| *at > 0).collect();
+    if created.is_empty() {
+        return Vec::new();
+    }
+    let mut buckets: Vec<AgeBucket> = AGE_BUCKETS
+        .iter()
+        .map(|(label, max_age_millis)| AgeBucket {
+            label: label.to_string(),
+            max_age_millis: *max_age_millis,
+            entries: 0,
+        })
+        .collect();
+    for at in created {
+        let age = now.saturating_sub(at);
+        if let Some(bucket) = buckets
+            .iter_mut()
+            .find(|bucket| bucket.max_age_millis.is_none_or(|max| age < max))
+        {
+            bucket.entries += 1;
+        }
+    }
+    buckets
+}
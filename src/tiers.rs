@@ -0,0 +1,318 @@
+use crate::entry::now_millis;
+use crate::error::VaultError;
+use crate::memory::MemorySize;
+use crate::resource::Resource;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Tier {
+    // Small and short-lived: what the agent is working with right now
+    Working,
+    // Log of things that happened
+    Episodic,
+    // Long-term facts
+    Semantic,
+}
+
+impl Tier {
+    pub const ALL: [Tier; 3] = [Tier::Working, Tier::Episodic, Tier::Semantic];
+}
+
+impl fmt::Display for Tier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Tier::Working => "working",
+            Tier::Episodic => "episodic",
+            Tier::Semantic => "semantic",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TierCapacities {
+    pub working: MemorySize,
+    pub episodic: MemorySize,
+    pub semantic: MemorySize,
+}
+
+impl Default for TierCapacities {
+    fn default() -> Self {
+        Self {
+            working: MemorySize::KB(64),
+            episodic: MemorySize::MB(64),
+            semantic: MemorySize::MB(256),
+        }
+    }
+}
+
+// When entries move between tiers on their own
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromotionRules {
+    // Episodic entries recalled this many times become semantic facts
+    pub promote_after_recalls: u64,
+    // Working entries unused for this long drop to episodic on `consolidate`
+    pub working_idle: Duration,
+}
+
+impl Default for PromotionRules {
+    fn default() -> Self {
+        Self {
+            promote_after_recalls: 3,
+            working_idle: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+// Called whenever an entry changes tier, with the entry and the tiers it moves
+// between. Returning a resource stores that in its place, e.g. an episode
+// rewritten as a fact.
+pub type ConsolidationCallback<K> =
+    Box<dyn FnMut(&K, &Resource, Tier, Tier) -> Option<Resource> + Send>;
+
+// One hit from `MemorySystem::recall`, tagged with the tier it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recollection<K> {
+    pub key: K,
+    pub resource: Resource,
+    pub tier: Tier,
+    pub score: f64,
+}
+
+// Working, episodic and semantic memory, each held in its own vault
+pub struct MemorySystem<K>
+where
+    K: Eq + Hash + fmt::Display,
+{
+    working: Vault<K>,
+    episodic: Vault<K>,
+    semantic: Vault<K>,
+    rules: PromotionRules,
+    consolidators: Vec<ConsolidationCallback<K>>,
+}
+
+impl<K> MemorySystem<K>
+where
+    K: Eq + Hash + fmt::Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+{
+    pub fn new(location: &str, capacities: TierCapacities) -> Self {
+        let vault = |tier: Tier, capacity| Vault::empty(format!("{}/{}", location, tier), capacity);
+        Self {
+            working: vault(Tier::Working, capacities.working),
+            episodic: vault(Tier::Episodic, capacities.episodic),
+            semantic: vault(Tier::Semantic, capacities.semantic),
+            rules: PromotionRules::default(),
+            consolidators: Vec::new(),
+        }
+    }
+
+    pub fn with_rules(mut self, rules: PromotionRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    pub fn on_consolidate<F>(&mut self, callback: F)
+    where
+        F: FnMut(&K, &Resource, Tier, Tier) -> Option<Resource> + Send + 'static,
+    {
+        self.consolidators.push(Box::new(callback));
+    }
+
+    pub fn tier(&self, tier: Tier) -> &Vault<K> {
+        match tier {
+            Tier::Working => &self.working,
+            Tier::Episodic => &self.episodic,
+            Tier::Semantic => &self.semantic,
+        }
+    }
+
+    // For tuning a tier's vault; entries added directly must not reuse a key
+    // held by another tier
+    pub fn tier_mut(&mut self, tier: Tier) -> &mut Vault<K> {
+        match tier {
+            Tier::Working => &mut self.working,
+            Tier::Episodic => &mut self.episodic,
+            Tier::Semantic => &mut self.semantic,
+        }
+    }
+
+    // Which tier holds `key`, checking the fastest first
+    pub fn locate(&self, key: &K) -> Option<Tier> {
+        Tier::ALL
+            .into_iter()
+            .find(|tier| self.tier(*tier).contains_key(key))
+    }
+
+    pub fn get(&self, key: &K) -> Option<(Tier, &Resource)> {
+        let tier = self.locate(key)?;
+        Some((tier, self.tier(tier).get(key)?))
+    }
+
+    // Stores a memory in `tier`. A key lives in one tier at a time. When working
+    // memory is full its least-retained entries drop to episodic to make room.
+    pub fn remember(&mut self, tier: Tier, key: K, resource: Resource) -> Result<(), VaultError> {
+        if let Some(existing) = self.locate(&key) {
+            return Err(VaultError::InvalidInput(format!(
+                "Key '{}' already exists in {} memory",
+                key, existing
+            )));
+        }
+        if tier == Tier::Working {
+            self.make_working_room(resource.size_bytes())?;
+        }
+        self.tier_mut(tier).add(key, resource)
+    }
+
+    pub fn forget(&mut self, key: &K) -> Result<Resource, VaultError> {
+        let tier = self
+            .locate(key)
+            .ok_or_else(|| VaultError::ResourceNotFound(key.to_string()))?;
+        self.tier_mut(tier).remove(key)
+    }
+
+    fn make_working_room(&mut self, needed: u64) -> Result<(), VaultError> {
+        let capacity = self.working.storage_capacity.size_bytes();
+        if needed > capacity {
+            return Ok(());
+        }
+        while self.working.current_usage() + needed > capacity {
+            let Some(key) = self
+                .working
+                .top_memories(usize::MAX)
+                .last()
+                .map(|(key, _, _)| (*key).clone())
+            else {
+                break;
+            };
+            self.move_to(&key, Tier::Episodic)?;
+        }
+        Ok(())
+    }
+
+    // Moves an entry to another tier, keeping its metadata, and runs the
+    // consolidation callbacks. The entry is only removed from its old tier
+    // once the new one has accepted it, and taken back out of the new one if
+    // that removal fails, so it never ends up in both.
+    pub fn move_to(&mut self, key: &K, to: Tier) -> Result<(), VaultError> {
+        let from = self
+            .locate(key)
+            .ok_or_else(|| VaultError::ResourceNotFound(key.to_string()))?;
+        if from == to {
+            return Ok(());
+        }
+        let source = self.tier(from);
+        // An entry that edges keep in place cannot leave its tier
+        source.check_removal(key, &HashSet::new())?;
+        let mut resource = source
+            .get(key)
+            .cloned()
+            .ok_or_else(|| VaultError::ResourceNotFound(key.to_string()))?;
+        let meta = source.entry_meta(key).cloned().unwrap_or_default();
+        for callback in &mut self.consolidators {
+            if let Some(rewritten) = callback(key, &resource, from, to) {
+                resource = rewritten;
+            }
+        }
+        self.tier_mut(to)
+            .add_with_meta(key.clone(), resource, meta)?;
+        if let Err(e) = self.tier_mut(from).remove(key) {
+            let _ = self.tier_mut(to).remove(key);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // Searches every tier, counts each hit as a recall and applies the
+    // promotion rules. Results carry their tier (after any promotion) and are
+    // ordered by retention score, best first.
+    pub fn recall(
+        &mut self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Recollection<K>>, VaultError> {
+        let mut hits = Vec::new();
+        for tier in Tier::ALL {
+            let vault = self.tier(tier);
+            hits.extend(
+                vault
+                    .search(query)
+                    .into_iter()
+                    .map(|(key, _)| (tier, key.clone())),
+            );
+        }
+
+        for (tier, key) in &hits {
+            self.tier_mut(*tier).reinforce(key)?;
+        }
+        self.promote_recalled()?;
+
+        let mut recollections: Vec<Recollection<K>> = hits
+            .into_iter()
+            .filter_map(|(_, key)| {
+                let tier = self.locate(&key)?;
+                let vault = self.tier(tier);
+                Some(Recollection {
+                    resource: vault.get(&key)?.clone(),
+                    score: vault.retention_score(&key)?,
+                    key,
+                    tier,
+                })
+            })
+            .collect();
+        recollections.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.tier.cmp(&b.tier)));
+        recollections.truncate(limit);
+        Ok(recollections)
+    }
+
+    fn promote_recalled(&mut self) -> Result<Vec<K>, VaultError> {
+        let threshold = self.rules.promote_after_recalls;
+        let ready: Vec<K> = self
+            .episodic
+            .keys()
+            .filter(|key| {
+                self.episodic
+                    .entry_meta(key)
+                    .is_some_and(|meta| meta.access_count >= threshold)
+            })
+            .cloned()
+            .collect();
+        for key in &ready {
+            self.move_to(key, Tier::Semantic)?;
+        }
+        Ok(ready)
+    }
+
+    // Applies the promotion rules now: frequently recalled episodes become
+    // semantic facts and idle working memory drops to episodic. Returns every
+    // key that moved.
+    pub fn consolidate(&mut self) -> Result<Vec<(K, Tier)>, VaultError> {
+        let mut moved: Vec<(K, Tier)> = self
+            .promote_recalled()?
+            .into_iter()
+            .map(|key| (key, Tier::Semantic))
+            .collect();
+
+        let cutoff = now_millis().saturating_sub(self.rules.working_idle.as_millis() as u64);
+        let idle: Vec<K> = self
+            .working
+            .keys()
+            .filter(|key| {
+                self.working
+                    .entry_meta(key)
+                    .is_some_and(|meta| meta.last_used() <= cutoff)
+            })
+            .cloned()
+            .collect();
+        for key in idle {
+            self.move_to(&key, Tier::Episodic)?;
+            moved.push((key, Tier::Episodic));
+        }
+        Ok(moved)
+    }
+}
@@ -0,0 +1,32 @@
+// Counts tokens for prompt budgets. Plug in the tokenizer of the target model
+// for exact numbers; the defaults here only approximate.
+pub trait Tokenizer {
+    fn count(&self, text: &str) -> usize;
+}
+
+// One token per whitespace-separated word
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn count(&self, text: &str) -> usize {
+        text.split_whitespace().count()
+    }
+}
+
+// Rough byte-pair estimate: a token per four characters of each word, at least
+// one per word, and one per punctuation mark
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ApproxTokenizer;
+
+impl Tokenizer for ApproxTokenizer {
+    fn count(&self, text: &str) -> usize {
+        text.split_whitespace()
+            .map(|word| {
+                let punct = word.chars().filter(|c| c.is_ascii_punctuation()).count();
+                let letters = word.chars().count() - punct;
+                letters.div_ceil(4) + punct
+            })
+            .sum()
+    }
+}
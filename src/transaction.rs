@@ -0,0 +1,312 @@
+use crate::backend::{MemoryBackend, StorageBackend};
+use crate::dedup::{DedupPlan, DUPLICATE_OF};
+use crate::entry::EntryMeta;
+use crate::error::VaultError;
+use crate::events::Removal;
+use crate::graph::{Edge, OnDelete};
+use crate::journal::JournalOp;
+use crate::resource::Resource;
+use crate::vault::Vault;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::hash::Hash;
+
+// An entry as it was before a batch, or `None` if it did not exist
+type Prior<K> = (K, Option<(Resource, EntryMeta)>);
+
+// A batch of changes staged against a vault. Nothing reaches the vault until
+// the closure passed to `Vault::transaction` returns `Ok`.
+pub struct Transaction<'a, K, B = MemoryBackend<K>>
+where
+    K: Eq + Hash + Display,
+{
+    vault: &'a Vault<K, B>,
+    // Latest staged state of every touched key; `None` means deleted
+    staged: HashMap<K, Option<Resource>>,
+    order: Vec<K>,
+}
+
+impl<'a, K, B> Transaction<'a, K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    fn new(vault: &'a Vault<K, B>) -> Self {
+        Self {
+            vault,
+            staged: HashMap::new(),
+            order: Vec::new(),
+        }
+    }
+
+    // Reads through the staged changes, falling back to the vault
+    pub fn get(&self, key: &K) -> Option<&Resource> {
+        match self.staged.get(key) {
+            Some(staged) => staged.as_ref(),
+            None => self.vault.get(key),
+        }
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    fn stage(&mut self, key: K, value: Option<Resource>) {
+        if !self.staged.contains_key(&key) {
+            self.order.push(key.clone());
+        }
+        self.staged.insert(key, value);
+    }
+
+    pub fn add(&mut self, key: K, resource: Resource) -> Result<(), VaultError> {
+        if self.contains_key(&key) {
+            return Err(VaultError::InvalidInput(format!(
+                "Key '{}' already exists",
+                key
+            )));
+        }
+        self.stage(key, Some(resource));
+        Ok(())
+    }
+
+    pub fn update(&mut self, key: &K, resource: Resource) -> Result<Resource, VaultError> {
+        let old = self
+            .get(key)
+            .cloned()
+            .ok_or_else(|| VaultError::ResourceNotFound(key.to_string()))?;
+        self.stage(key.clone(), Some(resource));
+        Ok(old)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Result<Resource, VaultError> {
+        let old = self
+            .get(key)
+            .cloned()
+            .ok_or_else(|| VaultError::ResourceNotFound(key.to_string()))?;
+        self.stage(key.clone(), None);
+        Ok(old)
+    }
+
+    // Net changes in first-touch order, minus deletions of keys the vault never had
+    fn into_changes(mut self) -> Vec<(K, Option<Resource>)> {
+        let vault = self.vault;
+        self.order
+            .into_iter()
+            .filter_map(|key| {
+                let value = self.staged.remove(&key)?;
+                match value {
+                    None if vault.get(&key).is_none() => None,
+                    value => Some((key, value)),
+                }
+            })
+            .collect()
+    }
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    // Runs `f` against a staging area and commits its changes all-or-nothing.
+    // If `f` fails, or the net result would not fit in the vault, nothing is
+    // applied. A committed batch is journaled as a single record, and
+    // subscribers are notified only after every change is in place. If the
+    // backend fails partway, the changes already made are undone and no
+    // events are sent.
+    pub fn transaction<T, F>(&mut self, f: F) -> Result<T, VaultError>
+    where
+        F: FnOnce(&mut Transaction<'_, K, B>) -> Result<T, VaultError>,
+    {
+        self.purge_expired();
+        let mut tx = Transaction::new(self);
+        let value = f(&mut tx)?;
+        let changes = tx.into_changes();
+        self.commit(changes)?;
+        Ok(value)
+    }
+
+    fn commit(&mut self, changes: Vec<(K, Option<Resource>)>) -> Result<(), VaultError> {
+        let DedupPlan {
+            changes,
+            mut merged,
+            links,
+        } = self.dedup_changes(changes)?;
+        let mut removed_bytes = 0;
+        let mut added_bytes = 0;
+        for (key, value) in &changes {
+            removed_bytes += self.get(key).map_or(0, |old| old.size_bytes());
+            added_bytes += value.as_ref().map_or(0, |new| new.size_bytes());
+        }
+
+        let current = self.current_usage();
+        let capacity = self.storage_capacity.size_bytes();
+        if current - removed_bytes + added_bytes > capacity {
+            return Err(VaultError::VaultFull {
+                capacity,
+                current,
+                new_size: added_bytes,
+            });
+        }
+        self.check_quotas_batch(&changes)?;
+
+        let leaving: HashSet<&K> = changes
+            .iter()
+            .filter(|(key, value)| value.is_none() && self.contains_key(key))
+            .map(|(key, _)| key)
+            .collect();
+        let gone = self.removal_closure(leaving.iter().copied());
+        for key in &leaving {
+            self.check_removal(key, &gone)?;
+        }
+
+        let mut ops: Vec<JournalOp<K>> = changes
+            .into_iter()
+            .map(|(key, value)| match value {
+                Some(resource) => {
+                    let mut meta = match self.meta.get(&key) {
+                        Some(meta) => {
+                            let mut meta = meta.clone();
+                            meta.touch();
+                            meta
+                        }
+                        None => EntryMeta::new(),
+                    };
+                    meta.duplicates += merged.remove(&key).unwrap_or(0);
+                    JournalOp::Put {
+                        key,
+                        resource,
+                        meta,
+                    }
+                }
+                None => JournalOp::Delete { key },
+            })
+            .collect();
+        // Originals the batch does not otherwise touch only have their count
+        // raised; the copies' links go last, once both ends are in place
+        let recounted: HashSet<K> = merged.keys().cloned().collect();
+        for (key, count) in merged {
+            if let Some(resource) = self.get(&key) {
+                let mut meta = self.meta.get(&key).cloned().unwrap_or_default();
+                meta.duplicates += count;
+                meta.touch();
+                ops.push(JournalOp::Put {
+                    key,
+                    resource: resource.clone(),
+                    meta,
+                });
+            }
+        }
+        ops.extend(links.into_iter().map(|(from, to)| JournalOp::Link {
+            edge: Edge {
+                from,
+                relation: DUPLICATE_OF.to_string(),
+                to,
+                on_delete: OnDelete::Nullify,
+            },
+        }));
+        if ops.is_empty() {
+            return Ok(());
+        }
+        let (before, edges) = self.undo_state(&ops);
+        self.log(&ops)?;
+
+        self.hold_events();
+        for op in ops {
+            let applied = match op {
+                JournalOp::Put { key, meta, .. } if recounted.contains(&key) => {
+                    self.replace_meta(&key, meta)
+                }
+                JournalOp::Put {
+                    key,
+                    resource,
+                    meta,
+                } => self.restore_entry(key, resource, meta),
+                JournalOp::Delete { key } => self.remove_entry(&key, Removal::Removed).map(|_| ()),
+                JournalOp::Link { edge } => {
+                    self.graph.insert(edge);
+                    Ok(())
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = applied {
+                self.roll_back(before, edges);
+                self.discard_events();
+                return Err(e);
+            }
+        }
+        self.release_events();
+        Ok(())
+    }
+
+    // Every entry the batch could change, cascades included, as it is now,
+    // and the edges touching them
+    fn undo_state(&self, ops: &[JournalOp<K>]) -> (Vec<Prior<K>>, Vec<Edge<K>>) {
+        let mut affected: Vec<&K> = Vec::new();
+        for op in ops {
+            match op {
+                JournalOp::Put { key, .. } => affected.push(key),
+                JournalOp::Delete { key } => affected.extend(self.removal_set(key)),
+                _ => {}
+            }
+        }
+        let affected: HashSet<&K> = affected.into_iter().collect();
+        let edges = self
+            .graph
+            .edges()
+            .filter(|edge| affected.contains(&edge.from) || affected.contains(&edge.to))
+            .cloned()
+            .collect();
+        let before = affected
+            .into_iter()
+            .map(|key| {
+                let prior =
+                    self.resources.get(key).cloned().map(|resource| {
+                        (resource, self.meta.get(key).cloned().unwrap_or_default())
+                    });
+                (key.clone(), prior)
+            })
+            .collect();
+        (before, edges)
+    }
+
+    // Puts back what `undo_state` recorded after a batch failed partway, and
+    // journals the reversal so a replay ends in the same place. This is best
+    // effort: a backend that keeps failing can still leave part of the batch.
+    fn roll_back(&mut self, before: Vec<Prior<K>>, edges: Vec<Edge<K>>) {
+        let mut undo = Vec::new();
+        for (key, prior) in before {
+            match prior {
+                Some((resource, meta)) => {
+                    if self.resources.get(&key) == Some(&resource)
+                        && self.meta.get(&key) == Some(&meta)
+                    {
+                        continue;
+                    }
+                    let _ = self.restore_entry(key.clone(), resource.clone(), meta.clone());
+                    undo.push(JournalOp::Put {
+                        key,
+                        resource,
+                        meta,
+                    });
+                }
+                None => {
+                    if self.resources.contains_key(&key) {
+                        let _ = self.remove_entry(&key, Removal::Removed);
+                        undo.push(JournalOp::Delete { key });
+                    }
+                }
+            }
+        }
+        for edge in &edges {
+            undo.push(JournalOp::Link { edge: edge.clone() });
+        }
+        self.restore_edges(edges);
+        if !undo.is_empty() {
+            let _ = self.log(&undo);
+        }
+    }
+}
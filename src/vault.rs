@@ -1,10 +1,27 @@
+use crate::audit::{resource_digest, AuditLog, AuditOp};
+use crate::backend::{MemoryBackend, StorageBackend};
+use crate::dedup::DedupIndex;
+use crate::embedding::Embeddings;
+use crate::entry::{now_millis, EntryMeta};
 use crate::error::VaultError;
+use crate::events::{EventFilter, Observers, Removal, SubscriptionId, VaultEvent};
+use crate::graph::{Edge, Graph};
+use crate::journal::{Journal, JournalOp};
 use crate::memory::MemorySize;
+use crate::quota::{track, QuotaIndex};
 use crate::resource::Resource;
+use crate::retention::RetentionPolicy;
+use imbl::HashMap as ImHashMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
 use std::fs;
 use std::hash::Hash;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct VaultMetadata {
@@ -15,43 +32,251 @@ pub struct VaultMetadata {
 }
 
 // Helper struct for serialization (only used internally)
-#[derive(Serialize, Deserialize)]
-struct VaultSnapshot<K: Clone + Eq + std::hash::Hash + Serialize> {
+#[derive(Deserialize)]
+struct VaultFile<K: Clone + Eq + std::hash::Hash> {
     location: String,
     storage_capacity: MemorySize,
-    resources: HashMap<K, Resource>,
+    resources: ImHashMap<K, Resource>,
+    #[serde(default = "ImHashMap::new")]
+    meta: ImHashMap<K, EntryMeta>,
+    #[serde(default = "Vec::new")]
+    edges: Vec<Edge<K>>,
 }
 
-pub struct Vault<K>
+// Borrowing twin of `VaultFile` that streams entries straight from the backend
+#[derive(Serialize)]
+struct VaultFileRef<'a, K: Clone + Eq + std::hash::Hash + Serialize> {
+    location: &'a str,
+    storage_capacity: &'a MemorySize,
+    resources: EntriesRef<'a, K>,
+    meta: &'a ImHashMap<K, EntryMeta>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    edges: Vec<&'a Edge<K>>,
+}
+
+struct EntriesRef<'a, K>(&'a dyn StorageBackend<K>);
+
+impl<K: Serialize> Serialize for EntriesRef<'_, K> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter())
+    }
+}
+
+pub struct Vault<K, B = MemoryBackend<K>>
 where
-    K: Eq + Hash + std::fmt::Display,
+    K: Eq + Hash + Display,
 {
     pub location: String,
     pub storage_capacity: MemorySize,
-    pub resources: HashMap<K, Resource>,
+    pub(crate) resources: B,
+    // Persistent map: cloning is O(1) and clones share structure, which is
+    // what makes `snapshot` cheap
+    pub(crate) meta: ImHashMap<K, EntryMeta>,
+    // Running total of resource sizes, kept in step by the entry helpers
+    usage: u64,
+    // Deadlines of entries with a time-to-live; checked against `meta` when purging
+    expiry: BTreeMap<u64, Vec<K>>,
+    observers: Observers<K>,
+    pub(crate) journal: Option<Journal>,
+    pub(crate) audit: Option<AuditLog>,
+    pub(crate) retention: RetentionPolicy,
+    pub(crate) evict_on_full: bool,
+    pub(crate) graph: Graph<K>,
+    // Present while duplicate detection is on
+    pub(crate) dedup: Option<DedupIndex<K>>,
+    // Present while an embedder is set
+    pub(crate) embeddings: Option<Embeddings<K>>,
+    // Present while quotas are set
+    pub(crate) quotas: Option<QuotaIndex>,
 }
 
 impl<K> Vault<K>
 where
-    K: Eq + Hash + std::fmt::Display + Clone + serde::Serialize,
-    for<'de> K: serde::Deserialize<'de>,
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
 {
-    pub fn current_usage(&self) -> u64 {
-        self.resources.values().map(|res| res.size_bytes()).sum()
-    }
-
     pub fn new(location: String, capacity: MemorySize) -> Self {
         println!("Vault created at {} with capacity {:?}", location, capacity);
+        Self::empty(location, capacity)
+    }
+
+    // Builds an empty vault without announcing it on stdout
+    pub(crate) fn empty(location: String, capacity: MemorySize) -> Self {
+        Self::assemble(location, capacity, MemoryBackend::new())
+    }
+
+    pub fn load_from_file(path: &str) -> Result<Self, VaultError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    // The vault saved at `path`, or a new empty one if nothing is saved there
+    // yet. Used by the servers, so nothing is printed.
+    pub fn open_or_create(
+        path: &str,
+        location: String,
+        capacity: MemorySize,
+    ) -> Result<Self, VaultError> {
+        if Path::new(path).exists() {
+            Self::load_from_file(path)
+        } else {
+            Ok(Self::empty(location, capacity))
+        }
+    }
+
+    // Parses the format written by `save_to_file`
+    pub(crate) fn from_json(json: &str) -> Result<Self, VaultError> {
+        let snapshot: VaultFile<K> = serde_json::from_str(json).map_err(invalid_data)?;
+
+        // Files written before entry metadata existed get fresh timestamps
+        let mut meta = snapshot.meta;
+
+        let mut vault = Self::empty(snapshot.location, snapshot.storage_capacity);
+        for (key, resource) in snapshot.resources {
+            let meta = meta.remove(&key).unwrap_or_default();
+            vault.insert_entry(key, resource, meta)?;
+        }
+        vault.restore_edges(snapshot.edges);
+        Ok(vault)
+    }
+}
+
+impl<K, B> Vault<K, B>
+where
+    K: Eq + Hash + Display + Clone + Serialize,
+    for<'de> K: Deserialize<'de>,
+    B: StorageBackend<K>,
+{
+    fn assemble(location: String, capacity: MemorySize, resources: B) -> Self {
         Self {
             location,
             storage_capacity: capacity,
-            resources: HashMap::new(),
+            resources,
+            meta: ImHashMap::new(),
+            usage: 0,
+            expiry: BTreeMap::new(),
+            observers: Observers::new(),
+            journal: None,
+            audit: None,
+            retention: RetentionPolicy::default(),
+            evict_on_full: false,
+            graph: Graph::new(),
+            dedup: None,
+            embeddings: None,
+            quotas: None,
+        }
+    }
+
+    // Builds a vault on top of a backend that may already hold entries. Their
+    // metadata is restored from the backend where it was kept by `flush`.
+    pub fn with_backend(
+        location: String,
+        capacity: MemorySize,
+        resources: B,
+    ) -> Result<Self, VaultError> {
+        let mut stored: ImHashMap<K, EntryMeta> = match resources.load_meta()? {
+            Some(bytes) => serde_json::from_slice(&bytes).map_err(invalid_data)?,
+            None => ImHashMap::new(),
+        };
+        let edges: Vec<Edge<K>> = match resources.load_edges()? {
+            Some(bytes) => serde_json::from_slice(&bytes).map_err(invalid_data)?,
+            None => Vec::new(),
+        };
+        let mut vault = Self::assemble(location, capacity, resources);
+        let keys: Vec<K> = vault.resources.keys().cloned().collect();
+        for key in keys {
+            vault.usage += vault.resources.size_of(&key).unwrap_or(0);
+            let meta = stored.remove(&key).unwrap_or_default();
+            if let Some(deadline) = meta.expires_at {
+                vault.expiry.entry(deadline).or_default().push(key.clone());
+            }
+            vault.meta.insert(key, meta);
+        }
+        vault.restore_edges(edges);
+        Ok(vault)
+    }
+
+    // Edges whose ends no longer exist are dropped
+    pub(crate) fn restore_edges(&mut self, edges: Vec<Edge<K>>) {
+        for edge in edges {
+            if self.resources.contains_key(&edge.from) && self.resources.contains_key(&edge.to) {
+                self.graph.insert(edge);
+            }
         }
     }
 
+    // Makes every change durable in the backend, entry metadata and edges included
+    pub fn flush(&mut self) -> Result<(), VaultError> {
+        let meta = serde_json::to_vec(&self.meta).map_err(invalid_data)?;
+        self.resources.store_meta(&meta)?;
+        let edges: Vec<&Edge<K>> = self.graph.edges().collect();
+        let edges = serde_json::to_vec(&edges).map_err(invalid_data)?;
+        self.resources.store_edges(&edges)?;
+        self.resources.flush()
+    }
+
+    pub fn current_usage(&self) -> u64 {
+        self.usage
+    }
+
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    // Iterates over live entries in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Resource)> {
+        let now = now_millis();
+        self.resources
+            .iter()
+            .filter(move |(key, _)| !self.meta.get(*key).is_some_and(|m| m.is_expired(now)))
+    }
+
     pub fn add(&mut self, key: K, resource: Resource) -> Result<(), VaultError> {
+        if self.dedup.is_some() {
+            return self.add_deduplicated(key, resource);
+        }
+        self.add_with_meta(key, resource, EntryMeta::new())
+    }
+
+    // Like `add`, but keeps metadata carried over from elsewhere
+    pub(crate) fn add_with_meta(
+        &mut self,
+        key: K,
+        resource: Resource,
+        meta: EntryMeta,
+    ) -> Result<(), VaultError> {
+        self.purge_expired();
+        if !self.resources.contains_key(&key) {
+            // Checked first, so nothing is evicted for an entry that would be refused
+            self.check_quotas(&key, &resource, None)?;
+            self.make_room(self.usage, resource.size_bytes(), None)?;
+        }
+        self.check_add(&key, &resource, self.usage)?;
+
+        if self.is_journaling() {
+            self.log(&[JournalOp::Put {
+                key: key.clone(),
+                resource: resource.clone(),
+                meta: meta.clone(),
+            }])?;
+        }
+        self.insert_entry(key, resource, meta)
+    }
+
+    // Validates an insert as if the vault currently held `current` bytes
+    fn check_add(&self, key: &K, resource: &Resource, current: u64) -> Result<(), VaultError> {
         let size = resource.size_bytes();
-        let current = self.current_usage();
         let capacity = self.storage_capacity.size_bytes();
 
         if current + size > capacity {
@@ -62,80 +287,565 @@ where
             });
         }
 
-        if self.resources.contains_key(&key) {
+        if self.resources.contains_key(key) {
             return Err(VaultError::InvalidInput(format!(
                 "Key '{}' already exists",
                 key
             )));
         }
-
-        self.resources.insert(key, resource);
         Ok(())
     }
 
+    // Adds each item that fits, in order, and reports every outcome. Accepted
+    // items are journaled together as a single record, unless dedup is on:
+    // then each item is checked against the ones before it and goes through
+    // `add` on its own.
+    pub fn add_many<I>(&mut self, items: I) -> Vec<Result<(), VaultError>>
+    where
+        I: IntoIterator<Item = (K, Resource)>,
+    {
+        if self.dedup.is_some() {
+            return items
+                .into_iter()
+                .map(|(key, resource)| self.add_deduplicated(key, resource))
+                .collect();
+        }
+        self.purge_expired();
+        let items = items.into_iter();
+        let expected = items.size_hint().0;
+        let mut results = Vec::with_capacity(expected);
+        let mut accepted = Vec::with_capacity(expected);
+        let mut batch_keys = HashSet::with_capacity(expected);
+        let mut usage = self.usage;
+        // Quota totals as they will be once the accepted items are in
+        let mut quota_used = self.quotas.as_ref().map(|index| index.used.clone());
+
+        for (key, resource) in items {
+            let within_quotas = match (&self.quotas, &quota_used) {
+                (Some(index), Some(used)) => index.check(used, &key.to_string(), &resource, None),
+                _ => Ok(()),
+            };
+            let result = if batch_keys.contains(&key) {
+                Err(VaultError::InvalidInput(format!(
+                    "Key '{}' already exists",
+                    key
+                )))
+            } else if self.resources.contains_key(&key) {
+                self.check_add(&key, &resource, usage)
+            } else if let Err(e) = within_quotas {
+                Err(e)
+            } else {
+                match self.make_room(usage, resource.size_bytes(), None) {
+                    Ok(freed) => {
+                        usage -= freed;
+                        self.check_add(&key, &resource, usage)
+                    }
+                    Err(e) => Err(e),
+                }
+            };
+            if result.is_ok() {
+                if let (Some(index), Some(used)) = (&self.quotas, &mut quota_used) {
+                    track(&index.quotas, used, &key.to_string(), &resource, true);
+                }
+                usage += resource.size_bytes();
+                batch_keys.insert(key.clone());
+                accepted.push(JournalOp::Put {
+                    key,
+                    resource,
+                    meta: EntryMeta::new(),
+                });
+            }
+            results.push(result);
+        }
+
+        if let Err(e) = self.log(&accepted) {
+            return batch_failed(results, &e);
+        }
+        let mut applied = accepted.into_iter().map(|op| match op {
+            JournalOp::Put {
+                key,
+                resource,
+                meta,
+            } => self.insert_entry(key, resource, meta),
+            _ => Ok(()),
+        });
+        results
+            .into_iter()
+            .map(|result| result.and_then(|_| applied.next().unwrap_or(Ok(()))))
+            .collect()
+    }
+
+    pub fn get_many(&self, keys: &[K]) -> Vec<Option<&Resource>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    // Removes each listed key that exists and reports every outcome. The
+    // removals are journaled together as a single record.
+    pub fn remove_many(&mut self, keys: &[K]) -> Vec<Result<Resource, VaultError>> {
+        self.purge_expired();
+        let mut seen = HashSet::new();
+        let mut results = Vec::with_capacity(keys.len());
+        let mut ops = Vec::new();
+        for key in keys {
+            if self.resources.contains_key(key) && seen.insert(key) {
+                results.push(Ok(()));
+            } else {
+                results.push(Err(VaultError::ResourceNotFound(key.to_string())));
+            }
+        }
+        // Restricting edges only block a removal when their source stays;
+        // each blocked key can in turn block the keys it points at, and the
+        // entries it would have taken along
+        loop {
+            let gone = self.removal_closure(seen.iter().copied());
+            let mut blocked = Vec::new();
+            for (index, (result, key)) in results.iter_mut().zip(keys).enumerate() {
+                if result.is_ok() {
+                    if let Err(e) = self.check_removal(key, &gone) {
+                        *result = Err(e);
+                        blocked.push(index);
+                    }
+                }
+            }
+            if blocked.is_empty() {
+                break;
+            }
+            for index in blocked {
+                seen.remove(&keys[index]);
+            }
+        }
+        for (result, key) in results.iter().zip(keys) {
+            if result.is_ok() {
+                ops.push(JournalOp::Delete { key: key.clone() });
+            }
+        }
+
+        if let Err(e) = self.log(&ops) {
+            return batch_failed(results, &e);
+        }
+        results
+            .into_iter()
+            .zip(keys)
+            .map(|(result, key)| {
+                result.and_then(|_| {
+                    self.remove_entry(key, Removal::Removed)?
+                        .ok_or_else(|| VaultError::ResourceNotFound(key.to_string()))
+                })
+            })
+            .collect()
+    }
+
     pub fn get(&self, key: &K) -> Option<&Resource> {
-        self.resources.get(key)
+        match self.meta.get(key) {
+            Some(meta) if meta.is_expired(now_millis()) => None,
+            _ => self.resources.get(key),
+        }
     }
 
-    pub fn summary(&self) {
-        let mut text_count = 0;
-        let mut sensor_count = 0;
-        let mut log_count = 0;
-
-        for res in self.resources.values() {
-            match res {
-                Resource::TextMessage(_) => text_count += 1,
-                Resource::SensorData(_) => sensor_count += 1,
-                Resource::SystemLogs(_) => log_count += 1,
+    pub fn entry_meta(&self, key: &K) -> Option<&EntryMeta> {
+        self.meta.get(key)
+    }
+
+    // Replaces an existing resource, returning the previous value
+    pub fn update(&mut self, key: &K, resource: Resource) -> Result<Resource, VaultError> {
+        self.purge_expired();
+        if self.dedup.is_some() {
+            return self.update_deduplicated(key, resource);
+        }
+        self.overwrite(key, resource)
+    }
+
+    // `update` without the dedup check
+    pub(crate) fn overwrite(
+        &mut self,
+        key: &K,
+        resource: Resource,
+    ) -> Result<Resource, VaultError> {
+        let size = resource.size_bytes();
+        let old_size = match self.resources.size_of(key) {
+            Some(old_size) => old_size,
+            None => return Err(VaultError::ResourceNotFound(key.to_string())),
+        };
+        self.check_quotas(key, &resource, self.resources.get(key))?;
+        self.make_room(self.usage - old_size, size, Some(key))?;
+        let current = self.usage;
+        let capacity = self.storage_capacity.size_bytes();
+
+        if current - old_size + size > capacity {
+            return Err(VaultError::VaultFull {
+                capacity,
+                current,
+                new_size: size,
+            });
+        }
+
+        if self.is_journaling() {
+            let mut meta = self.meta.get(key).cloned().unwrap_or_default();
+            meta.touch();
+            self.log(&[JournalOp::Put {
+                key: key.clone(),
+                resource: resource.clone(),
+                meta,
+            }])?;
+        }
+        self.replace_entry(key, resource, None)?
+            .ok_or_else(|| VaultError::ResourceNotFound(key.to_string()))
+    }
+
+    // Gives an entry a time-to-live, or clears it with `None`. Expired entries
+    // are hidden from reads and dropped on the next mutation or `purge_expired`.
+    pub fn set_ttl(&mut self, key: &K, ttl: Option<Duration>) -> Result<(), VaultError> {
+        let resource = self
+            .get(key)
+            .cloned()
+            .ok_or_else(|| VaultError::ResourceNotFound(key.to_string()))?;
+        let deadline = ttl.map(|ttl| {
+            now_millis().saturating_add(u64::try_from(ttl.as_millis()).unwrap_or(u64::MAX))
+        });
+        if self.is_journaling() {
+            let mut meta = self.meta.get(key).cloned().unwrap_or_default();
+            meta.expires_at = deadline;
+            self.log(&[JournalOp::Put {
+                key: key.clone(),
+                resource,
+                meta,
+            }])?;
+        }
+        if let Some(meta) = self.meta.get_mut(key) {
+            meta.expires_at = deadline;
+            self.resources.put_meta(key, meta)?;
+        }
+        if let Some(deadline) = deadline {
+            self.expiry.entry(deadline).or_default().push(key.clone());
+        }
+        Ok(())
+    }
+
+    // Remaining time-to-live, if the entry has one
+    pub fn ttl(&self, key: &K) -> Option<Duration> {
+        let deadline = self.meta.get(key)?.expires_at?;
+        Some(Duration::from_millis(deadline.saturating_sub(now_millis())))
+    }
+
+    // Drops every entry whose time-to-live has run out and returns their keys
+    pub fn purge_expired(&mut self) -> Vec<K> {
+        let now = now_millis();
+        let mut purged = Vec::new();
+        while let Some(entry) = self.expiry.first_entry() {
+            if *entry.key() > now {
+                break;
+            }
+            let (deadline, keys) = entry.remove_entry();
+            for key in keys {
+                // Skip keys whose deadline was changed or cleared since
+                let current = self.meta.get(&key).and_then(|m| m.expires_at);
+                // A backend failure leaves the entry in place, still hidden from reads
+                if current == Some(deadline)
+                    && matches!(self.remove_entry(&key, Removal::Expired), Ok(Some(_)))
+                {
+                    purged.push(key);
+                }
+            }
+        }
+        purged
+    }
+
+    // Calls `callback` after every matching mutation
+    pub fn subscribe<F>(&mut self, filter: EventFilter, callback: F) -> SubscriptionId
+    where
+        F: FnMut(&VaultEvent<K>) + Send + 'static,
+    {
+        self.observers.subscribe(filter, Box::new(callback))
+    }
+
+    // Returns a channel that receives every matching event; dropping the
+    // receiver ends the subscription
+    pub fn watch(&mut self, filter: EventFilter) -> Receiver<VaultEvent<K>> {
+        self.observers.channel(filter)
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.observers.unsubscribe(id)
+    }
+
+    // Every mutation goes through the helpers below, which keep `meta`, the
+    // usage counter and the indexes in step with the backend and notify
+    // subscribers once the change is complete. The audit record is written
+    // first, as the journal is, and the backend next; the rest is only
+    // updated once both have succeeded, so a failed write leaves the vault as
+    // it was.
+    pub(crate) fn insert_entry(
+        &mut self,
+        key: K,
+        resource: Resource,
+        meta: EntryMeta,
+    ) -> Result<(), VaultError> {
+        if self.is_auditing() {
+            let digest = resource_digest(&resource);
+            self.audit(AuditOp::Add, &key, None, Some(digest))?;
+        }
+        let size = resource.size_bytes();
+        let copy = self.needs_copy().then(|| resource.clone());
+        self.resources
+            .insert_with_meta(key.clone(), resource, &meta)?;
+        if let Some(resource) = copy {
+            self.index_entry(&key, &resource);
+            if !self.observers.is_empty() {
+                self.observers.emit(VaultEvent::Added {
+                    key: key.clone(),
+                    resource,
+                });
+            }
+        }
+        if let Some(deadline) = meta.expires_at {
+            self.expiry.entry(deadline).or_default().push(key.clone());
+        }
+        self.usage += size;
+        self.meta.insert(key, meta);
+        Ok(())
+    }
+
+    // Without `meta`, the entry keeps its metadata with `updated_at` moved on
+    pub(crate) fn replace_entry(
+        &mut self,
+        key: &K,
+        resource: Resource,
+        meta: Option<EntryMeta>,
+    ) -> Result<Option<Resource>, VaultError> {
+        let Some(current) = self.resources.get(key) else {
+            return Ok(None);
+        };
+        if self.is_auditing() {
+            let before = resource_digest(current);
+            let after = resource_digest(&resource);
+            self.audit(AuditOp::Update, key, Some(before), Some(after))?;
+        }
+        let size = resource.size_bytes();
+        let copy = self.needs_copy().then(|| resource.clone());
+        let meta = meta.unwrap_or_else(|| {
+            let mut meta = self.meta.get(key).cloned().unwrap_or_default();
+            meta.touch();
+            meta
+        });
+        let Some(old) = self
+            .resources
+            .insert_with_meta(key.clone(), resource, &meta)?
+        else {
+            return Ok(None);
+        };
+        if let Some(index) = &mut self.quotas {
+            index.remove(&key.to_string(), &old);
+        }
+        if let Some(deadline) = meta.expires_at {
+            self.expiry.entry(deadline).or_default().push(key.clone());
+        }
+        self.usage = self.usage - old.size_bytes() + size;
+        self.meta.insert(key.clone(), meta);
+        if let Some(new) = copy {
+            self.index_entry(key, &new);
+            if !self.observers.is_empty() {
+                self.observers.emit(VaultEvent::Updated {
+                    key: key.clone(),
+                    old: old.clone(),
+                    new,
+                });
             }
         }
-        println!("Vault summary at {}:", self.location);
-        println!("Text messages: {}", text_count);
-        println!("Sensor data: {}", sensor_count);
-        println!("System logs: {}", log_count);
+        Ok(Some(old))
+    }
+
+    // Whether a written value is still needed after the backend takes it
+    fn needs_copy(&self) -> bool {
+        self.dedup.is_some()
+            || self.embeddings.is_some()
+            || self.quotas.is_some()
+            || !self.observers.is_empty()
+    }
+
+    fn index_entry(&mut self, key: &K, resource: &Resource) {
+        if let Some(index) = &mut self.dedup {
+            index.insert(key.clone(), resource);
+        }
+        if let Some(embeddings) = &mut self.embeddings {
+            embeddings.insert(key.clone(), resource);
+        }
+        if let Some(index) = &mut self.quotas {
+            index.insert(&key.to_string(), resource);
+        }
+    }
+
+    // Puts an entry back exactly as recorded, replacing any current value
+    pub(crate) fn restore_entry(
+        &mut self,
+        key: K,
+        resource: Resource,
+        meta: EntryMeta,
+    ) -> Result<(), VaultError> {
+        if self.resources.contains_key(&key) {
+            self.replace_entry(&key, resource, Some(meta))?;
+            Ok(())
+        } else {
+            self.insert_entry(key, resource, meta)
+        }
+    }
+
+    // Queues events instead of delivering them, so a batch is only announced
+    // once all of it has been applied
+    pub(crate) fn hold_events(&mut self) {
+        self.observers.hold();
+    }
+
+    pub(crate) fn release_events(&mut self) {
+        self.observers.release();
+    }
+
+    pub(crate) fn discard_events(&mut self) {
+        self.observers.discard();
+    }
+
+    pub(crate) fn remove_entry(
+        &mut self,
+        key: &K,
+        cause: Removal,
+    ) -> Result<Option<Resource>, VaultError> {
+        if self.is_auditing() {
+            let Some(current) = self.resources.get(key) else {
+                return Ok(None);
+            };
+            let before = resource_digest(current);
+            self.audit(cause.into(), key, Some(before), None)?;
+        }
+        let Some(removed) = self.resources.remove(key)? else {
+            return Ok(None);
+        };
+        self.usage -= removed.size_bytes();
+        self.meta.remove(key);
+        if let Some(index) = &mut self.dedup {
+            index.remove(key);
+        }
+        if let Some(embeddings) = &mut self.embeddings {
+            embeddings.remove(key);
+        }
+        if let Some(index) = &mut self.quotas {
+            index.remove(&key.to_string(), &removed);
+        }
+        if !self.observers.is_empty() {
+            self.observers
+                .emit(VaultEvent::removal(cause, key.clone(), removed.clone()));
+        }
+        // Entries linked to this one by cascading edges go with it
+        for dependent in self.graph.detach(key) {
+            self.remove_entry(&dependent, cause)?;
+        }
+        Ok(Some(removed))
+    }
+
+    pub fn summary(&self) {
+        print!("{}", self.stats());
+        if let Some(limit) = self.quotas().and_then(|quotas| quotas.max_entry_bytes) {
+            println!("Largest entry allowed: {} bytes", limit);
+        }
+    }
+
+    // Entries whose key or content contains `query`, ignoring case, sorted by key
+    pub fn search(&self, query: &str) -> Vec<(&K, &Resource)> {
+        search_entries(self.iter(), query)
     }
 
     pub fn remove(&mut self, key: &K) -> Result<Resource, VaultError> {
-        match self.resources.remove(key) {
+        self.purge_expired();
+        if !self.resources.contains_key(key) {
+            return Err(VaultError::ResourceNotFound(key.to_string()));
+        }
+        self.check_removal(key, &HashSet::new())?;
+        self.log(&[JournalOp::Delete { key: key.clone() }])?;
+        match self.remove_entry(key, Removal::Removed)? {
             Some(removed) => Ok(removed),
             None => Err(VaultError::ResourceNotFound(key.to_string())),
         }
     }
 
+    // Writes to a temporary file and renames it over `path`, so a crash
+    // mid-save leaves the previous file intact
     pub fn save_to_file(&self, path: &str) -> Result<(), VaultError> {
-        let snapshot = VaultSnapshot {
-            location: self.location.clone(),
-            storage_capacity: self.storage_capacity.clone(),
-            resources: self.resources.clone(),
-        };
+        let json = self.to_json()?;
+        let temp = temp_path(path);
+        let written = fs::File::create(&temp)
+            .and_then(|mut file| {
+                file.write_all(json.as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temp, path));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&temp);
+            return Err(e.into());
+        }
+        Ok(())
+    }
 
-        let json = serde_json::to_string_pretty(&snapshot).map_err(|e| {
-            VaultError::IoError(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                e.to_string(),
-            ))
-        })?;
+    // The pretty-printed JSON that `save_to_file` writes
+    pub(crate) fn to_json(&self) -> Result<String, VaultError> {
+        let snapshot = VaultFileRef {
+            location: &self.location,
+            storage_capacity: &self.storage_capacity,
+            resources: EntriesRef(&self.resources),
+            meta: &self.meta,
+            edges: self.graph.edges().collect(),
+        };
 
-        fs::write(path, json)?;
-        Ok(())
+        serde_json::to_string_pretty(&snapshot).map_err(invalid_data)
     }
+}
 
-    pub fn load_from_file(path: &str) -> Result<Self, VaultError>
-    where
-        K: for<'de> serde::Deserialize<'de>,
-    {
-        let json = fs::read_to_string(path)?;
-        let snapshot: VaultSnapshot<K> = serde_json::from_str(&json).map_err(|e| {
-            VaultError::IoError(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                e.to_string(),
-            ))
-        })?;
-
-        Ok(Self {
-            location: snapshot.location,
-            storage_capacity: snapshot.storage_capacity,
-            resources: snapshot.resources,
+// Tells apart the temporary files of saves running at the same time
+static SAVE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Where a save to `path` is written before being renamed into place
+pub(crate) fn temp_path(path: &str) -> String {
+    format!(
+        "{}.{}.{}.tmp",
+        path,
+        std::process::id(),
+        SAVE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+pub(crate) fn invalid_data(e: serde_json::Error) -> VaultError {
+    VaultError::IoError(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        e.to_string(),
+    ))
+}
+
+// When journaling a batch fails nothing is applied, so every item that had
+// been accepted reports the journal error instead
+fn batch_failed<T, U>(
+    results: Vec<Result<T, VaultError>>,
+    error: &VaultError,
+) -> Vec<Result<U, VaultError>> {
+    results
+        .into_iter()
+        .map(|result| {
+            result.and_then(|_| {
+                Err(VaultError::IoError(std::io::Error::other(
+                    error.to_string(),
+                )))
+            })
         })
-    }
+        .collect()
+}
+
+// Search shared by `Vault` and `VaultSnapshot`
+pub(crate) fn search_entries<'a, K: std::fmt::Display + 'a>(
+    entries: impl Iterator<Item = (&'a K, &'a Resource)>,
+    query: &str,
+) -> Vec<(&'a K, &'a Resource)> {
+    let needle = query.to_lowercase();
+    let mut hits: Vec<_> = entries
+        .filter(|(key, res)| {
+            key.to_string().to_lowercase().contains(&needle) || res.contains_text(&needle)
+        })
+        .collect();
+    hits.sort_by_key(|(key, _)| key.to_string());
+    hits
 }
@@ -18,9 +18,10 @@ fn test_cli_summary() {
     let mut cmd = Command::new(cargo_bin!("memoria"));
     let input = "add\ntext\nGreeting\nkey1\nadd\nsensor\n25.5\nkey2\nadd\nlog\nLog1,Log2\nkey3\nsummary\nexit\n";
     cmd.write_stdin(input).assert().success().stdout(
-        predicate::str::contains("Text messages: 1")
-            .and(predicate::str::contains("Sensor data: 1"))
-            .and(predicate::str::contains("System logs: 1")),
+        predicate::str::contains("Entries      3")
+            .and(predicate::str::is_match(r"text +1 +8\n").unwrap())
+            .and(predicate::str::is_match(r"sensor +1 +8\n").unwrap())
+            .and(predicate::str::contains("Key lengths")),
     );
 }
 
@@ -65,3 +66,721 @@ fn test_cli_duplicate_key() {
             "Error: Input error: Key 'key' already exists",
         ));
 }
+
+fn write_vault(path: &std::path::Path, entries: &[(&str, f64)]) {
+    let mut vault =
+        memoria::Vault::<String>::new("CLI Vault".to_string(), memoria::MemorySize::MB(1));
+    for (key, value) in entries {
+        vault
+            .add(key.to_string(), memoria::Resource::SensorData(*value))
+            .unwrap();
+    }
+    vault.save_to_file(path.to_str().unwrap()).unwrap();
+}
+
+#[test]
+fn test_cli_diff() {
+    let dir = std::env::temp_dir();
+    let a = dir.join("memoria_cli_diff_a.json");
+    let b = dir.join("memoria_cli_diff_b.json");
+    write_vault(&a, &[("same", 1.0), ("gone", 2.0), ("edit", 3.0)]);
+    write_vault(&b, &[("same", 1.0), ("edit", 4.0), ("fresh", 5.0)]);
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["diff", a.to_str().unwrap(), b.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("+ fresh: SensorData(5.0)")
+                .and(predicate::str::contains("- gone: SensorData(2.0)"))
+                .and(predicate::str::contains(
+                    "~ edit: SensorData(3.0) -> SensorData(4.0)",
+                ))
+                .and(predicate::str::contains("1 added, 1 removed, 1 changed")),
+        );
+
+    let _ = std::fs::remove_file(a);
+    let _ = std::fs::remove_file(b);
+}
+
+#[test]
+fn test_cli_merge() {
+    let dir = std::env::temp_dir();
+    let base = dir.join("memoria_cli_merge_base.json");
+    let ours = dir.join("memoria_cli_merge_ours.json");
+    let theirs = dir.join("memoria_cli_merge_theirs.json");
+    let out = dir.join("memoria_cli_merge_out.json");
+    write_vault(&base, &[("k", 1.0), ("keep", 9.0)]);
+    write_vault(&ours, &[("k", 2.0), ("keep", 9.0)]);
+    write_vault(&theirs, &[("k", 3.0), ("keep", 9.0), ("new", 7.0)]);
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args([
+        "merge",
+        base.to_str().unwrap(),
+        ours.to_str().unwrap(),
+        theirs.to_str().unwrap(),
+        "--strategy",
+        "theirs",
+        "--output",
+        out.to_str().unwrap(),
+    ])
+    .assert()
+    .success()
+    .stdout(
+        predicate::str::contains("! conflict k").and(predicate::str::contains("Merged 3 entries")),
+    );
+
+    let merged = memoria::Vault::<String>::load_from_file(out.to_str().unwrap()).unwrap();
+    assert_eq!(
+        merged.get(&"k".to_string()),
+        Some(&memoria::Resource::SensorData(3.0))
+    );
+
+    for path in [base, ours, theirs, out] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[test]
+fn test_cli_merge_unknown_strategy() {
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args([
+        "merge",
+        "a.json",
+        "b.json",
+        "c.json",
+        "--strategy",
+        "coinflip",
+    ])
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("Unknown strategy 'coinflip'"));
+}
+
+#[test]
+fn test_cli_merge_requires_output() {
+    let dir = std::env::temp_dir();
+    let ours = dir.join("memoria_cli_merge_no_output.json");
+    write_vault(&ours, &[("k", 1.0)]);
+    let before = std::fs::read(&ours).unwrap();
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    let path = ours.to_str().unwrap();
+    cmd.args(["merge", path, path, path])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--output out.json"));
+    assert_eq!(std::fs::read(&ours).unwrap(), before);
+    let _ = std::fs::remove_file(ours);
+}
+
+#[test]
+fn test_cli_migrate_requires_json_and_db() {
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["migrate", "a.json", "b.json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Usage: memoria migrate"));
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_cli_migrate_round_trip() {
+    let dir = std::env::temp_dir();
+    let json = dir.join("memoria_cli_migrate.json");
+    let db = dir.join("memoria_cli_migrate.db");
+    let back = dir.join("memoria_cli_migrate_back.json");
+    let _ = std::fs::remove_file(&db);
+    write_vault(&json, &[("a", 1.0), ("b", 2.0)]);
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["migrate", json.to_str().unwrap(), db.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Migrated 2 entries"));
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["migrate", db.to_str().unwrap(), back.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let restored = memoria::Vault::<String>::load_from_file(back.to_str().unwrap()).unwrap();
+    assert_eq!(restored.len(), 2);
+    assert_eq!(restored.location, "CLI Vault");
+
+    for path in [json, db, back] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[test]
+fn test_cli_pack_get_and_scan() {
+    let dir = std::env::temp_dir();
+    let json = dir.join("memoria_cli_pack.json");
+    let pack = dir.join("memoria_cli_pack.pack");
+    write_vault(&json, &[("agent/a", 1.0), ("agent/b", 2.0), ("other", 3.0)]);
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["pack", json.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Packed 3 entries"));
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["get", pack.to_str().unwrap(), "agent/b"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("agent/b: SensorData(2.0)"));
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["scan", pack.to_str().unwrap(), "agent/"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("2 of 3 entries"));
+
+    // A damaged pack is rebuilt from the JSON snapshot beside it
+    std::fs::write(&pack, b"garbage").unwrap();
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["get", pack.to_str().unwrap(), "other"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("repacking"))
+        .stdout(predicate::str::contains("other: SensorData(3.0)"));
+
+    let _ = std::fs::remove_file(json);
+    let _ = std::fs::remove_file(pack);
+}
+
+#[test]
+fn test_cli_links() {
+    let path = std::env::temp_dir().join("memoria_cli_links.json");
+    let mut vault =
+        memoria::Vault::<String>::new("CLI Vault".to_string(), memoria::MemorySize::KB(1));
+    for key in ["alice", "acme", "berlin"] {
+        vault
+            .add(
+                key.to_string(),
+                memoria::Resource::TextMessage(key.to_string()),
+            )
+            .unwrap();
+    }
+    vault
+        .link(&"alice".to_string(), "works_at", &"acme".to_string())
+        .unwrap();
+    vault
+        .link(&"acme".to_string(), "based_in", &"berlin".to_string())
+        .unwrap();
+    vault.save_to_file(path.to_str().unwrap()).unwrap();
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["links", path.to_str().unwrap(), "acme", "--depth", "2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("based_in"))
+        .stdout(predicate::str::contains("works_at"))
+        .stdout(predicate::str::contains("berlin (1)"));
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["links", path.to_str().unwrap(), "nobody"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not found"));
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn test_cli_dedupe() {
+    let path = std::env::temp_dir().join("memoria_cli_dedupe.json");
+    let mut vault =
+        memoria::Vault::<String>::new("CLI Vault".to_string(), memoria::MemorySize::KB(4));
+    for (key, text) in [
+        ("a", "Alice works at Acme in Berlin"),
+        ("b", "alice works at acme in berlin!"),
+        ("c", "Bob prefers tea over coffee"),
+    ] {
+        vault
+            .add(
+                key.to_string(),
+                memoria::Resource::TextMessage(text.to_string()),
+            )
+            .unwrap();
+    }
+    vault.save_to_file(path.to_str().unwrap()).unwrap();
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["dedupe", path.to_str().unwrap(), "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Found 1 duplicates"));
+    let unchanged = memoria::Vault::<String>::load_from_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(unchanged.len(), 3);
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["dedupe", path.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 1 duplicates"));
+    let deduped = memoria::Vault::<String>::load_from_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(deduped.len(), 2);
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn test_cli_mcp_session() {
+    let path = std::env::temp_dir().join("memoria_cli_mcp.json");
+    let _ = std::fs::remove_file(&path);
+    let requests = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05","capabilities":{},"clientInfo":{"name":"test","version":"0"}}}"#,
+        r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#,
+        r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"memory_add","arguments":{"key":"user/name","value":"Ada Lovelace"}}}"#,
+        r#"{"jsonrpc":"2.0","id":4,"method":"tools/call","params":{"name":"memory_get","arguments":{"key":"missing"}}}"#,
+        r#"{"jsonrpc":"2.0","id":5,"method":"tools/call","params":{"name":"memory_search","arguments":{"query":"ada"}}}"#,
+        r#"{"jsonrpc":"2.0","id":6,"method":"bogus"}"#,
+    ];
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    let output = cmd
+        .args(["mcp", path.to_str().unwrap()])
+        .write_stdin(requests.join("\n"))
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let replies: Vec<serde_json::Value> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    // Every request but the notification is answered, in order
+    assert_eq!(replies.len(), 6);
+    assert_eq!(replies[0]["result"]["protocolVersion"], "2024-11-05");
+    let tools = replies[1]["result"]["tools"].as_array().unwrap();
+    assert_eq!(tools.len(), 5);
+    assert!(tools
+        .iter()
+        .all(|tool| tool["inputSchema"]["type"] == "object"));
+    assert_eq!(replies[2]["result"]["isError"], false);
+    assert_eq!(replies[3]["result"]["isError"], true);
+    assert_eq!(
+        replies[3]["result"]["structuredContent"]["error"]["kind"],
+        "not_found"
+    );
+    assert_eq!(
+        replies[4]["result"]["structuredContent"]["results"][0]["key"],
+        "user/name"
+    );
+    assert_eq!(replies[5]["error"]["code"], -32601);
+
+    // The vault persisted between calls and is there for the next session
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["mcp", path.to_str().unwrap()])
+        .write_stdin(
+            r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"memory_list","arguments":{"prefix":"user/"}}}"#,
+        )
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""keys":["user/name"]"#));
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_daemon_clients() {
+    use memoria::{DaemonClient, Resource, VaultError};
+    use serde_json::json;
+
+    let dir = std::env::temp_dir();
+    let path = dir.join("memoria_cli_daemon.json");
+    let socket = dir.join("memoria_cli_daemon.sock");
+    let _ = std::fs::remove_file(&path);
+    let socket = socket.to_str().unwrap().to_string();
+
+    let mut daemon = std::process::Command::new(cargo_bin!("memoria"))
+        .args(["daemon", "--socket", &socket, path.to_str().unwrap()])
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let connect = || {
+        for _ in 0..200 {
+            if let Ok(client) = DaemonClient::connect(&socket) {
+                return client;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(25));
+        }
+        panic!("daemon did not start");
+    };
+
+    // Concurrent clients each add their own entries
+    let writers: Vec<_> = (0..4)
+        .map(|n| {
+            let mut client = connect();
+            std::thread::spawn(move || {
+                for i in 0..10 {
+                    let resource = Resource::TextMessage(format!("note {} from {}", i, n));
+                    client.add(&format!("c{}/{}", n, i), resource).unwrap();
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let mut client = connect();
+    assert_eq!(client.len().unwrap(), 40);
+    assert_eq!(
+        client.get("c2/3").unwrap(),
+        Some(Resource::TextMessage("note 3 from 2".to_string()))
+    );
+    assert!(matches!(
+        client.remove("missing"),
+        Err(VaultError::ResourceNotFound(_))
+    ));
+
+    // Pipelined requests are answered in order, each with its own result
+    let results = client
+        .pipeline(vec![
+            ("remove", json!({ "key": "c0/0" })),
+            ("contains_key", json!({ "key": "c0/0" })),
+            ("bogus", json!({})),
+            ("set_ttl", json!({ "key": "c1/0", "seconds": -1.0 })),
+            ("len", json!(null)),
+        ])
+        .unwrap();
+    assert!(results[0].is_ok());
+    assert_eq!(results[1].as_ref().unwrap(), &json!(false));
+    assert!(matches!(results[2], Err(VaultError::InvalidInput(_))));
+    assert!(matches!(results[3], Err(VaultError::InvalidInput(_))));
+    assert_eq!(results[4].as_ref().unwrap(), &json!(39));
+
+    // SIGTERM shuts down cleanly: the vault is saved and the socket removed
+    let killed = std::process::Command::new("kill")
+        .args(["-TERM", &daemon.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    assert!(daemon.wait().unwrap().success());
+    assert!(!std::path::Path::new(&socket).exists());
+    let vault = memoria::Vault::<String>::load_from_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(vault.len(), 39);
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn test_cli_resp_over_tcp() {
+    use std::io::{BufRead, BufReader, Read, Write};
+
+    let path = std::env::temp_dir().join("memoria_cli_resp.json");
+    let _ = std::fs::remove_file(&path);
+    let mut server = std::process::Command::new(cargo_bin!("memoria"))
+        .args(["resp", "--port", "0", path.to_str().unwrap()])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut banner = String::new();
+    BufReader::new(server.stdout.as_mut().unwrap())
+        .read_line(&mut banner)
+        .unwrap();
+    let address = banner
+        .split_whitespace()
+        .nth(4)
+        .unwrap()
+        .trim_end_matches(',');
+
+    let mut stream = std::net::TcpStream::connect(address).unwrap();
+    // Pipelined RESP arrays plus one inline command
+    stream
+        .write_all(
+            b"*3\r\n$3\r\nSET\r\n$4\r\nuser\r\n$3\r\nAda\r\n\
+              *2\r\n$3\r\nGET\r\n$4\r\nuser\r\n\
+              EXISTS user nobody\r\n\
+              *1\r\n$8\r\nSHUTDOWN\r\n",
+        )
+        .unwrap();
+    let mut replies = String::new();
+    stream.read_to_string(&mut replies).unwrap();
+    assert_eq!(replies, "+OK\r\n$3\r\nAda\r\n:1\r\n+OK\r\n");
+
+    // SHUTDOWN saved the vault
+    assert!(server.wait().unwrap().success());
+    let vault = memoria::Vault::<String>::load_from_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(
+        vault.get(&"user".to_string()),
+        Some(&memoria::Resource::TextMessage("Ada".to_string()))
+    );
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_daemon_access_policy() {
+    use memoria::{DaemonClient, Resource, VaultError};
+
+    let dir = std::env::temp_dir();
+    let path = dir.join("memoria_cli_acl.json");
+    let policy = dir.join("memoria_cli_acl_policy.json");
+    let socket = dir.join("memoria_cli_acl.sock");
+    let _ = std::fs::remove_file(&path);
+    let socket = socket.to_str().unwrap().to_string();
+    std::fs::write(
+        &policy,
+        r#"{ "principals": [
+            { "name": "ops", "api_key": "k-ops",
+              "grants": [{ "prefix": "", "permissions": ["admin"] }] },
+            { "name": "agent", "api_key": "k-agent",
+              "grants": [{ "prefix": "agent/", "permissions": ["read", "write"] }] }
+        ] }"#,
+    )
+    .unwrap();
+
+    let mut daemon = std::process::Command::new(cargo_bin!("memoria"))
+        .args(["daemon", "--socket", &socket, "--policy"])
+        .args([policy.to_str().unwrap(), path.to_str().unwrap()])
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let connect = || {
+        for _ in 0..200 {
+            if let Ok(client) = DaemonClient::connect(&socket) {
+                return client;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(25));
+        }
+        panic!("daemon did not start");
+    };
+
+    // Nothing works before auth, and unknown keys are turned away
+    let mut agent = connect();
+    assert!(matches!(
+        agent.len(),
+        Err(VaultError::PermissionDenied { .. })
+    ));
+    assert!(agent.auth("k-guess").is_err());
+    assert_eq!(agent.auth("k-agent").unwrap(), "agent");
+
+    agent
+        .add("agent/note", Resource::TextMessage("mine".to_string()))
+        .unwrap();
+    let error = agent
+        .add("ops/note", Resource::TextMessage("theirs".to_string()))
+        .unwrap_err();
+    assert_eq!(
+        error.message(),
+        "'agent' lacks write permission on 'ops/note'"
+    );
+    assert!(matches!(
+        agent.remove("agent/note"),
+        Err(VaultError::PermissionDenied { .. })
+    ));
+    assert!(agent.shutdown().is_err());
+
+    let mut ops = connect();
+    ops.auth("k-ops").unwrap();
+    ops.add("ops/note", Resource::TextMessage("theirs".to_string()))
+        .unwrap();
+    assert_eq!(agent.keys().unwrap(), vec!["agent/note".to_string()]);
+    ops.shutdown().unwrap();
+    assert!(daemon.wait().unwrap().success());
+
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_file(policy);
+}
+
+#[test]
+fn test_cli_audit_verify_and_export() {
+    let dir = std::env::temp_dir();
+    let path = dir.join("memoria_cli_audit.json");
+    let log = dir.join("memoria_cli_audit.log");
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&log);
+    let log = log.to_str().unwrap();
+    let requests = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"memory_add","arguments":{"key":"a","value":"one"}}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"name":"memory_add","arguments":{"key":"b","value":"two"}}}"#,
+        r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"memory_delete","arguments":{"key":"a"}}}"#,
+    ];
+    Command::new(cargo_bin!("memoria"))
+        .args(["mcp", "--audit", log, path.to_str().unwrap()])
+        .write_stdin(requests.join("\n"))
+        .assert()
+        .success();
+
+    Command::new(cargo_bin!("memoria"))
+        .args(["audit", "verify", log])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("3 records, chain intact"));
+    let output = Command::new(cargo_bin!("memoria"))
+        .args(["audit", "export", log, "--key", "a"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let ops: Vec<String> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["op"].to_string())
+        .collect();
+    assert_eq!(ops, vec!["\"add\"", "\"remove\""]);
+
+    // Dropping a record from the middle is caught
+    let contents = std::fs::read_to_string(log).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    std::fs::write(log, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+    Command::new(cargo_bin!("memoria"))
+        .args(["audit", "verify", log])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("tampered at record 2"));
+
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_file(log);
+}
+
+#[test]
+fn test_cli_summary_command() {
+    let path = std::env::temp_dir().join("memoria_cli_summary.json");
+    let mut vault =
+        memoria::Vault::<String>::new("Summary Vault".to_string(), memoria::MemorySize::KB(4));
+    vault
+        .add(
+            "greeting".to_string(),
+            memoria::Resource::TextMessage("Hello there".to_string()),
+        )
+        .unwrap();
+    vault
+        .add("temp".to_string(), memoria::Resource::SensorData(21.5))
+        .unwrap();
+    vault.save_to_file(path.to_str().unwrap()).unwrap();
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["summary", path.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Summary Vault"))
+        .stdout(predicate::str::contains("greeting"))
+        .stdout(predicate::str::contains("Key lengths"));
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    let output = cmd
+        .args(["summary", path.to_str().unwrap(), "--format", "json"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stats: memoria::VaultStats = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(stats.entries, 2);
+    assert_eq!(stats.largest[0].key, "greeting");
+
+    let mut cmd = Command::new(cargo_bin!("memoria"));
+    cmd.args(["summary", path.to_str().unwrap(), "--format", "xml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown format"));
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_daemon_default_vault() {
+    use memoria::{DaemonClient, Resource};
+
+    // Run from an empty directory so the default `.memoria/vault.json` is fresh
+    let dir = std::env::temp_dir().join("memoria_cli_daemon_default");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let socket = dir.join("daemon.sock");
+    let socket = socket.to_str().unwrap().to_string();
+
+    let mut daemon = std::process::Command::new(cargo_bin!("memoria"))
+        .args(["daemon", "--socket", &socket])
+        .current_dir(&dir)
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut client = (0..200)
+        .find_map(|_| {
+            let client = DaemonClient::connect(&socket).ok();
+            if client.is_none() {
+                std::thread::sleep(std::time::Duration::from_millis(25));
+            }
+            client
+        })
+        .expect("daemon did not start");
+    client
+        .add("note", Resource::TextMessage("hello".to_string()))
+        .unwrap();
+
+    let killed = std::process::Command::new("kill")
+        .args(["-TERM", &daemon.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    assert!(daemon.wait().unwrap().success());
+    let path = dir.join(".memoria/vault.json");
+    let vault = memoria::Vault::<String>::load_from_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(vault.len(), 1);
+
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[cfg(all(unix, feature = "grpc"))]
+#[test]
+fn test_cli_grpc_saves_on_sigterm() {
+    use memoria::grpc::proto::{self, vault_client::VaultClient};
+    use std::io::{BufRead, BufReader};
+
+    let path = std::env::temp_dir().join("memoria_cli_grpc.json");
+    let _ = std::fs::remove_file(&path);
+    let mut server = std::process::Command::new(cargo_bin!("memoria"))
+        .args(["grpc", "--port", "0", path.to_str().unwrap()])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut banner = String::new();
+    BufReader::new(server.stdout.as_mut().unwrap())
+        .read_line(&mut banner)
+        .unwrap();
+    let address = banner
+        .split_whitespace()
+        .nth(4)
+        .unwrap()
+        .trim_end_matches(',');
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mut client = VaultClient::connect(format!("http://{}", address))
+            .await
+            .unwrap();
+        let resource = memoria::Resource::TextMessage("Ada".to_string());
+        let request = proto::AddRequest {
+            key: "user".to_string(),
+            resource: Some(proto::Resource::from(&resource)),
+        };
+        client.add(request).await.unwrap();
+    });
+
+    // `kill` stops the server like Ctrl-C does, saving the vault first
+    let killed = std::process::Command::new("kill")
+        .args(["-TERM", &server.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    assert!(server.wait().unwrap().success());
+    let vault = memoria::Vault::<String>::load_from_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(
+        vault.get(&"user".to_string()),
+        Some(&memoria::Resource::TextMessage("Ada".to_string()))
+    );
+
+    let _ = std::fs::remove_file(path);
+}